@@ -0,0 +1,283 @@
+use chrono::{Duration, Local, NaiveDate, NaiveTime, Utc};
+use regex::Regex;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::sync::LazyLock;
+
+use crate::model::{Block, JournalDay};
+use crate::{preview, Client};
+
+pub const AGENDA_PULL: &str = "[:block/uuid :block/content :block/marker :block/priority :block/scheduled :block/deadline
+                           {:block/page [:block/original-name]}]";
+
+/// `SCHEDULED: <2024-05-22 Wed 10:00>` or `DEADLINE: …` with a time.
+static PLANNED_TIME: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(SCHEDULED|DEADLINE): <\d{4}-\d{2}-\d{2} \w+ (\d{1,2}:\d{2})").expect("valid regex")
+});
+
+/// Whether an agenda item comes from `SCHEDULED:` or `DEADLINE:`.
+#[derive(Clone, Copy)]
+enum Planning {
+    Scheduled,
+    Deadline,
+}
+
+impl Planning {
+    fn as_str(self) -> &'static str {
+        match self {
+            Planning::Scheduled => "SCHEDULED",
+            Planning::Deadline => "DEADLINE",
+        }
+    }
+}
+
+struct AgendaItem {
+    date: NaiveDate,
+    time: Option<NaiveTime>,
+    planning: Planning,
+    block: Block,
+}
+
+impl AgendaItem {
+    /// `TODO [#A] title`, without the planning lines.
+    fn summary(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(marker) = &self.block.marker {
+            parts.push(marker.to_string());
+        }
+        if let Some(priority) = &self.block.priority {
+            parts.push(format!("[#{}]", priority));
+        }
+        parts.push(preview(&title(&self.block)));
+        parts.join(" ")
+    }
+
+    fn page(&self) -> &str {
+        self.block.page.as_ref().map(|page| page.display_name()).unwrap_or("")
+    }
+}
+
+/// The first line of a block without its marker and priority.
+fn title(block: &Block) -> String {
+    let first_line = block.content.lines().next().unwrap_or("");
+    let mut rest = first_line.trim();
+    if let Some(marker) = &block.marker {
+        rest = rest.strip_prefix(marker.as_str()).unwrap_or(rest).trim_start();
+    }
+    if let Some(priority) = &block.priority {
+        rest = rest.strip_prefix(&format!("[#{}]", priority)).unwrap_or(rest).trim_start();
+    }
+    rest.to_string()
+}
+
+/// The time in `SCHEDULED: <2024-05-22 Wed 10:00>`, if there is one.
+fn planned_time(content: &str, planning: Planning) -> Option<NaiveTime> {
+    let captures = PLANNED_TIME.captures_iter(content).find(|captures| &captures[1] == planning.as_str())?;
+    NaiveTime::parse_from_str(&captures[2], "%H:%M").ok()
+}
+
+/// Escape text for an iCalendar property value.
+fn ics_escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Fold iCalendar lines longer than 75 octets and end them with CRLF.
+fn ics_line(line: &str, out: &mut String) {
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            length = 1;
+        }
+        out.push(c);
+        length += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+fn render_ics(items: &[AgendaItem]) -> String {
+    let stamp = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+    let mut out = String::new();
+    ics_line("BEGIN:VCALENDAR", &mut out);
+    ics_line("VERSION:2.0", &mut out);
+    ics_line(&format!("PRODID:-//rlu//{}//EN", env!("CARGO_PKG_VERSION")), &mut out);
+
+    for item in items {
+        ics_line("BEGIN:VEVENT", &mut out);
+        ics_line(
+            &format!("UID:{}-{}@rlu", item.block.uuid, item.planning.as_str().to_lowercase()),
+            &mut out,
+        );
+        ics_line(&format!("DTSTAMP:{}", stamp), &mut out);
+        match item.time {
+            Some(time) => {
+                let start = item.date.and_time(time);
+                ics_line(&format!("DTSTART:{}", start.format("%Y%m%dT%H%M%S")), &mut out);
+                ics_line(&format!("DTEND:{}", (start + Duration::hours(1)).format("%Y%m%dT%H%M%S")), &mut out);
+            }
+            None => {
+                ics_line(&format!("DTSTART;VALUE=DATE:{}", item.date.format("%Y%m%d")), &mut out);
+                ics_line(
+                    &format!("DTEND;VALUE=DATE:{}", (item.date + Duration::days(1)).format("%Y%m%d")),
+                    &mut out,
+                );
+            }
+        }
+        let summary = match item.planning {
+            Planning::Scheduled => item.summary(),
+            Planning::Deadline => format!("Deadline: {}", item.summary()),
+        };
+        ics_line(&format!("SUMMARY:{}", ics_escape(&summary)), &mut out);
+        ics_line(&format!("DESCRIPTION:{}", ics_escape(&item.block.content)), &mut out);
+        if !item.page().is_empty() {
+            ics_line(&format!("CATEGORIES:{}", ics_escape(item.page())), &mut out);
+        }
+        ics_line("END:VEVENT", &mut out);
+    }
+
+    ics_line("END:VCALENDAR", &mut out);
+    out
+}
+
+fn print_agenda(items: &[AgendaItem], today: NaiveDate) {
+    let mut days: BTreeMap<NaiveDate, Vec<&AgendaItem>> = BTreeMap::new();
+    for item in items {
+        days.entry(item.date).or_default().push(item);
+    }
+
+    for (date, items) in days {
+        let overdue = if date < today { " (overdue)" } else { "" };
+        println!("{}{}", date.format("%Y-%m-%d %a"), overdue);
+        for item in items {
+            let time = item.time.map(|time| format!("{} ", time.format("%H:%M"))).unwrap_or_default();
+            println!(
+                "  {} {}{} {} ({})",
+                item.block.uuid,
+                time,
+                item.planning.as_str(),
+                item.summary(),
+                item.page()
+            );
+        }
+    }
+}
+
+impl Client {
+    /// Open scheduled and deadline blocks up to `days` days ahead, together
+    /// with the overdue ones.
+    fn agenda_items(&self, today: NaiveDate, days: i64) -> Result<Vec<AgendaItem>, String> {
+        let end = JournalDay::from_date(today + Duration::days(days));
+        let query = format!(
+            "[:find (pull ?b {pull})
+             :in $ ?end
+             :where
+             (or [?b :block/scheduled ?d]
+                 [?b :block/deadline ?d])
+             [(<= ?d ?end)]]",
+            pull = AGENDA_PULL,
+        );
+        let blocks: Vec<Block> = self.query_pulled(&query, &[json!(end.0.to_string())])?;
+
+        let mut items = Vec::new();
+        for block in blocks {
+            if block.marker.as_ref().is_some_and(|marker| marker.is_closed()) {
+                continue;
+            }
+            let planned = [(Planning::Scheduled, block.scheduled), (Planning::Deadline, block.deadline)];
+            for (planning, day) in planned {
+                let Some(date) = day.and_then(JournalDay::date) else {
+                    continue;
+                };
+                // Past notes without a marker are not overdue, just old.
+                if date > today + Duration::days(days) || (date < today && block.marker.is_none()) {
+                    continue;
+                }
+                items.push(AgendaItem {
+                    date,
+                    time: planned_time(&block.content, planning),
+                    planning,
+                    block: block.clone(),
+                });
+            }
+        }
+        items.sort_by_key(|item| (item.date, item.time));
+        Ok(items)
+    }
+
+    pub fn show_agenda(&self, days: i64, ics: bool) {
+        let today = Local::now().date_naive();
+        let items = match self.agenda_items(today, days) {
+            Ok(items) => items,
+            Err(err) => {
+                eprintln!("Failed to get agenda: {}", err);
+                return;
+            }
+        };
+
+        if ics {
+            print!("{}", render_ics(&items));
+        } else if items.is_empty() {
+            eprintln!("Nothing scheduled in the next {} days.", days);
+        } else {
+            print_agenda(&items, today);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Marker;
+
+    #[test]
+    fn escapes_ics_text() {
+        assert_eq!(ics_escape(r"a\b; c, d"), r"a\\b\; c\, d");
+        assert_eq!(ics_escape("two\nlines"), r"two\nlines");
+    }
+
+    #[test]
+    fn folds_long_ics_lines() {
+        let mut out = String::new();
+        ics_line(&"x".repeat(80), &mut out);
+        assert_eq!(out, format!("{}\r\n {}\r\n", "x".repeat(75), "x".repeat(5)));
+
+        let mut out = String::new();
+        ics_line("short", &mut out);
+        assert_eq!(out, "short\r\n");
+    }
+
+    #[test]
+    fn folds_without_splitting_characters() {
+        let mut out = String::new();
+        ics_line(&format!("{}é{}", "x".repeat(74), "ü".repeat(40)), &mut out);
+        let lines: Vec<&str> = out.trim_end_matches("\r\n").split("\r\n").collect();
+        assert_eq!(lines[0], "x".repeat(74));
+        assert!(lines.iter().all(|line| line.len() <= 75));
+        assert_eq!(out.replace("\r\n ", ""), format!("{}é{}\r\n", "x".repeat(74), "ü".repeat(40)));
+    }
+
+    #[test]
+    fn reads_planned_times() {
+        let content = "TODO call\nSCHEDULED: <2024-05-22 Wed>\nDEADLINE: <2024-05-24 Fri 9:30>";
+        assert_eq!(planned_time(content, Planning::Scheduled), None);
+        assert_eq!(planned_time(content, Planning::Deadline), NaiveTime::from_hms_opt(9, 30, 0));
+        let content = "meet\nSCHEDULED: <2024-05-22 Wed 14:00 .+1w>";
+        assert_eq!(planned_time(content, Planning::Scheduled), NaiveTime::from_hms_opt(14, 0, 0));
+    }
+
+    #[test]
+    fn titles_drop_marker_and_priority() {
+        let block = Block {
+            content: "TODO [#A] call the bank\nSCHEDULED: <2024-05-22 Wed>".to_string(),
+            marker: Some(Marker::Todo),
+            priority: Some("A".to_string()),
+            ..Block::default()
+        };
+        assert_eq!(title(&block), "call the bank");
+        let block = Block { content: "plain".to_string(), ..Block::default() };
+        assert_eq!(title(&block), "plain");
+    }
+}
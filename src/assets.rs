@@ -0,0 +1,243 @@
+//! `rlu attach` and `rlu assets`: files in the graph's `assets/` folder.
+//! Attached files are copied there under a unique name and linked from a
+//! new block the way Logseq links uploads, `![name](../assets/name_123.png)`.
+//!
+//! The graph folder is the one given with `--graph` (or `RLU_GRAPH_DIR`),
+//! otherwise the folder of the graph open in Logseq.
+
+use chrono::Local;
+use log::debug;
+use percent_encoding::percent_decode_str;
+use regex::Regex;
+use serde_json::json;
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+use crate::model::Block;
+use crate::{edn_string, Client};
+
+/// The part after `assets/` of a link target such as `../assets/a.png`,
+/// up to the `)` of a Markdown link, the `]` of an Org link, a quote or
+/// whitespace.
+static ASSET_LINK: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?:^|[^\w.-])assets/([^)\]\s"]+)"#).expect("valid regex"));
+
+/// Where an attachment's block goes.
+pub enum AttachTarget {
+    Block(String),
+    Journal,
+}
+
+/// `name` with everything but letters, digits, `-` and `_` replaced, so the
+/// link needs no escaping.
+fn safe_file_stem(name: &str) -> String {
+    let stem: String = name
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    match stem.trim_matches('_') {
+        "" => "file".to_string(),
+        stem => stem.to_string(),
+    }
+}
+
+/// Files below `dir`, as paths relative to it with `/` separators.
+fn asset_files(dir: &Path, prefix: &str, files: &mut Vec<String>) -> Result<(), String> {
+    let entries = fs::read_dir(dir).map_err(|err| format!("Failed to read {}: {}", dir.display(), err))?;
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let Ok(name) = entry.file_name().into_string() else {
+            continue;
+        };
+        if name.starts_with('.') {
+            continue;
+        }
+        let relative = format!("{}{}", prefix, name);
+        match entry.file_type() {
+            Ok(kind) if kind.is_dir() => asset_files(&entry.path(), &format!("{}/", relative), files)?,
+            Ok(kind) if kind.is_file() => files.push(relative),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// The assets linked from `contents`, as paths relative to `assets/`.
+/// Links to names with spaces or other special characters are
+/// percent-encoded, so both forms are kept.
+fn linked_assets<'a>(contents: impl Iterator<Item = &'a str>) -> HashSet<String> {
+    let mut linked = HashSet::new();
+    for content in contents {
+        for captures in ASSET_LINK.captures_iter(content) {
+            let target = &captures[1];
+            linked.insert(percent_decode_str(target).decode_utf8_lossy().into_owned());
+            linked.insert(target.to_string());
+        }
+    }
+    linked
+}
+
+impl Client {
+    /// The graph folder: `graph`, `RLU_GRAPH_DIR`, or the path of the graph
+    /// open in Logseq.
+    fn graph_dir(&self, graph: Option<&Path>) -> Result<PathBuf, String> {
+        if let Some(graph) = graph {
+            return Ok(graph.to_path_buf());
+        }
+        if let Ok(dir) = env::var("RLU_GRAPH_DIR") {
+            return Ok(PathBuf::from(dir));
+        }
+        let current = self.call("logseq.App.getCurrentGraph", json!([]))?;
+        current
+            .get("path")
+            .and_then(|v| v.as_str())
+            .map(PathBuf::from)
+            .ok_or_else(|| "Logseq has no graph open; give its folder with --graph".to_string())
+    }
+
+    pub fn attach(&self, file: &Path, target: AttachTarget, graph: Option<&Path>) {
+        match self.attach_inner(file, target, graph) {
+            Ok(link) => eprintln!("Attached {}.", link),
+            Err(err) => {
+                eprintln!("Failed to attach {}: {}", file.display(), err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    fn attach_inner(&self, file: &Path, target: AttachTarget, graph: Option<&Path>) -> Result<String, String> {
+        if !file.is_file() {
+            return Err("not a file".to_string());
+        }
+        // Check the target before copying anything.
+        let parent = match &target {
+            AttachTarget::Block(uuid) => {
+                self.call_as::<Option<Block>>("logseq.Editor.getBlock", json!([uuid]))?
+                    .ok_or_else(|| format!("Block {} not found", uuid))?
+                    .uuid
+            }
+            AttachTarget::Journal => self.journal_page_uuid(Local::now().date_naive())?,
+        };
+
+        let assets = self.graph_dir(graph)?.join("assets");
+        fs::create_dir_all(&assets).map_err(|err| format!("Failed to create {}: {}", assets.display(), err))?;
+
+        let name = file.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        let stem = file.file_stem().map(|stem| safe_file_stem(&stem.to_string_lossy())).unwrap_or_default();
+        let extension = file.extension().map(|ext| format!(".{}", safe_file_stem(&ext.to_string_lossy()))).unwrap_or_default();
+        // Logseq names uploads `<name>_<milliseconds>`, which keeps them unique.
+        let stored = format!("{}_{}{}", stem, Local::now().timestamp_millis(), extension);
+        let destination = assets.join(&stored);
+        fs::copy(file, &destination).map_err(|err| format!("Failed to copy to {}: {}", destination.display(), err))?;
+        debug!("Copied {} to {}", file.display(), destination.display());
+
+        let link = format!("![{}](../assets/{})", name.replace(['[', ']'], ""), stored);
+        let inserted = match &target {
+            AttachTarget::Block(_) => self.call("logseq.Editor.insertBlock", json!([parent, link, {"sibling": false}])),
+            AttachTarget::Journal => self.call("logseq.Editor.insertBlock", json!([parent, link, {"isPageBlock": true}])),
+        };
+        if let Err(err) = inserted {
+            // Do not leave a file behind that nothing links to.
+            fs::remove_file(&destination).ok();
+            return Err(err);
+        }
+        Ok(link)
+    }
+
+    pub fn list_orphaned_assets(&self, graph: Option<&Path>) {
+        match self.orphaned_assets(graph) {
+            Ok((orphaned, total)) => {
+                for asset in &orphaned {
+                    println!("{}", asset);
+                }
+                eprintln!("{} of {} assets are not referenced.", orphaned.len(), total);
+            }
+            Err(err) => {
+                eprintln!("Failed to list orphaned assets: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    /// Assets no block links to, as `assets/...` paths, and the number of
+    /// assets.
+    fn orphaned_assets(&self, graph: Option<&Path>) -> Result<(Vec<String>, usize), String> {
+        let assets = self.graph_dir(graph)?.join("assets");
+        let mut files = Vec::new();
+        if assets.is_dir() {
+            asset_files(&assets, "", &mut files)?;
+        }
+        files.sort();
+
+        let rows = self.datascript_query(
+            "[:find ?content
+             :in $ ?text
+             :where
+             [?b :block/content ?content]
+             [(clojure.string/includes? ?content ?text)]]",
+            &[json!(edn_string("assets/"))],
+        )?;
+        let linked = linked_assets(rows.iter().filter_map(|row| row.get(0)?.as_str()));
+
+        let total = files.len();
+        let orphaned = files
+            .into_iter()
+            .filter(|file| !linked.contains(file))
+            .map(|file| format!("assets/{}", file))
+            .collect();
+        Ok((orphaned, total))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn makes_safe_file_stems() {
+        assert_eq!(safe_file_stem("Screen Shot 2024-05-22 at 10.15"), "Screen_Shot_2024-05-22_at_10_15");
+        assert_eq!(safe_file_stem("Übersicht"), "Übersicht");
+        assert_eq!(safe_file_stem("(draft)"), "draft");
+        assert_eq!(safe_file_stem("???"), "file");
+        assert_eq!(safe_file_stem(""), "file");
+    }
+
+    #[test]
+    fn lists_asset_files_recursively() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("sub/deeper")).unwrap();
+        fs::create_dir(dir.path().join(".git")).unwrap();
+        for name in ["a.png", "sub/b.pdf", "sub/deeper/c.txt", ".DS_Store", ".git/config"] {
+            fs::write(dir.path().join(name), "x").unwrap();
+        }
+        let mut files = Vec::new();
+        asset_files(dir.path(), "", &mut files).unwrap();
+        files.sort();
+        assert_eq!(files, ["a.png", "sub/b.pdf", "sub/deeper/c.txt"]);
+    }
+
+    #[test]
+    fn finds_linked_assets() {
+        let contents = [
+            "![shot](../assets/shot_1.png) and ![doc](../assets/my%20doc_2.pdf)",
+            "[[../assets/org_3.pdf][slides]]",
+            r#"<img src="../assets/html_4.png"/> assets/bare_5.txt"#,
+            "nested ../assets/sub/deep_6.png\tnext",
+        ];
+        let linked = linked_assets(contents.iter().copied());
+        for name in ["shot_1.png", "my doc_2.pdf", "my%20doc_2.pdf", "org_3.pdf", "html_4.png", "bare_5.txt", "sub/deep_6.png"] {
+            assert!(linked.contains(name), "{} is linked", name);
+        }
+        assert_eq!(linked.len(), 7);
+    }
+
+    #[test]
+    fn matches_asset_paths_exactly() {
+        let linked = linked_assets(["![a](../assets/image.png.bak) see myassets/other.png"].into_iter());
+        assert!(linked.contains("image.png.bak"));
+        assert!(!linked.contains("image.png"));
+        assert!(!linked.contains("other.png"));
+    }
+}
@@ -0,0 +1,618 @@
+//! A local SQLite copy of the graph's pages and blocks that `show`,
+//! `output-content` and completions read from.
+//!
+//! Reads sync the cache first; completions skip that when it was synced
+//! within the last half minute. Each sync pulls the entities whose
+//! `:block/updated-at` is at or after the newest stamp already cached. Logseq
+//! keeps no trace of deleted entities, so a sync also compares the number of
+//! pages and blocks with the graph's; when they differ, and every few minutes
+//! and on `rlu cache sync` regardless, the cached uuids are compared with the
+//! graph's to drop deleted entities and pick up ones that were added without
+//! a stamp. A deletion that coincides with an unstamped addition is only
+//! noticed by that periodic comparison. When Logseq cannot be reached, reads
+//! use the cache as it is and say so.
+
+use chrono::Local;
+use log::debug;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::model::{Block, BlockChild, JournalDay, Page, Timestamp};
+use crate::{edn_string, Client};
+
+const ENTITY_PULL: &str = "[:block/uuid :block/name :block/original-name :block/journal-day :block/content
+                           :block/updated-at {:block/page [:block/uuid]} {:block/parent [:block/uuid]}
+                           {:block/left [:block/uuid]}]";
+
+/// How often a sync also looks for deleted and unstamped entities.
+const RECONCILE_INTERVAL_MS: i64 = 5 * 60 * 1000;
+
+/// Completions skip the sync when the cache was checked this recently, so
+/// that repeated tab presses stay instant.
+const COMPLETION_FRESHNESS_MS: i64 = 30 * 1000;
+
+/// `RLU_CACHE_DIR`, `$XDG_CACHE_HOME/rlu` or `~/.cache/rlu`.
+pub fn cache_dir() -> PathBuf {
+    if let Ok(dir) = env::var("RLU_CACHE_DIR") {
+        return PathBuf::from(dir);
+    }
+    match env::var("XDG_CACHE_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir).join("rlu"),
+        _ => PathBuf::from(env::var("HOME").unwrap_or_default()).join(".cache").join("rlu"),
+    }
+}
+
+#[derive(Deserialize)]
+struct UuidRef {
+    uuid: String,
+}
+
+/// A page or block as pulled with `ENTITY_PULL`.
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct Entity {
+    uuid: String,
+    name: Option<String>,
+    original_name: Option<String>,
+    journal_day: Option<JournalDay>,
+    content: Option<String>,
+    updated_at: Option<Timestamp>,
+    page: Option<UuidRef>,
+    parent: Option<UuidRef>,
+    left: Option<UuidRef>,
+}
+
+fn now_ms() -> i64 {
+    Local::now().timestamp_millis()
+}
+
+fn sql_error(err: rusqlite::Error) -> String {
+    format!("Cache error: {}", err)
+}
+
+pub struct Cache {
+    conn: Connection,
+}
+
+impl Cache {
+    pub fn open() -> Result<Cache, String> {
+        let dir = cache_dir();
+        fs::create_dir_all(&dir).map_err(|err| format!("Failed to create {}: {}", dir.display(), err))?;
+        let path = dir.join("graph.sqlite");
+        let conn = Connection::open(&path).map_err(|err| format!("Failed to open {}: {}", path.display(), err))?;
+        Cache::with_connection(conn)
+    }
+
+    /// Set up the tables in `conn` unless they are there already.
+    fn with_connection(conn: Connection) -> Result<Cache, String> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS pages (
+                 uuid TEXT PRIMARY KEY,
+                 name TEXT NOT NULL,
+                 original_name TEXT NOT NULL,
+                 journal_day INTEGER,
+                 updated_at INTEGER
+             );
+             CREATE TABLE IF NOT EXISTS blocks (
+                 uuid TEXT PRIMARY KEY,
+                 page_uuid TEXT,
+                 parent_uuid TEXT,
+                 left_uuid TEXT,
+                 content TEXT NOT NULL,
+                 updated_at INTEGER
+             );
+             CREATE INDEX IF NOT EXISTS blocks_page ON blocks (page_uuid);
+             CREATE INDEX IF NOT EXISTS blocks_parent ON blocks (parent_uuid);
+             CREATE TABLE IF NOT EXISTS meta (
+                 key TEXT PRIMARY KEY,
+                 value TEXT NOT NULL
+             );",
+        )
+        .map_err(sql_error)?;
+        Ok(Cache { conn })
+    }
+
+    fn meta(&self, key: &str) -> Result<Option<String>, String> {
+        self.conn
+            .query_row("SELECT value FROM meta WHERE key = ?1", [key], |row| row.get(0))
+            .optional()
+            .map_err(sql_error)
+    }
+
+    /// A meta value that holds milliseconds since the epoch.
+    fn meta_ms(&self, key: &str) -> Result<Option<i64>, String> {
+        Ok(self.meta(key)?.and_then(|value| value.parse().ok()))
+    }
+
+    fn set_meta(&self, key: &str, value: impl ToString) -> Result<(), String> {
+        self.conn
+            .execute("INSERT OR REPLACE INTO meta (key, value) VALUES (?1, ?2)", params![key, value.to_string()])
+            .map(|_| ())
+            .map_err(sql_error)
+    }
+
+    pub fn clear(&self) -> Result<(), String> {
+        self.conn
+            .execute_batch("DELETE FROM pages; DELETE FROM blocks; DELETE FROM meta;")
+            .map_err(sql_error)
+    }
+
+    /// When the cache last heard from Logseq, if ever.
+    fn checked_at(&self) -> Result<Option<Timestamp>, String> {
+        Ok(self.meta_ms("checked-at")?.map(Timestamp))
+    }
+
+    fn counts(&self) -> Result<(i64, i64), String> {
+        let count = |table: &str| {
+            self.conn
+                .query_row(&format!("SELECT count(*) FROM {}", table), [], |row| row.get(0))
+                .map_err(sql_error)
+        };
+        Ok((count("pages")?, count("blocks")?))
+    }
+
+    fn store(&mut self, entities: &[Entity]) -> Result<(), String> {
+        let tx = self.conn.transaction().map_err(sql_error)?;
+        for entity in entities {
+            let updated_at = entity.updated_at.map(|stamp| stamp.0);
+            if let Some(name) = &entity.name {
+                tx.execute(
+                    "INSERT OR REPLACE INTO pages (uuid, name, original_name, journal_day, updated_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![
+                        entity.uuid,
+                        name,
+                        entity.original_name.as_deref().unwrap_or(name),
+                        entity.journal_day.map(|day| day.0),
+                        updated_at
+                    ],
+                )
+                .map_err(sql_error)?;
+            } else if let Some(page) = &entity.page {
+                tx.execute(
+                    "INSERT OR REPLACE INTO blocks (uuid, page_uuid, parent_uuid, left_uuid, content, updated_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    params![
+                        entity.uuid,
+                        page.uuid,
+                        entity.parent.as_ref().map(|parent| &parent.uuid),
+                        entity.left.as_ref().map(|left| &left.uuid),
+                        entity.content.as_deref().unwrap_or(""),
+                        updated_at
+                    ],
+                )
+                .map_err(sql_error)?;
+            }
+        }
+        tx.commit().map_err(sql_error)
+    }
+
+    fn cached_uuids(&self) -> Result<HashSet<String>, String> {
+        let mut statement = self
+            .conn
+            .prepare("SELECT uuid FROM pages UNION ALL SELECT uuid FROM blocks")
+            .map_err(sql_error)?;
+        let uuids = statement
+            .query_map([], |row| row.get(0))
+            .map_err(sql_error)?
+            .collect::<Result<_, _>>()
+            .map_err(sql_error)?;
+        Ok(uuids)
+    }
+
+    /// Drop the cached entities whose uuid is not in `live` and return the
+    /// live uuids that are not cached.
+    fn reconcile(&mut self, live: &HashSet<String>) -> Result<Vec<String>, String> {
+        let cached = self.cached_uuids()?;
+        let removed: Vec<String> = cached.difference(live).cloned().collect();
+        let missing: Vec<String> = live.difference(&cached).cloned().collect();
+        debug!("Reconciling cache: {} removed, {} missing", removed.len(), missing.len());
+        self.remove(&removed)?;
+        Ok(missing)
+    }
+
+    fn remove(&mut self, uuids: &[String]) -> Result<(), String> {
+        let tx = self.conn.transaction().map_err(sql_error)?;
+        for uuid in uuids {
+            tx.execute("DELETE FROM pages WHERE uuid = ?1", [uuid]).map_err(sql_error)?;
+            tx.execute("DELETE FROM blocks WHERE uuid = ?1", [uuid]).map_err(sql_error)?;
+        }
+        tx.commit().map_err(sql_error)
+    }
+
+    /// `(uuid, content, left_uuid)` of the blocks on `page` below `parent`.
+    fn child_rows(&self, page: &str, parent: &str) -> Result<Vec<(String, String, Option<String>)>, String> {
+        let mut statement = self
+            .conn
+            .prepare_cached("SELECT uuid, content, left_uuid FROM blocks WHERE page_uuid = ?1 AND parent_uuid = ?2")
+            .map_err(sql_error)?;
+        let rows = statement
+            .query_map([page, parent], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .map_err(sql_error)?
+            .collect::<Result<_, _>>()
+            .map_err(sql_error)?;
+        Ok(rows)
+    }
+
+    /// The children of `parent` in outline order, with their subtrees.
+    fn children(&self, page: &str, parent: &str) -> Result<Vec<Block>, String> {
+        let rows = self.child_rows(page, parent)?;
+
+        // Each block's left is its previous sibling, or the parent for the
+        // first child; follow that chain and keep stragglers at the end.
+        let mut by_left: HashMap<String, usize> = HashMap::new();
+        for (index, (_, _, left)) in rows.iter().enumerate() {
+            if let Some(left) = left {
+                by_left.insert(left.clone(), index);
+            }
+        }
+        let mut order = Vec::new();
+        let mut seen = HashSet::new();
+        let mut previous = parent.to_string();
+        while let Some(&index) = by_left.get(&previous) {
+            if !seen.insert(index) {
+                break;
+            }
+            order.push(index);
+            previous = rows[index].0.clone();
+        }
+        order.extend((0..rows.len()).filter(|index| !seen.contains(index)));
+
+        order
+            .into_iter()
+            .map(|index| {
+                let (uuid, content, _) = &rows[index];
+                let children = self.children(page, uuid)?;
+                Ok(Block {
+                    uuid: uuid.clone(),
+                    content: content.clone(),
+                    children: children.into_iter().map(|child| BlockChild::Loaded(Box::new(child))).collect(),
+                    ..Block::default()
+                })
+            })
+            .collect()
+    }
+
+    /// A block with all of its descendants loaded.
+    pub fn block_tree(&self, uuid: &str) -> Result<Option<Block>, String> {
+        let row: Option<(String, String)> = self
+            .conn
+            .query_row("SELECT page_uuid, content FROM blocks WHERE uuid = ?1", [uuid], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .optional()
+            .map_err(sql_error)?;
+        let Some((page, content)) = row else {
+            return Ok(None);
+        };
+
+        let children = self.children(&page, uuid)?;
+        Ok(Some(Block {
+            uuid: uuid.to_string(),
+            content,
+            children: children.into_iter().map(|child| BlockChild::Loaded(Box::new(child))).collect(),
+            ..Block::default()
+        }))
+    }
+
+    /// The top-level blocks of the journal page of `day`, with their
+    /// subtrees.
+    pub fn journal_blocks(&self, day: JournalDay) -> Result<Vec<Block>, String> {
+        let page: Option<String> = self
+            .conn
+            .query_row("SELECT uuid FROM pages WHERE journal_day = ?1", [day.0], |row| row.get(0))
+            .optional()
+            .map_err(sql_error)?;
+        match page {
+            Some(page) => self.top_level_blocks(&page),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// The top-level blocks of the page with uuid `page`, with their
+    /// subtrees.
+    pub fn top_level_blocks(&self, page: &str) -> Result<Vec<Block>, String> {
+        self.children(page, page)
+    }
+
+    pub fn pages(&self) -> Result<Vec<Page>, String> {
+        let mut statement = self
+            .conn
+            .prepare("SELECT uuid, name, original_name, journal_day FROM pages")
+            .map_err(sql_error)?;
+        let pages = statement
+            .query_map([], |row| {
+                Ok(Page {
+                    uuid: row.get(0)?,
+                    name: row.get(1)?,
+                    original_name: row.get(2)?,
+                    journal_day: row.get::<_, Option<i64>>(3)?.map(JournalDay),
+                    ..Page::default()
+                })
+            })
+            .map_err(sql_error)?
+            .collect::<Result<_, _>>()
+            .map_err(sql_error)?;
+        Ok(pages)
+    }
+}
+
+impl Client {
+    fn pull_entities(&self, query: &str, inputs: &[serde_json::Value]) -> Result<Vec<Entity>, String> {
+        self.query_pulled(&query.replace("PULL", ENTITY_PULL), inputs)
+    }
+
+    /// Bring `cache` up to date. With `full` it is rebuilt from scratch.
+    fn sync_cache(&self, cache: &mut Cache, full: bool) -> Result<(), String> {
+        // A cache only holds one graph; start over when another one is open.
+        let graph = self.call("logseq.App.getCurrentGraph", json!([]))?;
+        let graph = graph
+            .get("path")
+            .or_else(|| graph.get("name"))
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string();
+        if full || cache.meta("graph")?.as_ref() != Some(&graph) {
+            debug!("Rebuilding the cache for graph {}", graph);
+            cache.clear()?;
+            cache.set_meta("graph", &graph)?;
+        }
+
+        let since = cache.meta_ms("synced-until")?;
+        let entities = match since {
+            None => self.pull_entities("[:find (pull ?e PULL) :where [?e :block/uuid]]", &[])?,
+            Some(since) => self.pull_entities(
+                "[:find (pull ?e PULL)
+                 :in $ ?since
+                 :where
+                 [?e :block/updated-at ?updated]
+                 [(>= ?updated ?since)]]",
+                &[json!(since.to_string())],
+            )?,
+        };
+        debug!("Caching {} updated entities", entities.len());
+        cache.store(&entities)?;
+
+        let newest = entities.iter().filter_map(|entity| entity.updated_at).map(|stamp| stamp.0).max();
+        let synced_until = newest.max(since).unwrap_or_default();
+        let now = now_ms();
+
+        let reconciled_at = cache.meta_ms("reconciled-at")?.unwrap_or_default();
+        if since.is_none() {
+            cache.set_meta("reconciled-at", now)?;
+        } else if now - reconciled_at >= RECONCILE_INTERVAL_MS {
+            self.reconcile_cache(cache)?;
+            cache.set_meta("reconciled-at", now)?;
+        } else {
+            // Deleted entities leave no stamp behind, but they change the count.
+            let (pages, blocks) = cache.counts()?;
+            if self.graph_entity_count()? != pages + blocks {
+                self.reconcile_cache(cache)?;
+                cache.set_meta("reconciled-at", now)?;
+            }
+        }
+
+        cache.set_meta("synced-until", synced_until)?;
+        cache.set_meta("checked-at", now)?;
+        Ok(())
+    }
+
+    /// The number of pages and blocks in the graph, counted like the cache
+    /// stores them.
+    fn graph_entity_count(&self) -> Result<i64, String> {
+        let rows = self.datascript_query(
+            "[:find (count ?e)
+             :where
+             [?e :block/uuid]
+             (or [?e :block/name] [?e :block/page])]",
+            &[],
+        )?;
+        Ok(rows.first().and_then(|row| row.get(0)).and_then(|v| v.as_i64()).unwrap_or(0))
+    }
+
+    /// Drop cached entities that are gone from the graph and fetch the ones
+    /// the `updated-at` query cannot see.
+    fn reconcile_cache(&self, cache: &mut Cache) -> Result<(), String> {
+        let rows = self.datascript_query("[:find ?uuid :where [?e :block/uuid ?uuid]]", &[])?;
+        let live: HashSet<String> = rows
+            .iter()
+            .filter_map(|row| row.get(0).and_then(|v| v.as_str()).map(str::to_string))
+            .collect();
+        let missing = cache.reconcile(&live)?;
+        if !missing.is_empty() {
+            let uuids: Vec<String> = missing.iter().map(|uuid| format!("#uuid {}", edn_string(uuid))).collect();
+            let entities = self.pull_entities(
+                "[:find (pull ?e PULL)
+                 :in $ [?uuid ...]
+                 :where
+                 [?e :block/uuid ?uuid]]",
+                &[json!(format!("[{}]", uuids.join(" ")))],
+            )?;
+            cache.store(&entities)?;
+        }
+        Ok(())
+    }
+
+    /// The cache, synced first unless it was checked within `freshness_ms`.
+    /// When the sync fails, the cache is used as it is with a warning, even
+    /// when it is still empty.
+    fn synced_cache(&self, freshness_ms: i64) -> Result<Cache, String> {
+        let mut cache = Cache::open()?;
+        let checked_at = cache.checked_at()?;
+        if checked_at.is_some_and(|checked| now_ms() - checked.0 < freshness_ms) {
+            return Ok(cache);
+        }
+
+        match self.sync_cache(&mut cache, false) {
+            Ok(()) => Ok(cache),
+            Err(err) => match checked_at.and_then(Timestamp::to_local) {
+                Some(checked) => {
+                    debug!("Cache sync failed: {}", err);
+                    eprintln!(
+                        "Warning: could not reach Logseq; using the cache from {}.",
+                        checked.format("%Y-%m-%d %H:%M")
+                    );
+                    Ok(cache)
+                }
+                None => {
+                    eprintln!("Warning: could not reach Logseq and nothing is cached yet: {}", err);
+                    Ok(cache)
+                }
+            },
+        }
+    }
+
+    /// The cache, synced first so that it has everything written so far.
+    pub fn cached_graph(&self) -> Result<Cache, String> {
+        self.synced_cache(0)
+    }
+
+    pub fn cached_graph_for_completion(&self) -> Result<Cache, String> {
+        self.synced_cache(COMPLETION_FRESHNESS_MS)
+    }
+
+    pub fn sync_cache_command(&self, full: bool) {
+        let result = Cache::open().and_then(|mut cache| {
+            // Force the check for deleted entities on an explicit sync.
+            cache.set_meta("reconciled-at", 0)?;
+            self.sync_cache(&mut cache, full)?;
+            cache.counts()
+        });
+        match result {
+            Ok((pages, blocks)) => eprintln!("Cached {} pages and {} blocks in {}.", pages, blocks, cache_dir().display()),
+            Err(err) => {
+                eprintln!("Failed to sync the cache: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+pub fn clear_cache() {
+    match Cache::open().and_then(|cache| cache.clear()) {
+        Ok(()) => eprintln!("Cleared the cache in {}.", cache_dir().display()),
+        Err(err) => {
+            eprintln!("Failed to clear the cache: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache() -> Cache {
+        Cache::with_connection(Connection::open_in_memory().unwrap()).unwrap()
+    }
+
+    fn uuid_ref(uuid: &str) -> Option<UuidRef> {
+        Some(UuidRef { uuid: uuid.to_string() })
+    }
+
+    fn page(uuid: &str, name: &str, day: Option<i64>) -> Entity {
+        Entity { uuid: uuid.to_string(), name: Some(name.to_string()), journal_day: day.map(JournalDay), ..Entity::default() }
+    }
+
+    fn block(uuid: &str, page: &str, parent: &str, left: &str, content: &str) -> Entity {
+        Entity {
+            uuid: uuid.to_string(),
+            content: Some(content.to_string()),
+            page: uuid_ref(page),
+            parent: uuid_ref(parent),
+            left: uuid_ref(left),
+            ..Entity::default()
+        }
+    }
+
+    fn outline(blocks: &[Block]) -> Vec<String> {
+        let mut lines = Vec::new();
+        fn walk(block: &Block, depth: usize, lines: &mut Vec<String>) {
+            lines.push(format!("{}{}", "  ".repeat(depth), block.content));
+            block.loaded_children().for_each(|child| walk(child, depth + 1, lines));
+        }
+        blocks.iter().for_each(|block| walk(block, 0, &mut lines));
+        lines
+    }
+
+    #[test]
+    fn stores_pages_and_blocks() {
+        let mut cache = cache();
+        let mut work = page("p1", "work", None);
+        work.original_name = Some("Work".to_string());
+        cache.store(&[work, page("p2", "may 22nd, 2024", Some(20240522))]).unwrap();
+        cache.store(&[block("b1", "p1", "p1", "p1", "first")]).unwrap();
+        // Entities that are neither pages nor blocks are not cached.
+        cache.store(&[Entity { uuid: "x".to_string(), ..Entity::default() }]).unwrap();
+        assert_eq!(cache.counts().unwrap(), (2, 1));
+
+        let mut pages = cache.pages().unwrap();
+        pages.sort_by(|a, b| a.uuid.cmp(&b.uuid));
+        assert_eq!(pages[0].display_name(), "Work");
+        assert_eq!(pages[1].display_name(), "may 22nd, 2024");
+        assert_eq!(pages[1].journal_day, Some(JournalDay(20240522)));
+
+        cache.store(&[block("b1", "p1", "p1", "p1", "edited")]).unwrap();
+        assert_eq!(cache.counts().unwrap(), (2, 1));
+        assert_eq!(cache.block_tree("b1").unwrap().unwrap().content, "edited");
+        assert!(cache.block_tree("missing").unwrap().is_none());
+    }
+
+    #[test]
+    fn orders_children_by_their_left_sibling() {
+        let mut cache = cache();
+        cache
+            .store(&[
+                page("p", "may 22nd, 2024", Some(20240522)),
+                block("c", "p", "p", "b", "third"),
+                block("b2", "p", "b", "b1", "nested two"),
+                block("a", "p", "p", "p", "first"),
+                block("stray", "p", "p", "gone", "stray"),
+                block("b", "p", "p", "a", "second"),
+                block("b1", "p", "b", "b", "nested one"),
+            ])
+            .unwrap();
+
+        let blocks = cache.journal_blocks(JournalDay(20240522)).unwrap();
+        assert_eq!(outline(&blocks), ["first", "second", "  nested one", "  nested two", "third", "stray"]);
+        assert_eq!(outline(&[cache.block_tree("b").unwrap().unwrap()]), ["second", "  nested one", "  nested two"]);
+        assert!(cache.journal_blocks(JournalDay(20240523)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn survives_a_cycle_in_left_siblings() {
+        let mut cache = cache();
+        cache
+            .store(&[page("p", "loop", None), block("a", "p", "p", "b", "a"), block("b", "p", "p", "a", "b")])
+            .unwrap();
+        assert_eq!(cache.top_level_blocks("p").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn reconciles_deleted_and_missing_entities() {
+        let mut cache = cache();
+        cache
+            .store(&[page("p", "work", None), block("a", "p", "p", "p", "kept"), block("b", "p", "p", "a", "deleted")])
+            .unwrap();
+
+        let live: HashSet<String> = ["p", "a", "new"].iter().map(|uuid| uuid.to_string()).collect();
+        assert_eq!(cache.reconcile(&live).unwrap(), ["new"]);
+        assert_eq!(cache.counts().unwrap(), (1, 1));
+        assert_eq!(outline(&cache.top_level_blocks("p").unwrap()), ["kept"]);
+    }
+
+    #[test]
+    fn keeps_meta_values() {
+        let cache = cache();
+        assert_eq!(cache.checked_at().unwrap(), None);
+        cache.set_meta("checked-at", 1716370200000i64).unwrap();
+        assert_eq!(cache.checked_at().unwrap(), Some(Timestamp(1716370200000)));
+        cache.clear().unwrap();
+        assert_eq!(cache.meta("checked-at").unwrap(), None);
+    }
+}
@@ -0,0 +1,244 @@
+use chrono::{DateTime, Local, NaiveDate};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+use crate::journal;
+use crate::{read_input, Client};
+
+/// Where `rlu capture` queues notes: `RLU_SPOOL_DIR` or `~/.rlu/spool`.
+pub fn spool_dir() -> PathBuf {
+    match env::var("RLU_SPOOL_DIR") {
+        Ok(dir) => PathBuf::from(dir),
+        Err(_) => PathBuf::from(env::var("HOME").unwrap_or_default()).join(".rlu").join("spool"),
+    }
+}
+
+/// A queued note, one JSON file per capture.
+#[derive(Serialize, Deserialize)]
+struct CapturedNote {
+    /// RFC 3339, with the local offset at capture time.
+    captured_at: String,
+    /// The journal day from `--date`, otherwise the day of `captured_at`.
+    date: Option<String>,
+    content: String,
+}
+
+impl CapturedNote {
+    fn journal_day(&self) -> Result<NaiveDate, String> {
+        match &self.date {
+            Some(date) => journal::parse_date(date),
+            None => Ok(self.captured_at()?.date_naive()),
+        }
+    }
+
+    fn captured_at(&self) -> Result<DateTime<Local>, String> {
+        DateTime::parse_from_rfc3339(&self.captured_at)
+            .map(|time| time.with_timezone(&Local))
+            .map_err(|err| format!("Invalid capture time '{}': {}", self.captured_at, err))
+    }
+}
+
+/// Write a note to the spool directory. This does not talk to Logseq, so
+/// it works whether or not Logseq is running.
+pub fn capture(content: Option<String>, date: Option<String>) -> Result<PathBuf, String> {
+    if let Some(date) = &date {
+        journal::parse_date(date)?;
+    }
+    let content = read_input(content);
+    if content.trim().is_empty() {
+        return Err("Nothing to capture".to_string());
+    }
+
+    let now = Local::now();
+    let note = CapturedNote { captured_at: now.to_rfc3339(), date, content };
+    let dir = spool_dir();
+    fs::create_dir_all(&dir).map_err(|err| format!("Failed to create {}: {}", dir.display(), err))?;
+
+    // Named by capture time so that the queue drains in order.
+    let stem = format!("{}-{}", now.format("%Y%m%dT%H%M%S%.3f"), process::id());
+    let mut path = dir.join(format!("{}.json", stem));
+    let mut counter = 1;
+    while path.exists() {
+        path = dir.join(format!("{}-{}.json", stem, counter));
+        counter += 1;
+    }
+
+    // Write under another name first so a flush never sees half a note.
+    let partial = path.with_extension("partial");
+    let json = serde_json::to_string_pretty(&note).map_err(|err| format!("Failed to serialize note: {}", err))?;
+    fs::write(&partial, json).map_err(|err| format!("Failed to write {}: {}", partial.display(), err))?;
+    fs::rename(&partial, &path).map_err(|err| format!("Failed to write {}: {}", path.display(), err))?;
+    Ok(path)
+}
+
+/// Queued notes, oldest first.
+fn queued_notes() -> Result<Vec<PathBuf>, String> {
+    notes_in(&spool_dir())
+}
+
+fn notes_in(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(format!("Failed to read {}: {}", dir.display(), err)),
+    };
+
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    paths.sort();
+    Ok(paths)
+}
+
+fn read_note(path: &Path) -> Result<(CapturedNote, NaiveDate, DateTime<Local>), String> {
+    let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let note: CapturedNote = serde_json::from_str(&text).map_err(|err| err.to_string())?;
+    let date = note.journal_day()?;
+    let captured_at = note.captured_at()?;
+    Ok((note, date, captured_at))
+}
+
+impl Client {
+    /// Insert queued notes into their journal days, oldest first, removing
+    /// each file once its note is in Logseq. Stops at the first API error,
+    /// keeping that note queued, so the remaining notes keep their order.
+    /// Returns how many were flushed.
+    pub fn flush_captures(&self) -> Result<usize, String> {
+        let mut journals: HashMap<NaiveDate, String> = HashMap::new();
+        let mut flushed = 0;
+
+        for path in queued_notes()? {
+            let (note, date, captured_at) = match read_note(&path) {
+                Ok(parsed) => parsed,
+                Err(err) => {
+                    // Set broken files aside instead of blocking the queue.
+                    let rejected = path.with_extension("rejected");
+                    eprintln!("Skipping {}: {}. Moved to {}", path.display(), err, rejected.display());
+                    let _ = fs::rename(&path, &rejected);
+                    continue;
+                }
+            };
+
+            let page = match journals.get(&date) {
+                Some(page) => page.clone(),
+                None => {
+                    let page = self.journal_page_uuid(date)?;
+                    journals.insert(date, page.clone());
+                    page
+                }
+            };
+
+            if let Some(uuid) = self.insert_note(&page, &note.content)? {
+                let stamp = captured_at.format("%Y-%m-%d %H:%M").to_string();
+                if let Err(err) = self.call("logseq.Editor.upsertBlockProperty", json!([uuid, "captured-at", stamp])) {
+                    eprintln!("Added {} but could not set its capture time: {}", path.display(), err);
+                }
+            }
+            debug!("Flushed {} into {}", path.display(), date);
+            fs::remove_file(&path).map_err(|err| format!("Failed to remove {}: {}", path.display(), err))?;
+            flushed += 1;
+        }
+
+        Ok(flushed)
+    }
+
+    pub fn flush(&self) {
+        match self.flush_captures() {
+            Ok(0) => eprintln!("No queued notes."),
+            Ok(flushed) => eprintln!("Flushed {} notes.", flushed),
+            Err(err) => {
+                let remaining = queued_notes().map(|notes| notes.len()).unwrap_or_default();
+                eprintln!("Failed to flush: {}. {} notes are still queued.", err, remaining);
+                process::exit(1);
+            }
+        }
+    }
+
+    /// Keep draining the capture queue every `interval` seconds.
+    pub fn drain_captures(&self, interval: u64) {
+        eprintln!("Watching {} every {}s.", spool_dir().display(), interval);
+        loop {
+            match self.flush_captures() {
+                Ok(0) => {}
+                Ok(flushed) => eprintln!("Flushed {} notes.", flushed),
+                Err(err) => debug!("Flush failed, retrying later: {}", err),
+            }
+            thread::sleep(Duration::from_secs(interval));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(dir: &Path, name: &str, text: &str) -> PathBuf {
+        let path = dir.join(name);
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn queues_json_files_oldest_first() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), "20240522T101500.000-2.json", "{}");
+        write(dir.path(), "20240522T090000.000-1.json", "{}");
+        write(dir.path(), "20240522T090000.000-1-1.json", "{}");
+        write(dir.path(), "20240522T110000.000-3.partial", "{}");
+        write(dir.path(), "20240521T080000.000-4.rejected", "{}");
+        let names: Vec<String> = notes_in(dir.path())
+            .unwrap()
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(names, ["20240522T090000.000-1-1.json", "20240522T090000.000-1.json", "20240522T101500.000-2.json"]);
+        assert!(notes_in(&dir.path().join("missing")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn reads_notes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write(
+            dir.path(),
+            "note.json",
+            r#"{"captured_at": "2024-05-22T23:30:00+02:00", "date": null, "content": "idea"}"#,
+        );
+        let (note, date, captured_at) = read_note(&path).unwrap();
+        assert_eq!(note.content, "idea");
+        let expected = DateTime::parse_from_rfc3339("2024-05-22T23:30:00+02:00").unwrap().with_timezone(&Local);
+        assert_eq!(captured_at, expected);
+        assert_eq!(date, expected.date_naive());
+
+        let path = write(
+            dir.path(),
+            "dated.json",
+            r#"{"captured_at": "2024-05-22T23:30:00+02:00", "date": "2024-05-20", "content": "late"}"#,
+        );
+        assert_eq!(read_note(&path).unwrap().1, NaiveDate::from_ymd_opt(2024, 5, 20).unwrap());
+    }
+
+    #[test]
+    fn rejects_broken_notes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write(dir.path(), "truncated.json", r#"{"captured_at": "#);
+        assert!(read_note(&path).is_err());
+        let path = write(dir.path(), "time.json", r#"{"captured_at": "yesterday", "date": null, "content": "x"}"#);
+        assert!(read_note(&path).is_err_and(|err| err.contains("Invalid capture time")));
+        let path = write(
+            dir.path(),
+            "date.json",
+            r#"{"captured_at": "2024-05-22T23:30:00+02:00", "date": "someday-ish", "content": "x"}"#,
+        );
+        assert!(read_note(&path).is_err());
+    }
+}
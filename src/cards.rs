@@ -0,0 +1,268 @@
+//! `rlu cards review`: spaced repetition of blocks tagged `#card`, with the
+//! block as the question and its children (or its `{{cloze ...}}` text) as
+//! the answer. Ratings are scheduled with SM-2 and written back to the
+//! `card-*` properties Logseq keeps its own review state in, so reviews
+//! here and in Logseq's flashcards continue each other.
+
+use chrono::{DateTime, Duration, Local, Utc};
+use regex::Regex;
+use serde_json::json;
+use std::io::{self, BufRead, Write};
+use std::sync::LazyLock;
+
+use crate::model::Block;
+use crate::Client;
+
+const CARD_PULL: &str = "[:block/uuid :block/content :block/properties {:block/page [:block/original-name]}]";
+
+const REPEATS_PROPERTY: &str = "card-repeats";
+const INTERVAL_PROPERTY: &str = "card-last-interval";
+const EASE_PROPERTY: &str = "card-ease-factor";
+const NEXT_SCHEDULE_PROPERTY: &str = "card-next-schedule";
+const LAST_REVIEWED_PROPERTY: &str = "card-last-reviewed";
+const LAST_SCORE_PROPERTY: &str = "card-last-score";
+
+const INITIAL_EASE: f64 = 2.5;
+const MINIMUM_EASE: f64 = 1.3;
+
+/// `{{cloze hidden text}}`.
+static CLOZE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\{\{cloze\s+(.*?)\}\}").expect("valid regex"));
+
+/// `#card`, `#[[card]]` or `[[card]]`.
+static CARD_TAG: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\s*(#card\b|#\[\[card\]\]|\[\[card\]\])").expect("valid regex"));
+
+/// Where a card stands in its review schedule.
+#[derive(Debug, Clone, Copy)]
+struct CardState {
+    repeats: u32,
+    /// Days between the last two reviews.
+    interval: f64,
+    ease: f64,
+}
+
+impl CardState {
+    fn of(block: &Block) -> CardState {
+        let number = |key: &str| block.property(key).and_then(|value| value.as_text().trim().parse::<f64>().ok());
+        CardState {
+            repeats: number(REPEATS_PROPERTY).map_or(0, |repeats| repeats.max(0.0) as u32),
+            interval: number(INTERVAL_PROPERTY).unwrap_or(0.0).max(0.0),
+            ease: number(EASE_PROPERTY).unwrap_or(INITIAL_EASE).max(MINIMUM_EASE),
+        }
+    }
+
+    /// SM-2: a quality below 3 starts the card over, otherwise the
+    /// interval grows from 1 to 6 days and then by the ease factor, which
+    /// itself moves with how easy the recall was.
+    fn review(self, quality: u8) -> CardState {
+        let lapse = f64::from(5 - quality.min(5));
+        let ease = (self.ease + 0.1 - lapse * (0.08 + lapse * 0.02)).max(MINIMUM_EASE);
+        if quality < 3 {
+            return CardState { repeats: 0, interval: 1.0, ease };
+        }
+        let interval = match self.repeats {
+            0 => 1.0,
+            1 => 6.0,
+            _ => (self.interval.max(1.0) * self.ease).round(),
+        };
+        CardState { repeats: self.repeats + 1, interval, ease }
+    }
+}
+
+/// Two decimals, without trailing zeros.
+fn round2(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+/// `2024-05-01T09:30:00.000Z`, the format of Logseq's card timestamps.
+fn card_timestamp(time: DateTime<Utc>) -> String {
+    time.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()
+}
+
+/// When the card is next due; new cards have no schedule.
+fn next_schedule(block: &Block) -> Option<DateTime<Utc>> {
+    let value = block.property(NEXT_SCHEDULE_PROPERTY)?.as_text();
+    DateTime::parse_from_rfc3339(value.trim()).ok().map(|time| time.with_timezone(&Utc))
+}
+
+/// Block text without property lines and the card tag.
+fn card_text(content: &str) -> String {
+    content
+        .lines()
+        .filter(|line| !line.contains(":: "))
+        .map(|line| CARD_TAG.replace_all(line, "").trim_end().to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// What a rating answer means.
+enum Rating {
+    Quality(u8),
+    Skip,
+    Quit,
+}
+
+fn parse_rating(answer: &str) -> Option<Rating> {
+    match answer.trim() {
+        "s" => Some(Rating::Skip),
+        "q" => Some(Rating::Quit),
+        digit => digit.parse::<u8>().ok().filter(|quality| *quality <= 5).map(Rating::Quality),
+    }
+}
+
+/// Print `prompt` and read a line; `None` once input ends.
+fn ask(input: &mut impl Iterator<Item = io::Result<String>>, prompt: &str) -> Option<String> {
+    print!("{}", prompt);
+    io::stdout().flush().ok();
+    input.next().and_then(Result::ok)
+}
+
+impl Client {
+    /// Cards due now, the longest overdue first, then new cards.
+    fn due_cards(&self) -> Result<Vec<Block>, String> {
+        let query = format!(
+            "[:find (pull ?b {pull})
+             :where
+             [?card :block/name \"card\"]
+             [?b :block/refs ?card]]",
+            pull = CARD_PULL,
+        );
+        let now = Utc::now();
+        let mut cards: Vec<(Option<DateTime<Utc>>, Block)> = self
+            .query_pulled::<Block>(&query, &[])?
+            .into_iter()
+            .map(|block| (next_schedule(&block), block))
+            .filter(|(schedule, _)| schedule.is_none_or(|schedule| schedule <= now))
+            .collect();
+        cards.sort_by_key(|(schedule, _)| (schedule.is_none(), *schedule));
+        Ok(cards.into_iter().map(|(_, block)| block).collect())
+    }
+
+    fn save_review(&self, uuid: &str, state: CardState, quality: u8) -> Result<(), String> {
+        let now = Utc::now();
+        let next = now + Duration::seconds((state.interval * 86400.0) as i64);
+        let properties = [
+            (REPEATS_PROPERTY, state.repeats.to_string()),
+            (INTERVAL_PROPERTY, round2(state.interval).to_string()),
+            (EASE_PROPERTY, round2(state.ease).to_string()),
+            (NEXT_SCHEDULE_PROPERTY, card_timestamp(next)),
+            (LAST_REVIEWED_PROPERTY, card_timestamp(now)),
+            (LAST_SCORE_PROPERTY, quality.to_string()),
+        ];
+        for (key, value) in properties {
+            self.call("logseq.Editor.upsertBlockProperty", json!([uuid, key, value]))?;
+        }
+        Ok(())
+    }
+
+    pub fn review_cards(&self, limit: Option<usize>) {
+        if let Err(err) = self.review_cards_inner(limit) {
+            eprintln!("Failed to review cards: {}", err);
+            std::process::exit(1);
+        }
+    }
+
+    fn review_cards_inner(&self, limit: Option<usize>) -> Result<(), String> {
+        let due = self.due_cards()?;
+        if due.is_empty() {
+            eprintln!("No cards are due.");
+            return Ok(());
+        }
+        let total = limit.map_or(due.len(), |limit| limit.min(due.len()));
+        let mut input = io::stdin().lock().lines();
+        let mut reviewed = 0;
+
+        'cards: for (index, card) in due.iter().take(total).enumerate() {
+            let page = card.page.as_ref().map(|page| page.display_name()).unwrap_or_default();
+            let text = card_text(&card.content);
+            println!("\n[{}/{}] {}", index + 1, total, page);
+            println!("{}", CLOZE.replace_all(&text, "[...]"));
+            match ask(&mut input, "\nPress Enter to show the answer (q to quit) ").as_deref().map(str::trim) {
+                None | Some("q") => break,
+                _ => {}
+            }
+
+            if CLOZE.is_match(&text) {
+                println!("{}", CLOZE.replace_all(&text, "$1"));
+            }
+            let mut block = self
+                .call_as::<Option<Block>>("logseq.Editor.getBlock", json!([card.uuid, {"includeChildren": true}]))?
+                .ok_or_else(|| format!("Block {} not found", card.uuid))?;
+            self.load_children(&mut block)?;
+            let mut answer = String::new();
+            for child in block.loaded_children() {
+                self.collect_block_content(child, &mut answer, 1)?;
+            }
+            print!("{}", answer);
+
+            let quality = loop {
+                let prompt = "\n1 forgotten, 3 took a while, 5 remembered (0-5, s to skip, q to quit): ";
+                match ask(&mut input, prompt).as_deref().map(parse_rating) {
+                    None | Some(Some(Rating::Quit)) => break 'cards,
+                    Some(Some(Rating::Skip)) => continue 'cards,
+                    Some(Some(Rating::Quality(quality))) => break quality,
+                    Some(None) => continue,
+                }
+            };
+
+            let state = CardState::of(card).review(quality);
+            self.save_review(&card.uuid, state, quality)?;
+            let due = Local::now() + Duration::seconds((state.interval * 86400.0) as i64);
+            println!("Next review on {}.", due.format("%Y-%m-%d"));
+            reviewed += 1;
+        }
+
+        eprintln!("Reviewed {} of {} due cards.", reviewed, due.len());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_card() -> CardState {
+        CardState { repeats: 0, interval: 0.0, ease: INITIAL_EASE }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn grows_the_interval_from_one_to_six_days_then_by_the_ease() {
+        let first = new_card().review(4);
+        assert_eq!((first.repeats, first.interval), (1, 1.0));
+        let second = first.review(4);
+        assert_eq!((second.repeats, second.interval), (2, 6.0));
+        let third = second.review(4);
+        assert_eq!((third.repeats, third.interval), (3, 15.0));
+        let fourth = CardState { ease: 2.36, ..third }.review(4);
+        assert_eq!((fourth.repeats, fourth.interval), (4, 35.0));
+    }
+
+    #[test]
+    fn moves_the_ease_with_the_quality() {
+        assert_close(new_card().review(5).ease, 2.6);
+        assert_close(new_card().review(4).ease, 2.5);
+        assert_close(new_card().review(3).ease, 2.36);
+        assert_close(new_card().review(0).ease, 1.7);
+    }
+
+    #[test]
+    fn starts_over_below_quality_three() {
+        let learned = CardState { repeats: 5, interval: 40.0, ease: 2.5 };
+        for quality in 0..3 {
+            let lapsed = learned.review(quality);
+            assert_eq!((lapsed.repeats, lapsed.interval), (0, 1.0));
+        }
+    }
+
+    #[test]
+    fn keeps_the_ease_at_the_minimum() {
+        let hard = CardState { repeats: 3, interval: 10.0, ease: 1.4 };
+        assert_close(hard.review(0).ease, MINIMUM_EASE);
+        assert_close(hard.review(3).ease, MINIMUM_EASE);
+        assert_close(CardState { ease: MINIMUM_EASE, ..hard }.review(1).ease, MINIMUM_EASE);
+    }
+}
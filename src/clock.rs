@@ -0,0 +1,553 @@
+//! `rlu clock`: time tracking in the `:LOGBOOK:` drawer Logseq itself
+//! writes when a task moves to `DOING`:
+//!
+//! ```text
+//! DOING Write the report
+//! :LOGBOOK:
+//! CLOCK: [2024-05-01 Wed 10:00:00]--[2024-05-01 Wed 10:25:00] =>  00:25:00
+//! CLOCK: [2024-05-01 Wed 14:00:00]
+//! :END:
+//! ```
+//!
+//! A `CLOCK:` line without an end is the running clock. Open clocks are
+//! found by querying the graph, so clocks started in Logseq can be stopped
+//! here and the other way around.
+
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, TimeZone};
+use clap::ValueEnum;
+use regex::Regex;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::LazyLock;
+
+use crate::model::Block;
+use crate::{edn_string, journal, preview, Client};
+
+const CLOCK_PULL: &str = "[:block/uuid :block/content {:block/page [:block/original-name]}]";
+
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %a %H:%M:%S";
+
+/// `CLOCK: [start]` or `CLOCK: [start]--[end] =>  duration`.
+static CLOCK_LINE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^\s*CLOCK:\s*\[([^\]]+)\](?:--\[([^\]]+)\])?").expect("valid regex")
+});
+
+pub const MARKERS: &str = "TODO|DOING|DONE|LATER|NOW|WAITING|WAIT|CANCELED|CANCELLED|IN-PROGRESS|STARTED";
+
+/// The marker a first line may start with, and the priority after it.
+pub static TASK_PREFIX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(&format!(r"^(?:({})\s+)?(\[#[A-Z]\]\s+)?", MARKERS)).expect("valid regex"));
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ReportFormat {
+    Text,
+    Csv,
+    Json,
+}
+
+/// Read a logbook timestamp; the weekday and seconds are optional.
+fn parse_timestamp(text: &str) -> Option<DateTime<Local>> {
+    let mut parts = text.split_whitespace();
+    let date = parts.next()?;
+    let time = parts.find(|part| part.contains(':'))?;
+    let time = if time.len() == 5 { format!("{}:00", time) } else { time.to_string() };
+    let naive = NaiveDateTime::parse_from_str(&format!("{} {}", date, time), "%Y-%m-%d %H:%M:%S").ok()?;
+    Local.from_local_datetime(&naive).earliest()
+}
+
+/// `HH:MM:SS`, as Logseq writes clock durations.
+fn logbook_duration(seconds: i64) -> String {
+    let seconds = seconds.max(0);
+    format!("{:02}:{:02}:{:02}", seconds / 3600, seconds % 3600 / 60, seconds % 60)
+}
+
+/// `1:05`, hours and minutes.
+fn report_duration(seconds: i64) -> String {
+    format!("{}:{:02}", seconds / 3600, seconds % 3600 / 60)
+}
+
+/// Clocked intervals in `content`; open clocks have no end.
+fn clock_entries(content: &str) -> Vec<(DateTime<Local>, Option<DateTime<Local>>)> {
+    content
+        .lines()
+        .filter_map(|line| CLOCK_LINE.captures(line))
+        .filter_map(|captures| {
+            let start = parse_timestamp(&captures[1])?;
+            let end = match captures.get(2) {
+                Some(end) => Some(parse_timestamp(end.as_str())?),
+                None => None,
+            };
+            Some((start, end))
+        })
+        .collect()
+}
+
+fn has_open_clock(content: &str) -> bool {
+    clock_entries(content).iter().any(|(_, end)| end.is_none())
+}
+
+/// The first line without its marker and priority.
+fn task_title(content: &str) -> String {
+    let first = content.lines().next().unwrap_or_default();
+    preview(&TASK_PREFIX.replace(first, ""))
+}
+
+/// `content` with the marker of its first line replaced, or added.
+fn with_marker(content: &str, marker: &str) -> String {
+    let (first, rest) = content.split_once('\n').map_or((content, None), |(first, rest)| (first, Some(rest)));
+    let stripped = TASK_PREFIX.replace(first, |captures: &regex::Captures| {
+        format!("{} {}", marker, captures.get(2).map_or("", |priority| priority.as_str()))
+    });
+    match rest {
+        Some(rest) => format!("{}\n{}", stripped, rest),
+        None => stripped.into_owned(),
+    }
+}
+
+/// The marker a task is clocked in with: `NOW` in the LATER/NOW workflow,
+/// `DOING` otherwise.
+fn running_marker(content: &str) -> &'static str {
+    match TASK_PREFIX.captures(content).and_then(|captures| captures.get(1)) {
+        Some(marker) if marker.as_str() == "LATER" || marker.as_str() == "NOW" => "NOW",
+        _ => "DOING",
+    }
+}
+
+/// `content` with a new open clock in its logbook, which is created after
+/// the title, planning and property lines if there is none.
+fn clock_in_content(content: &str, now: DateTime<Local>) -> String {
+    let clock = format!("CLOCK: [{}]", now.format(TIMESTAMP_FORMAT));
+    let mut lines: Vec<String> = with_marker(content, running_marker(content)).lines().map(str::to_string).collect();
+
+    let drawer = lines.iter().position(|line| line.trim() == ":LOGBOOK:");
+    let end = drawer.and_then(|start| lines.iter().skip(start).position(|line| line.trim() == ":END:").map(|end| start + end));
+    match end {
+        Some(end) => lines.insert(end, clock),
+        None => {
+            let at = 1 + lines
+                .iter()
+                .skip(1)
+                .take_while(|line| {
+                    let line = line.trim_start();
+                    line.starts_with("SCHEDULED:") || line.starts_with("DEADLINE:") || line.contains(":: ")
+                })
+                .count();
+            lines.splice(at..at, [":LOGBOOK:".to_string(), clock, ":END:".to_string()]);
+        }
+    }
+    lines.join("\n")
+}
+
+/// `content` with its open clocks closed at `now`, the task set to
+/// `marker`, and the time clocked.
+fn clock_out_content(content: &str, now: DateTime<Local>, marker: &str) -> (String, i64) {
+    let mut clocked = 0;
+    let lines: Vec<String> = content
+        .lines()
+        .map(|line| {
+            let Some(captures) = CLOCK_LINE.captures(line).filter(|captures| captures.get(2).is_none()) else {
+                return line.to_string();
+            };
+            let Some(start) = parse_timestamp(&captures[1]) else {
+                return line.to_string();
+            };
+            let seconds = (now - start).num_seconds().max(0);
+            clocked += seconds;
+            format!(
+                "CLOCK: [{}]--[{}] =>  {}",
+                &captures[1],
+                now.format(TIMESTAMP_FORMAT),
+                logbook_duration(seconds)
+            )
+        })
+        .collect();
+    (with_marker(&lines.join("\n"), marker), clocked)
+}
+
+/// The marker a clocked-out task goes back to.
+fn stopped_marker(content: &str, done: bool) -> &'static str {
+    match (done, running_marker(content)) {
+        (true, _) => "DONE",
+        (false, "NOW") => "LATER",
+        (false, _) => "TODO",
+    }
+}
+
+/// The first moment of `date` in `zone`: midnight, or the first minute
+/// after it where the clocks skip midnight when daylight saving starts.
+fn day_start<Tz: TimeZone>(zone: &Tz, date: NaiveDate) -> DateTime<Tz> {
+    let midnight = date.and_hms_opt(0, 0, 0).expect("valid time");
+    (0..24 * 60)
+        .map(|minutes| midnight + Duration::minutes(minutes))
+        .find_map(|time| zone.from_local_datetime(&time).earliest())
+        .unwrap_or_else(|| zone.from_utc_datetime(&midnight))
+}
+
+/// Totals, most time first.
+fn by_time(totals: BTreeMap<&str, i64>) -> Vec<(&str, i64)> {
+    let mut totals: Vec<(&str, i64)> = totals.into_iter().collect();
+    totals.sort_by_key(|(_, seconds)| -seconds);
+    totals
+}
+
+/// Time clocked on one task in the reported days.
+struct TaskTime {
+    uuid: String,
+    title: String,
+    page: String,
+    tags: Vec<String>,
+    seconds: i64,
+}
+
+impl Client {
+    /// Blocks with a logbook clock line.
+    fn clocked_blocks(&self) -> Result<Vec<Block>, String> {
+        let query = format!(
+            "[:find (pull ?b {pull})
+             :in $ ?text
+             :where
+             [?b :block/content ?content]
+             [(clojure.string/includes? ?content ?text)]]",
+            pull = CLOCK_PULL,
+        );
+        self.query_pulled(&query, &[json!(edn_string("CLOCK:"))])
+    }
+
+    fn clock_out_block(&self, block: &Block, done: bool) -> Result<i64, String> {
+        let marker = stopped_marker(&block.content, done);
+        let (content, clocked) = clock_out_content(&block.content, Local::now(), marker);
+        self.call("logseq.Editor.updateBlock", json!([block.uuid, content]))?;
+        eprintln!("Clocked out of {} after {}.", task_title(&block.content), logbook_duration(clocked));
+        Ok(clocked)
+    }
+
+    pub fn clock_in(&self, uuid: &str) {
+        if let Err(err) = self.clock_in_inner(uuid) {
+            eprintln!("Failed to clock in: {}", err);
+            std::process::exit(1);
+        }
+    }
+
+    fn clock_in_inner(&self, uuid: &str) -> Result<(), String> {
+        let block: Block = self
+            .call_as::<Option<Block>>("logseq.Editor.getBlock", json!([uuid]))?
+            .ok_or_else(|| format!("Block {} not found", uuid))?;
+        if has_open_clock(&block.content) {
+            return Err(format!("Already clocked in to {}", task_title(&block.content)));
+        }
+
+        // One clock runs at a time.
+        for running in self.clocked_blocks()? {
+            if running.uuid != block.uuid && has_open_clock(&running.content) {
+                self.clock_out_block(&running, false)?;
+            }
+        }
+
+        let content = clock_in_content(&block.content, Local::now());
+        self.call("logseq.Editor.updateBlock", json!([block.uuid, content]))?;
+        eprintln!("Clocked in to {}.", task_title(&block.content));
+        Ok(())
+    }
+
+    pub fn clock_out(&self, uuid: Option<&str>, done: bool) {
+        if let Err(err) = self.clock_out_inner(uuid, done) {
+            eprintln!("Failed to clock out: {}", err);
+            std::process::exit(1);
+        }
+    }
+
+    fn clock_out_inner(&self, uuid: Option<&str>, done: bool) -> Result<(), String> {
+        let running: Vec<Block> = self
+            .clocked_blocks()?
+            .into_iter()
+            .filter(|block| uuid.is_none_or(|uuid| block.uuid == uuid))
+            .filter(|block| has_open_clock(&block.content))
+            .collect();
+        if running.is_empty() {
+            return Err(match uuid {
+                Some(uuid) => format!("No clock is running on {}", uuid),
+                None => "No clock is running".to_string(),
+            });
+        }
+        for block in &running {
+            self.clock_out_block(block, done)?;
+        }
+        Ok(())
+    }
+
+    /// Pages each clocked block links to, other than its marker and
+    /// priority pages.
+    fn clocked_block_tags(&self) -> Result<HashMap<String, Vec<String>>, String> {
+        let rows = self.datascript_query(
+            "[:find ?uuid ?name
+             :in $ ?text
+             :where
+             [?b :block/content ?content]
+             [(clojure.string/includes? ?content ?text)]
+             [?b :block/uuid ?uuid]
+             [?b :block/refs ?p]
+             [?p :block/original-name ?name]]",
+            &[json!(edn_string("CLOCK:"))],
+        )?;
+
+        let mut tags: HashMap<String, Vec<String>> = HashMap::new();
+        for row in rows {
+            let (Some(uuid), Some(name)) = (row.get(0).and_then(|v| v.as_str()), row.get(1).and_then(|v| v.as_str())) else {
+                continue;
+            };
+            let is_marker = MARKERS.split('|').any(|marker| marker.eq_ignore_ascii_case(name));
+            let is_priority = name.len() == 1 && name.chars().all(|c| c.is_ascii_uppercase());
+            if !is_marker && !is_priority {
+                tags.entry(uuid.to_string()).or_default().push(name.to_string());
+            }
+        }
+        Ok(tags)
+    }
+
+    /// Time clocked per task from the start of `from` to the end of `to`.
+    /// Clocks that started earlier or end later count with the part inside
+    /// those days; running clocks count up to now.
+    fn clocked_times(&self, from: NaiveDate, to: NaiveDate) -> Result<Vec<TaskTime>, String> {
+        let (start, end) = (day_start(&Local, from), day_start(&Local, to.succ_opt().unwrap_or(to)));
+        let now = Local::now();
+        let mut tags = self.clocked_block_tags()?;
+
+        let mut times: Vec<TaskTime> = self
+            .clocked_blocks()?
+            .into_iter()
+            .filter_map(|block| {
+                let seconds: i64 = clock_entries(&block.content)
+                    .into_iter()
+                    .map(|(clock_start, clock_end)| {
+                        let clock_end = clock_end.unwrap_or(now).min(end);
+                        (clock_end - clock_start.max(start)).num_seconds().max(0)
+                    })
+                    .sum();
+                (seconds > 0).then(|| TaskTime {
+                    title: task_title(&block.content),
+                    page: block.page.as_ref().map(|page| page.display_name().to_string()).unwrap_or_default(),
+                    tags: tags.remove(&block.uuid).unwrap_or_default(),
+                    uuid: block.uuid,
+                    seconds,
+                })
+            })
+            .collect();
+        times.sort_by(|a, b| b.seconds.cmp(&a.seconds).then_with(|| a.title.cmp(&b.title)));
+        Ok(times)
+    }
+
+    pub fn clock_report(&self, from: Option<&str>, to: Option<&str>, format: ReportFormat) {
+        if let Err(err) = self.clock_report_inner(from, to, format) {
+            eprintln!("Failed to report clocked time: {}", err);
+            std::process::exit(1);
+        }
+    }
+
+    fn clock_report_inner(&self, from: Option<&str>, to: Option<&str>, format: ReportFormat) -> Result<(), String> {
+        let today = Local::now().date_naive();
+        let from = from.map(journal::parse_date).transpose()?.unwrap_or(today);
+        let to = to.map(journal::parse_date).transpose()?.unwrap_or(today.max(from));
+        if to < from {
+            return Err(format!("--to {} is before --from {}", to, from));
+        }
+
+        let tasks = self.clocked_times(from, to)?;
+        let total: i64 = tasks.iter().map(|task| task.seconds).sum();
+        let mut pages: BTreeMap<&str, i64> = BTreeMap::new();
+        let mut tags: BTreeMap<&str, i64> = BTreeMap::new();
+        for task in &tasks {
+            *pages.entry(&task.page).or_default() += task.seconds;
+            for tag in &task.tags {
+                *tags.entry(tag).or_default() += task.seconds;
+            }
+        }
+        let (pages, tags) = (by_time(pages), by_time(tags));
+
+        match format {
+            ReportFormat::Text => {
+                println!("{} to {}: {}", from, to, report_duration(total));
+                println!("Tasks");
+                for task in &tasks {
+                    println!("  {:>6}  {} {} ({})", report_duration(task.seconds), task.uuid, task.title, task.page);
+                }
+                for (heading, totals) in [("Pages", &pages), ("Tags", &tags)] {
+                    if !totals.is_empty() {
+                        println!("{}", heading);
+                    }
+                    for (name, seconds) in totals {
+                        println!("  {:>6}  {}", report_duration(*seconds), name);
+                    }
+                }
+            }
+            ReportFormat::Csv => {
+                let mut writer = csv::Writer::from_writer(std::io::stdout());
+                let mut write = |record: [&str; 5]| writer.write_record(record).map_err(|err| err.to_string());
+                write(["kind", "name", "uuid", "page", "seconds"])?;
+                for task in &tasks {
+                    write(["task", &task.title, &task.uuid, &task.page, &task.seconds.to_string()])?;
+                }
+                for (name, seconds) in &pages {
+                    write(["page", name, "", "", &seconds.to_string()])?;
+                }
+                for (name, seconds) in &tags {
+                    write(["tag", name, "", "", &seconds.to_string()])?;
+                }
+                writer.flush().map_err(|err| err.to_string())?;
+            }
+            ReportFormat::Json => {
+                let totals = |totals: &[(&str, i64)]| {
+                    totals
+                        .iter()
+                        .map(|(name, seconds)| json!({"name": name, "seconds": seconds}))
+                        .collect::<Vec<_>>()
+                };
+                let report = json!({
+                    "from": from.to_string(),
+                    "to": to.to_string(),
+                    "seconds": total,
+                    "tasks": tasks
+                        .iter()
+                        .map(|task| json!({
+                            "uuid": task.uuid,
+                            "title": task.title,
+                            "page": task.page,
+                            "tags": task.tags,
+                            "seconds": task.seconds,
+                        }))
+                        .collect::<Vec<_>>(),
+                    "pages": totals(&pages),
+                    "tags": totals(&tags),
+                });
+                println!("{}", serde_json::to_string_pretty(&report).unwrap_or_default());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{FixedOffset, LocalResult, NaiveTime};
+
+    fn at(hour: u32, minute: u32, second: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 5, 1, hour, minute, second).earliest().unwrap()
+    }
+
+    #[test]
+    fn sets_markers_keeping_the_priority() {
+        assert_eq!(with_marker("TODO [#B] write report", "DONE"), "DONE [#B] write report");
+        assert_eq!(with_marker("NOW task\nnotes", "LATER"), "LATER task\nnotes");
+        assert_eq!(with_marker("plain text\nTODO not a title", "DOING"), "DOING plain text\nTODO not a title");
+        assert_eq!(with_marker("TODOS for today", "DONE"), "DONE TODOS for today");
+        assert_eq!(with_marker("[#A] urgent", "TODO"), "TODO [#A] urgent");
+    }
+
+    #[test]
+    fn picks_the_marker_of_the_workflow() {
+        assert_eq!(running_marker("LATER task"), "NOW");
+        assert_eq!(running_marker("TODO task"), "DOING");
+        assert_eq!(running_marker("task"), "DOING");
+        assert_eq!(stopped_marker("NOW task", false), "LATER");
+        assert_eq!(stopped_marker("DOING task", false), "TODO");
+        assert_eq!(stopped_marker("NOW task", true), "DONE");
+    }
+
+    #[test]
+    fn clocks_in_with_a_new_logbook() {
+        assert_eq!(
+            clock_in_content("TODO write report", at(9, 30, 0)),
+            "DOING write report\n:LOGBOOK:\nCLOCK: [2024-05-01 Wed 09:30:00]\n:END:"
+        );
+        assert_eq!(
+            clock_in_content("LATER [#A] task\nSCHEDULED: <2024-05-01 Wed>\nid:: 1234\nnotes", at(9, 30, 0)),
+            "NOW [#A] task\nSCHEDULED: <2024-05-01 Wed>\nid:: 1234\n:LOGBOOK:\nCLOCK: [2024-05-01 Wed 09:30:00]\n:END:\nnotes"
+        );
+    }
+
+    #[test]
+    fn clocks_in_to_an_existing_logbook() {
+        let content = "TODO task\n:LOGBOOK:\nCLOCK: [2024-04-30 Tue 10:00:00]--[2024-04-30 Tue 10:10:00] =>  00:10:00\n:END:";
+        assert_eq!(
+            clock_in_content(content, at(9, 30, 0)),
+            "DOING task\n:LOGBOOK:\nCLOCK: [2024-04-30 Tue 10:00:00]--[2024-04-30 Tue 10:10:00] =>  00:10:00\nCLOCK: [2024-05-01 Wed 09:30:00]\n:END:"
+        );
+    }
+
+    #[test]
+    fn clocks_out_open_clocks() {
+        let content = clock_in_content("TODO task", at(9, 0, 0));
+        let (content, seconds) = clock_out_content(&content, at(10, 30, 15), "DONE");
+        assert_eq!(seconds, 5415);
+        assert_eq!(
+            content,
+            "DONE task\n:LOGBOOK:\nCLOCK: [2024-05-01 Wed 09:00:00]--[2024-05-01 Wed 10:30:15] =>  01:30:15\n:END:"
+        );
+        assert!(!has_open_clock(&content));
+
+        // Closed clocks are left alone.
+        let (again, seconds) = clock_out_content(&content, at(11, 0, 0), "DONE");
+        assert_eq!((again.as_str(), seconds), (content.as_str(), 0));
+    }
+
+    /// UTC-3 until clocks skip from 00:00 to 01:00 on 2018-11-04, UTC-2 after.
+    #[derive(Clone)]
+    struct SkippedMidnight;
+
+    impl SkippedMidnight {
+        fn switch() -> NaiveDateTime {
+            NaiveDate::from_ymd_opt(2018, 11, 4).unwrap().and_hms_opt(3, 0, 0).unwrap()
+        }
+    }
+
+    impl TimeZone for SkippedMidnight {
+        type Offset = FixedOffset;
+
+        fn from_offset(_: &FixedOffset) -> Self {
+            SkippedMidnight
+        }
+
+        fn offset_from_local_date(&self, local: &NaiveDate) -> LocalResult<FixedOffset> {
+            self.offset_from_local_datetime(&local.and_time(NaiveTime::MIN))
+        }
+
+        fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> LocalResult<FixedOffset> {
+            let (before, after) = (FixedOffset::west_opt(3 * 3600).unwrap(), FixedOffset::west_opt(2 * 3600).unwrap());
+            let midnight = NaiveDate::from_ymd_opt(2018, 11, 4).unwrap().and_hms_opt(0, 0, 0).unwrap();
+            if *local < midnight {
+                LocalResult::Single(before)
+            } else if *local < midnight + Duration::hours(1) {
+                LocalResult::None
+            } else {
+                LocalResult::Single(after)
+            }
+        }
+
+        fn offset_from_utc_date(&self, utc: &NaiveDate) -> FixedOffset {
+            self.offset_from_utc_datetime(&utc.and_time(NaiveTime::MIN))
+        }
+
+        fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> FixedOffset {
+            let hours = if *utc < Self::switch() { 3 } else { 2 };
+            FixedOffset::west_opt(hours * 3600).unwrap()
+        }
+    }
+
+    #[test]
+    fn starts_days_after_a_skipped_midnight() {
+        let day = |d| NaiveDate::from_ymd_opt(2018, 11, d).unwrap();
+        assert_eq!(day_start(&SkippedMidnight, day(3)).naive_local(), day(3).and_hms_opt(0, 0, 0).unwrap());
+        assert_eq!(day_start(&SkippedMidnight, day(4)).naive_local(), day(4).and_hms_opt(1, 0, 0).unwrap());
+        assert_eq!(day_start(&SkippedMidnight, day(4)).naive_utc(), SkippedMidnight::switch());
+    }
+
+    #[test]
+    fn skipped_midnight_offsets_dates_at_midnight() {
+        let day = |d| NaiveDate::from_ymd_opt(2018, 11, d).unwrap();
+        let west = |hours| FixedOffset::west_opt(hours * 3600).unwrap();
+        assert_eq!(SkippedMidnight.offset_from_local_date(&day(3)), LocalResult::Single(west(3)));
+        assert_eq!(SkippedMidnight.offset_from_local_date(&day(4)), LocalResult::None);
+        assert_eq!(SkippedMidnight.offset_from_utc_date(&day(4)), west(3));
+        assert_eq!(SkippedMidnight.offset_from_utc_date(&day(5)), west(2));
+    }
+}
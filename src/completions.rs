@@ -0,0 +1,382 @@
+use clap::{Command, CommandFactory, ValueEnum};
+use clap_complete::Shell;
+use log::debug;
+
+use crate::model::{Block, JournalDay};
+use crate::{preview, Cli, Client};
+
+/// Kinds of values that are completed dynamically by asking Logseq via
+/// `rlu __complete <kind>`.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum CompletionKind {
+    EntryIds,
+    Pages,
+    Tags,
+}
+
+impl CompletionKind {
+    fn name(self) -> &'static str {
+        match self {
+            CompletionKind::EntryIds => "entry-ids",
+            CompletionKind::Pages => "pages",
+            CompletionKind::Tags => "tags",
+        }
+    }
+}
+
+/// Long flags whose values are completed dynamically, by the subcommands
+/// they belong to (none for every subcommand) and the flag's name.
+const DYNAMIC_FLAGS: &[(&[&str], &str, CompletionKind)] = &[
+    (&[], "entry-id", CompletionKind::EntryIds),
+    (&[], "page", CompletionKind::Pages),
+    (&[], "block", CompletionKind::EntryIds),
+    (&[], "tree", CompletionKind::EntryIds),
+    (&[], "tag", CompletionKind::Tags),
+    (&["attach"], "to", CompletionKind::EntryIds),
+];
+
+/// Positional arguments whose values are completed dynamically, by the
+/// subcommands leading to them and the argument's name.
+const DYNAMIC_POSITIONALS: &[(&[&str], &str, CompletionKind)] = &[
+    (&["edit"], "uuid", CompletionKind::EntryIds),
+    (&["replace"], "entry_id", CompletionKind::EntryIds),
+    (&["backlinks"], "uuid", CompletionKind::EntryIds),
+    (&["refs"], "page", CompletionKind::Pages),
+    (&["clock", "in"], "uuid", CompletionKind::EntryIds),
+    (&["page", "show"], "name", CompletionKind::Pages),
+    (&["page", "info"], "name", CompletionKind::Pages),
+    (&["page", "rename"], "old", CompletionKind::Pages),
+    (&["page", "alias"], "page", CompletionKind::Pages),
+    (&["page", "merge"], "src", CompletionKind::Pages),
+    (&["page", "merge"], "dst", CompletionKind::Pages),
+];
+
+pub fn print_completions(shell: Shell) {
+    let bin_name = env!("CARGO_PKG_NAME");
+    let script = generated_script(shell, bin_name);
+
+    let script = match shell {
+        Shell::Bash => bash_dynamic(script, bin_name),
+        Shell::Zsh => {
+            let (script, unmatched) = zsh_dynamic(script, bin_name);
+            if !unmatched.is_empty() {
+                debug!("No zsh completion spec found for {}", unmatched.join(", "));
+            }
+            script
+        }
+        Shell::Fish => fish_dynamic(script, bin_name),
+        // Elvish and PowerShell only get the static completions.
+        _ => script,
+    };
+
+    print!("{}", script);
+}
+
+/// The static completion script clap_complete generates.
+fn generated_script(shell: Shell, bin_name: &'static str) -> String {
+    let cli = Cli::command();
+
+    // Hidden commands such as `__complete` are left out of the scripts.
+    let mut cmd = Command::new(bin_name)
+        .about(cli.get_about().cloned().unwrap_or_default())
+        .args(cli.get_arguments().cloned())
+        .subcommands(cli.get_subcommands().filter(|sub| !sub.is_hide_set()).cloned());
+
+    let mut script = Vec::new();
+    clap_complete::generate(shell, &mut cmd, bin_name, &mut script);
+    String::from_utf8(script).expect("completion script is not valid UTF-8")
+}
+
+/// How many positional arguments come before `arg` in the subcommand
+/// reached by `path`.
+fn positional_index(path: &[&str], arg: &str) -> usize {
+    let mut cmd = Cli::command();
+    for name in path {
+        cmd = cmd.find_subcommand(name).cloned().expect("known subcommand");
+    }
+    let index = cmd.get_positionals().position(|positional| positional.get_id() == arg);
+    index.expect("known argument")
+}
+
+/// `COMPREPLY` from the candidates `rlu __complete` prints for `kind`.
+fn bash_candidates(bin_name: &str, kind: CompletionKind) -> String {
+    format!(
+        "COMPREPLY=( $(compgen -W \"$({bin} __complete {kind} \"${{cur}}\" 2>/dev/null | cut -f1)\" -- \"${{cur}}\") )",
+        bin = bin_name,
+        kind = kind.name(),
+    )
+}
+
+/// Wrap the generated `_rlu` function so that values of the dynamic flags
+/// and positional arguments are completed from `rlu __complete` and
+/// everything else falls through.
+fn bash_dynamic(mut script: String, bin_name: &str) -> String {
+    // Arguments are found by the words before them that are not options,
+    // e.g. `clock in`, followed by the option before the cursor for flags
+    // and by any earlier positional arguments otherwise.
+    let mut cases = String::new();
+    for (path, flag, kind) in DYNAMIC_FLAGS {
+        let path = match path {
+            [] => "*".to_string(),
+            path => format!("\"{}\"*", path.join(" ")),
+        };
+        cases.push_str(&format!(
+            "        {path}\"|--{flag}\")\n            {reply}\n            return 0\n            ;;\n",
+            path = path,
+            flag = flag,
+            reply = bash_candidates(bin_name, *kind),
+        ));
+    }
+
+    let mut positional_cases = String::new();
+    for (path, arg, kind) in DYNAMIC_POSITIONALS {
+        positional_cases.push_str(&format!(
+            "            \"{path}\"{earlier})\n                {reply}\n                return 0\n                ;;\n",
+            path = path.join(" "),
+            earlier = "\" \"*".repeat(positional_index(path, arg)),
+            reply = bash_candidates(bin_name, *kind),
+        ));
+    }
+
+    script.push_str(&format!(
+        "\n_{bin}_dynamic() {{\n    local cur prev i path\n    cur=\"${{COMP_WORDS[COMP_CWORD]}}\"\n    prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"\n    path=\"\"\n    for (( i = 1; i < COMP_CWORD; i++ )); do\n        [[ ${{COMP_WORDS[i]}} == -* ]] || path=\"${{path:+${{path}} }}${{COMP_WORDS[i]}}\"\n    done\n    case \"${{path}}|${{prev}}\" in\n{cases}    esac\n    if [[ ${{cur}} != -* ]]; then\n        case \"${{path}}\" in\n{positional_cases}        esac\n    fi\n    _{bin} \"$@\"\n}}\n\ncomplete -F _{bin}_dynamic -o bashdefault -o default {bin}\n",
+        bin = bin_name,
+        cases = cases,
+        positional_cases = positional_cases,
+    ));
+    script
+}
+
+/// The `_arguments` call of the subcommand reached by `path`, or the whole
+/// script for an empty path.
+fn zsh_arguments(script: &str, path: &[&str]) -> Option<(usize, usize)> {
+    if path.is_empty() {
+        return Some((0, script.len()));
+    }
+    let mut start = 0;
+    for name in path {
+        start += script[start..].find(&format!("({})\n_arguments", name))?;
+    }
+    let end = start + script[start..].find("&& ret=0")?;
+    Some((start, end))
+}
+
+/// Replace every `from` within `range` of `script` by `to`; returns whether
+/// there was one.
+fn replace_within(script: &mut String, (start, end): (usize, usize), from: &str, to: &str) -> bool {
+    if !script[start..end].contains(from) {
+        return false;
+    }
+    let replaced = script[start..end].replace(from, to);
+    script.replace_range(start..end, &replaced);
+    true
+}
+
+/// Point the `_arguments` actions of the dynamic flags and positional
+/// arguments at a helper that describes the candidates returned by
+/// `rlu __complete`. Also returns the arguments whose spec was not found,
+/// which means the generator's output changed.
+fn zsh_dynamic(script: String, bin_name: &str) -> (String, Vec<String>) {
+    let mut script = script;
+    let mut unmatched = Vec::new();
+    for (path, arg, kind) in DYNAMIC_POSITIONALS {
+        let action = format!("':{}:_{}_dynamic {}'", arg, bin_name, kind.name());
+        let found = zsh_arguments(&script, path)
+            .is_some_and(|range| replace_within(&mut script, range, &format!("':{}:'", arg), &action));
+        if !found {
+            unmatched.push(format!("{} <{}>", path.join(" "), arg));
+        }
+    }
+    for (path, flag, kind) in DYNAMIC_FLAGS {
+        let value_name = flag.replace('-', "_").to_uppercase();
+        let action = format!("]:{}:_{}_dynamic {}'", value_name, bin_name, kind.name());
+        let found = zsh_arguments(&script, path).is_some_and(|range| {
+            // Flags without a value hint end in an empty action, the others
+            // in `_default`.
+            let empty = replace_within(&mut script, range, &format!("]:{}: '", value_name), &action);
+            let range = zsh_arguments(&script, path).unwrap_or(range);
+            replace_within(&mut script, range, &format!("]:{}:_default'", value_name), &action) || empty
+        });
+        if !found {
+            unmatched.push(format!("{} --{}", path.join(" "), flag).trim_start().to_string());
+        }
+    }
+
+    let helper = format!(
+        "_{bin}_dynamic() {{\n    local -a candidates\n    candidates=(${{(f)\"$({bin} __complete $1 2>/dev/null | sed -e 's/:/\\\\:/g' -e 's/\\t/:/')\"}})\n    _describe -t $1 $1 candidates\n}}\n\n",
+        bin = bin_name,
+    );
+
+    // The helper has to be defined before the `compdef` line at the end.
+    let script = match script.rfind("if [ \"$funcstack[1]\" = \"_") {
+        Some(index) => {
+            script.insert_str(index, &helper);
+            script
+        }
+        None => helper + &script,
+    };
+    (script, unmatched)
+}
+
+/// Turn off file completion for the dynamic flags and positional arguments
+/// and offer the candidates from `rlu __complete` instead; fish shows the
+/// tab-separated preview.
+fn fish_dynamic(script: String, bin_name: &str) -> String {
+    let mut script: String = script
+        .lines()
+        .map(|line| {
+            let kind = DYNAMIC_FLAGS
+                .iter()
+                .find(|(path, flag, _)| {
+                    line.contains(&format!(" -l {} ", flag))
+                        && (path.is_empty() || line.starts_with(&format!("complete -c {} -n \"{}\"", bin_name, fish_condition(path))))
+                })
+                .map(|(_, _, kind)| *kind);
+
+            match kind {
+                Some(kind) => format!("{} -f -a \"({} __complete {})\"\n", line, bin_name, kind.name()),
+                None => format!("{}\n", line),
+            }
+        })
+        .collect();
+
+    // fish cannot tell positional arguments apart, so `page merge` offers
+    // pages for both of them.
+    let mut paths: Vec<(&[&str], CompletionKind)> = Vec::new();
+    for (path, _, kind) in DYNAMIC_POSITIONALS {
+        if !paths.iter().any(|(seen, _)| seen == path) {
+            paths.push((path, *kind));
+        }
+    }
+    for (path, kind) in paths {
+        let condition = fish_condition(path);
+        script.push_str(&format!(
+            "complete -c {bin} -n \"{condition}\" -f -a \"({bin} __complete {kind})\"\n",
+            bin = bin_name,
+            condition = condition,
+            kind = kind.name(),
+        ));
+    }
+    script
+}
+
+/// The fish condition for being in the subcommand reached by `path`.
+fn fish_condition(path: &[&str]) -> String {
+    path.iter()
+        .map(|name| format!("__fish_seen_subcommand_from {}", name))
+        .collect::<Vec<_>>()
+        .join("; and ")
+}
+
+impl Client {
+    pub fn print_completion_candidates(&self, kind: CompletionKind, prefix: &str) {
+        let candidates = match kind {
+            CompletionKind::EntryIds => self.entry_id_candidates(),
+            CompletionKind::Pages => self.page_name_candidates(),
+            CompletionKind::Tags => self.tag_candidates(),
+        };
+
+        match candidates {
+            Ok(candidates) => {
+                for (value, description) in candidates {
+                    if !value.starts_with(prefix) {
+                        continue;
+                    }
+                    match description {
+                        Some(description) => println!("{}\t{}", value, description),
+                        None => println!("{}", value),
+                    }
+                }
+            }
+            Err(err) => {
+                // Completion scripts discard stderr; keep it for debugging.
+                debug!("Failed to fetch completion candidates: {}", err);
+            }
+        }
+    }
+
+    /// Blocks on today's journal page, with a short content preview.
+    fn entry_id_candidates(&self) -> Result<Vec<(String, Option<String>)>, String> {
+        fn collect(block: &Block, candidates: &mut Vec<(String, Option<String>)>) {
+            if !block.content.trim().is_empty() {
+                candidates.push((block.uuid.clone(), Some(preview(&block.content))));
+            }
+            for child in block.loaded_children() {
+                collect(child, candidates);
+            }
+        }
+
+        let today = JournalDay::from_date(chrono::Local::now().date_naive());
+        let mut candidates = Vec::new();
+        for block in self.cached_graph_for_completion()?.journal_blocks(today)? {
+            collect(&block, &mut candidates);
+        }
+        Ok(candidates)
+    }
+
+    fn page_name_candidates(&self) -> Result<Vec<(String, Option<String>)>, String> {
+        let mut names: Vec<String> = self
+            .cached_graph_for_completion()?
+            .pages()?
+            .into_iter()
+            .map(|page| page.display_name().to_string())
+            .collect();
+        names.sort_by_key(|name| name.to_lowercase());
+        Ok(names.into_iter().map(|name| (name, None)).collect())
+    }
+
+    fn tag_candidates(&self) -> Result<Vec<(String, Option<String>)>, String> {
+        Ok(self
+            .tag_counts()?
+            .into_iter()
+            .map(|(name, count)| (name, Some(format!("{} uses", count))))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_every_dynamic_argument_in_the_zsh_script() {
+        let (script, unmatched) = zsh_dynamic(generated_script(Shell::Zsh, "rlu"), "rlu");
+        assert!(unmatched.is_empty(), "not found: {:?}", unmatched);
+        // `clock report --to` takes a date, unlike `attach --to`.
+        assert!(script.contains("]:TO:_rlu_dynamic entry-ids'"));
+        assert!(script.contains("]:TO: '"));
+    }
+
+    #[test]
+    fn completes_every_dynamic_argument_in_bash() {
+        let script = bash_dynamic(generated_script(Shell::Bash, "rlu"), "rlu");
+        assert!(script.contains("        *\"|--entry-id\")\n"));
+        assert!(script.contains("        \"attach\"*\"|--to\")\n"));
+        assert!(script.contains("            \"page merge\")\n"));
+        assert!(script.contains("            \"page merge\"\" \"*)\n"));
+        assert!(script.contains("            \"clock in\")\n"));
+        assert!(script.contains("complete -F _rlu_dynamic"));
+    }
+
+    #[test]
+    fn completes_every_dynamic_flag_in_fish() {
+        let script = fish_dynamic(generated_script(Shell::Fish, "rlu"), "rlu");
+        for (path, flag, kind) in DYNAMIC_FLAGS {
+            let completed = script.lines().any(|line| {
+                line.contains(&format!(" -l {} ", flag))
+                    && line.contains(&fish_condition(path))
+                    && line.ends_with(&format!("-a \"(rlu __complete {})\"", kind.name()))
+            });
+            assert!(completed, "--{} is not completed", flag);
+        }
+        let report_to = script.lines().find(|line| line.contains("subcommand_from report") && line.contains(" -l to "));
+        assert!(report_to.is_some_and(|line| !line.contains("__complete")));
+    }
+
+    #[test]
+    fn counts_earlier_positional_arguments() {
+        assert_eq!(positional_index(&["page", "merge"], "src"), 0);
+        assert_eq!(positional_index(&["page", "merge"], "dst"), 1);
+        assert_eq!(positional_index(&["replace"], "entry_id"), 0);
+    }
+}
@@ -0,0 +1,232 @@
+//! `rlu daemon`: keeps one HTTP client and caches of journal and page uuids
+//! warm, and serves them over a Unix domain socket. Every command's Logseq
+//! calls go through the daemon when it is running, so they skip the uuid
+//! lookups and connection setup.
+//!
+//! The protocol is JSON-RPC 2.0, one request or response per line:
+//!
+//! - `logseq` `{"method": "logseq.Editor.getBlock", "args": [...]}`: a
+//!   Logseq API call, answered with its result.
+//! - `journal_page_uuid` `{"date": "2024-05-22"}`: the journal page's uuid,
+//!   creating the page if needed.
+//! - `page_uuid` `{"name": "Project X"}`: a page's uuid, creating the page if
+//!   needed.
+//! - `ping`: answered with `"pong"`.
+
+use chrono::NaiveDate;
+use log::debug;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::fs::DirBuilder;
+use std::os::unix::fs::DirBuilderExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{journal, Client};
+
+/// Cached uuids are looked up again after this long, in case the page was
+/// deleted or renamed in the meantime.
+const CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// `RLU_SOCKET` or `~/.rlu/daemon.sock`.
+pub fn socket_path() -> PathBuf {
+    match env::var("RLU_SOCKET") {
+        Ok(path) => PathBuf::from(path),
+        Err(_) => PathBuf::from(env::var("HOME").unwrap_or_default()).join(".rlu").join("daemon.sock"),
+    }
+}
+
+/// A uuid cache whose entries expire after `CACHE_TTL`.
+#[derive(Default)]
+struct UuidCache<K> {
+    entries: Mutex<HashMap<K, (String, Instant)>>,
+}
+
+impl<K: std::hash::Hash + Eq> UuidCache<K> {
+    fn get_or_try_insert(&self, key: K, resolve: impl FnOnce() -> Result<String, String>) -> Result<String, String> {
+        if let Some((uuid, at)) = self.entries.lock().unwrap().get(&key) {
+            if at.elapsed() < CACHE_TTL {
+                return Ok(uuid.clone());
+            }
+        }
+        let uuid = resolve()?;
+        self.entries.lock().unwrap().insert(key, (uuid.clone(), Instant::now()));
+        Ok(uuid)
+    }
+}
+
+struct Daemon {
+    client: Client,
+    journals: UuidCache<NaiveDate>,
+    pages: UuidCache<String>,
+}
+
+impl Daemon {
+    fn handle(&self, method: &str, params: &Value) -> Result<Value, String> {
+        let param = |name: &str| {
+            params
+                .get(name)
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| format!("Missing parameter '{}'", name))
+        };
+
+        match method {
+            "ping" => Ok(json!("pong")),
+            "logseq" => {
+                let args = params.get("args").cloned().unwrap_or_else(|| json!([]));
+                self.client.call(param("method")?, args)
+            }
+            "journal_page_uuid" => {
+                let date = journal::parse_date(param("date")?)?;
+                let uuid = self.journals.get_or_try_insert(date, || self.client.journal_page_uuid(date))?;
+                Ok(json!(uuid))
+            }
+            "page_uuid" => {
+                let name = param("name")?;
+                let uuid = self
+                    .pages
+                    .get_or_try_insert(name.to_lowercase(), || self.client.get_or_create_page(name))?;
+                Ok(json!(uuid))
+            }
+            _ => Err(format!("Unknown method '{}'", method)),
+        }
+    }
+
+    fn serve(&self, stream: UnixStream) {
+        let mut writer = match stream.try_clone() {
+            Ok(writer) => writer,
+            Err(err) => {
+                debug!("Failed to clone connection: {}", err);
+                return;
+            }
+        };
+
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else { break };
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let response = match serde_json::from_str::<Value>(&line) {
+                Ok(request) => {
+                    let method = request.get("method").and_then(|v| v.as_str()).unwrap_or("");
+                    let params = request.get("params").cloned().unwrap_or(Value::Null);
+                    debug!("Request: {} {}", method, params);
+                    match self.handle(method, &params) {
+                        Ok(result) => json!({"jsonrpc": "2.0", "id": request.get("id"), "result": result}),
+                        Err(message) => json!({
+                            "jsonrpc": "2.0",
+                            "id": request.get("id"),
+                            "error": {"code": -32000, "message": message}
+                        }),
+                    }
+                }
+                Err(err) => json!({
+                    "jsonrpc": "2.0",
+                    "id": null,
+                    "error": {"code": -32700, "message": format!("Parse error: {}", err)}
+                }),
+            };
+
+            if writeln!(writer, "{}", response).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+/// Run the daemon: serve the socket and drain the capture queue every
+/// `interval` seconds.
+pub fn run(interval: u64) -> Result<(), String> {
+    let path = socket_path();
+    if UnixStream::connect(&path).is_ok() {
+        return Err(format!("A daemon is already listening on {}", path.display()));
+    }
+    // Left behind by a daemon that did not shut down cleanly.
+    let _ = fs::remove_file(&path);
+    if let Some(dir) = path.parent() {
+        DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)
+            .map_err(|err| format!("Failed to create {}: {}", dir.display(), err))?;
+    }
+    // Fail right away rather than on every request without an API key.
+    let client = Client::direct();
+    client.http_client()?;
+
+    // The socket hands out the API key's full access, so it is created
+    // private instead of being restricted after the fact.
+    // SAFETY: umask only swaps the process's file mode mask; no other
+    // threads are running yet.
+    let umask = unsafe { libc::umask(0o177) };
+    let listener = UnixListener::bind(&path);
+    unsafe { libc::umask(umask) };
+    let listener = listener.map_err(|err| format!("Failed to listen on {}: {}", path.display(), err))?;
+    eprintln!("Listening on {}.", path.display());
+
+    thread::spawn(move || Client::direct().drain_captures(interval));
+
+    let daemon = Arc::new(Daemon {
+        client,
+        journals: UuidCache::default(),
+        pages: UuidCache::default(),
+    });
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let daemon = Arc::clone(&daemon);
+                thread::spawn(move || daemon.serve(stream));
+            }
+            Err(err) => debug!("Failed to accept connection: {}", err),
+        }
+    }
+    Ok(())
+}
+
+impl Client {
+    /// Send a request to the daemon. `None` when no daemon is running or
+    /// this client talks to Logseq directly.
+    pub fn daemon_request(&self, method: &str, params: Value) -> Option<Result<Value, String>> {
+        let path = self.daemon.as_ref()?;
+        let stream = UnixStream::connect(path).ok()?;
+        debug!("Using daemon at {} for {}", path.display(), method);
+
+        Some(request(stream, method, params))
+    }
+}
+
+/// A uuid answered by the daemon.
+pub fn uuid_result(result: Result<Value, String>) -> Result<String, String> {
+    match result? {
+        Value::String(uuid) => Ok(uuid),
+        other => Err(format!("Unexpected daemon response: {}", other)),
+    }
+}
+
+fn request(mut stream: UnixStream, method: &str, params: Value) -> Result<Value, String> {
+    let request = json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params});
+    writeln!(stream, "{}", request).map_err(|err| format!("Failed to send to daemon: {}", err))?;
+
+    let mut line = String::new();
+    BufReader::new(stream)
+        .read_line(&mut line)
+        .map_err(|err| format!("Failed to read from daemon: {}", err))?;
+    let mut response: Value =
+        serde_json::from_str(&line).map_err(|err| format!("Invalid response from daemon: {}", err))?;
+
+    match response.get("error") {
+        Some(error) => Err(error
+            .get("message")
+            .and_then(|v| v.as_str())
+            .unwrap_or("Unknown daemon error")
+            .to_string()),
+        None => Ok(response.get_mut("result").map(Value::take).unwrap_or(Value::Null)),
+    }
+}
@@ -0,0 +1,297 @@
+use log::debug;
+use serde_json::json;
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+
+use crate::outline::{parse_outline, render_outline};
+use crate::model::{Block, BlockNode};
+use crate::Client;
+
+#[derive(Default)]
+struct EditSummary {
+    updated: usize,
+    inserted: usize,
+    removed: usize,
+}
+
+/// Pair up existing and edited siblings: the longest run of unchanged blocks
+/// is kept, blocks between two kept ones are matched up in order.
+/// Returns `(existing index, edited index)` pairs, either side may be missing.
+fn align(existing: &[&Block], edited: &[BlockNode]) -> Vec<(Option<usize>, Option<usize>)> {
+    let (n, m) = (existing.len(), edited.len());
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if existing[i].content == edited[j].content {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    fn flush_gap(pairs: &mut Vec<(Option<usize>, Option<usize>)>, existing: (usize, usize), edited: (usize, usize)) {
+        let len = (existing.1 - existing.0).max(edited.1 - edited.0);
+        for k in 0..len {
+            let a = (existing.0 + k < existing.1).then_some(existing.0 + k);
+            let b = (edited.0 + k < edited.1).then_some(edited.0 + k);
+            pairs.push((a, b));
+        }
+    }
+
+    let mut pairs = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    let (mut gap_i, mut gap_j) = (0, 0);
+
+    while i < n && j < m {
+        if existing[i].content == edited[j].content {
+            flush_gap(&mut pairs, (gap_i, i), (gap_j, j));
+            pairs.push((Some(i), Some(j)));
+            i += 1;
+            j += 1;
+            gap_i = i;
+            gap_j = j;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    flush_gap(&mut pairs, (gap_i, n), (gap_j, m));
+
+    pairs
+}
+
+fn open_editor(path: &Path) -> Result<(), String> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().ok_or("EDITOR is empty")?;
+
+    let status = Command::new(program)
+        .args(parts)
+        .arg(path)
+        .status()
+        .map_err(|err| format!("Failed to start {}: {}", editor, err))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("{} exited with {}", editor, status))
+    }
+}
+
+impl Client {
+    /// Open a block (and with `children` its subtree) in `$EDITOR` and write
+    /// back only the blocks that changed.
+    pub fn edit_block(&self, uuid: &str, children: bool) {
+        match self.edit_block_inner(uuid, children) {
+            Ok(None) => eprintln!("No changes."),
+            Ok(Some(summary)) => eprintln!(
+                "Updated {}, inserted {} and removed {} blocks.",
+                summary.updated, summary.inserted, summary.removed
+            ),
+            Err(err) => {
+                eprintln!("Failed to edit block: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    /// What the edit changed, or `None` when the file was saved unchanged.
+    fn edit_block_inner(&self, uuid: &str, children: bool) -> Result<Option<EditSummary>, String> {
+        let existing = self
+            .call_as::<Option<Block>>("logseq.Editor.getBlock", json!([uuid, {"includeChildren": children}]))?
+            .ok_or_else(|| format!("Block {} not found", uuid))?;
+
+        let original = if children {
+            render_outline(&[BlockNode::from(&existing)])
+        } else {
+            format!("{}\n", existing.content)
+        };
+
+        // A new file with an unpredictable name that only we can read; it is
+        // kept when applying the edit fails.
+        let (mut file, path) = tempfile::Builder::new()
+            .prefix("rlu-edit-")
+            .suffix(".md")
+            .tempfile()
+            .and_then(|file| file.keep().map_err(|err| err.error))
+            .map_err(|err| format!("Failed to create a temporary file: {}", err))?;
+        if let Err(err) = file.write_all(original.as_bytes()) {
+            let _ = fs::remove_file(&path);
+            return Err(format!("Failed to write {}: {}", path.display(), err));
+        }
+
+        open_editor(&path)?;
+        let edited = fs::read_to_string(&path).map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+
+        if edited == original {
+            let _ = fs::remove_file(&path);
+            return Ok(None);
+        }
+
+        let mut summary = EditSummary::default();
+        let result = if children {
+            let mut roots = parse_outline(&edited);
+            if roots.len() != 1 {
+                return Err(format!(
+                    "expected a single top-level block, found {}. Your edit is kept in {}",
+                    roots.len(),
+                    path.display()
+                ));
+            }
+            let root = roots.remove(0);
+            self.sync_block(&existing, &root, &mut summary)
+        } else {
+            let root = BlockNode { content: edited.trim_end().to_string(), children: Vec::new() };
+            self.sync_content(&existing, &root, &mut summary)
+        };
+        result.map_err(|err| format!("{}. Your edit is kept in {}", err, path.display()))?;
+
+        let _ = fs::remove_file(&path);
+        Ok(Some(summary))
+    }
+
+    fn sync_content(&self, existing: &Block, edited: &BlockNode, summary: &mut EditSummary) -> Result<(), String> {
+        if existing.content != edited.content {
+            debug!("Updating block {}", existing.uuid);
+            self.call("logseq.Editor.updateBlock", json!([existing.uuid, edited.content]))?;
+            summary.updated += 1;
+        }
+        Ok(())
+    }
+
+    fn sync_block(&self, existing: &Block, edited: &BlockNode, summary: &mut EditSummary) -> Result<(), String> {
+        self.sync_content(existing, edited, summary)?;
+
+        let existing_children: Vec<&Block> = existing.loaded_children().collect();
+        let pairs = align(&existing_children, &edited.children);
+
+        for (old, new) in &pairs {
+            if let (Some(old), None) = (old, new) {
+                let removed = existing_children[*old];
+                debug!("Removing block {}", removed.uuid);
+                self.call("logseq.Editor.removeBlock", json!([removed.uuid]))?;
+                summary.removed += count_nodes(&[BlockNode::from(removed)]);
+            }
+        }
+
+        // Blocks inserted before the first kept child go in front of it.
+        let first_kept = pairs
+            .iter()
+            .find_map(|(old, new)| old.zip(*new).map(|(old, _)| existing_children[old].uuid.clone()));
+        let mut previous: Option<String> = None;
+
+        for (old, new) in &pairs {
+            match (old, new) {
+                (Some(old), Some(new)) => {
+                    let child = existing_children[*old];
+                    self.sync_block(child, &edited.children[*new], summary)?;
+                    previous = Some(child.uuid.clone());
+                }
+                (None, Some(new)) => {
+                    let node = &edited.children[*new];
+                    let inserted: Block = match (&previous, &first_kept) {
+                        (Some(previous), _) => self.call_as(
+                            "logseq.Editor.insertBlock",
+                            json!([previous, node.content, {"sibling": true}]),
+                        )?,
+                        (None, Some(next)) => self.call_as(
+                            "logseq.Editor.insertBlock",
+                            json!([next, node.content, {"sibling": true, "before": true}]),
+                        )?,
+                        (None, None) => self.call_as(
+                            "logseq.Editor.insertBlock",
+                            json!([existing.uuid, node.content, {"sibling": false}]),
+                        )?,
+                    };
+                    self.insert_block_tree(&inserted.uuid, &node.children, false)?;
+                    summary.inserted += 1 + count_nodes(&node.children);
+                    previous = Some(inserted.uuid);
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn count_nodes(nodes: &[BlockNode]) -> usize {
+    nodes.iter().map(|node| 1 + count_nodes(&node.children)).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blocks(contents: &[&str]) -> Vec<Block> {
+        contents
+            .iter()
+            .map(|content| Block { content: content.to_string(), ..Block::default() })
+            .collect()
+    }
+
+    fn nodes(contents: &[&str]) -> Vec<BlockNode> {
+        contents
+            .iter()
+            .map(|content| BlockNode { content: content.to_string(), children: Vec::new() })
+            .collect()
+    }
+
+    fn aligned(existing: &[&str], edited: &[&str]) -> Vec<(Option<usize>, Option<usize>)> {
+        let existing = blocks(existing);
+        let existing: Vec<&Block> = existing.iter().collect();
+        align(&existing, &nodes(edited))
+    }
+
+    #[test]
+    fn keeps_unchanged_blocks() {
+        assert_eq!(aligned(&["a", "b"], &["a", "b"]), vec![(Some(0), Some(0)), (Some(1), Some(1))]);
+    }
+
+    #[test]
+    fn pairs_changed_blocks_in_order() {
+        assert_eq!(
+            aligned(&["a", "b", "c"], &["a", "B", "c"]),
+            vec![(Some(0), Some(0)), (Some(1), Some(1)), (Some(2), Some(2))]
+        );
+    }
+
+    #[test]
+    fn finds_inserted_blocks() {
+        assert_eq!(
+            aligned(&["a", "c"], &["new", "a", "b", "c"]),
+            vec![(None, Some(0)), (Some(0), Some(1)), (None, Some(2)), (Some(1), Some(3))]
+        );
+    }
+
+    #[test]
+    fn finds_deleted_blocks() {
+        assert_eq!(
+            aligned(&["a", "b", "c", "d"], &["b", "d"]),
+            vec![(Some(0), None), (Some(1), Some(0)), (Some(2), None), (Some(3), Some(1))]
+        );
+    }
+
+    #[test]
+    fn reordered_blocks_are_removed_and_inserted() {
+        // The longest unchanged run stays, the moved block is written anew.
+        assert_eq!(
+            aligned(&["a", "b", "c"], &["c", "a", "b"]),
+            vec![(None, Some(0)), (Some(0), Some(1)), (Some(1), Some(2)), (Some(2), None)]
+        );
+    }
+
+    #[test]
+    fn handles_empty_sides() {
+        assert_eq!(aligned(&[], &["a"]), vec![(None, Some(0))]);
+        assert_eq!(aligned(&["a"], &[]), vec![(Some(0), None)]);
+        assert!(aligned(&[], &[]).is_empty());
+    }
+}
@@ -0,0 +1,507 @@
+//! `rlu gateway`: a small REST server in front of the Logseq API that hands
+//! out its own tokens, so scripts get only the access they need instead of
+//! `LOGSEQ_API_KEY`.
+//!
+//! Clients are configured in TOML:
+//!
+//! ```toml
+//! [[clients]]
+//! name = "ci"
+//! token = "long random string"
+//! permissions = ["append"]      # "read" and/or "append"
+//! pages = ["Builds"]            # optional allowlist of pages
+//! journals = true               # whether journal pages are allowed (default true)
+//! ```
+//!
+//! Every request is appended to the audit log as one JSON line.
+
+use chrono::{Local, NaiveDate};
+use log::debug;
+use percent_encoding::percent_decode_str;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::model::{Block, Page};
+use crate::outline::parse_outline;
+use crate::serve::block_json;
+use crate::{journal, Client};
+
+/// `~/.rlu/gateway.toml`.
+pub fn default_config_path() -> PathBuf {
+    PathBuf::from(env::var("HOME").unwrap_or_default()).join(".rlu").join("gateway.toml")
+}
+
+/// `~/.rlu/gateway-audit.log`.
+pub fn default_audit_log_path() -> PathBuf {
+    PathBuf::from(env::var("HOME").unwrap_or_default()).join(".rlu").join("gateway-audit.log")
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Permission {
+    Read,
+    Append,
+}
+
+impl Permission {
+    fn as_str(self) -> &'static str {
+        match self {
+            Permission::Read => "read",
+            Permission::Append => "append",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GatewayClient {
+    name: String,
+    token: String,
+    permissions: Vec<Permission>,
+    /// Page names this client may use; all pages when unset.
+    pages: Option<Vec<String>>,
+    #[serde(default = "default_journals")]
+    journals: bool,
+}
+
+fn default_journals() -> bool {
+    true
+}
+
+impl GatewayClient {
+    fn may(&self, permission: Permission) -> Result<(), Failure> {
+        if self.permissions.contains(&permission) {
+            Ok(())
+        } else {
+            Err((403, format!("Token '{}' may not {}", self.name, permission.as_str())))
+        }
+    }
+
+    fn may_use_page(&self, page: &Page) -> bool {
+        if page.journal {
+            return self.journals;
+        }
+        self.may_use_page_name(page.display_name())
+    }
+
+    fn may_use_page_name(&self, name: &str) -> bool {
+        match &self.pages {
+            Some(pages) => pages.iter().any(|allowed| allowed.to_lowercase() == name.to_lowercase()),
+            None => true,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct GatewayConfig {
+    #[serde(default)]
+    clients: Vec<GatewayClient>,
+}
+
+fn load_config(path: &Path) -> Result<GatewayConfig, String> {
+    let text = fs::read_to_string(path).map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+    let config: GatewayConfig = toml::from_str(&text).map_err(|err| format!("Invalid {}: {}", path.display(), err))?;
+    if config.clients.is_empty() {
+        return Err(format!("{} defines no [[clients]]", path.display()));
+    }
+    for client in &config.clients {
+        if client.token.len() < 16 {
+            return Err(format!("The token of '{}' is shorter than 16 characters", client.name));
+        }
+    }
+    Ok(config)
+}
+
+/// Compare tokens without returning early, so response times do not tell
+/// how much of a guess was right.
+pub fn tokens_match(given: &str, expected: &str) -> bool {
+    given.len() == expected.len() && given.bytes().zip(expected.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// An HTTP status and the message sent with it.
+type Failure = (u16, String);
+
+fn logseq_failure(err: String) -> Failure {
+    (502, err)
+}
+
+pub fn decode(segment: &str) -> String {
+    percent_decode_str(segment).decode_utf8_lossy().into_owned()
+}
+
+/// Larger request bodies are refused with 413.
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+
+/// The body of a request as text, at most `MAX_BODY_BYTES` of it.
+pub fn read_body_text(request: &mut Request) -> Result<String, Failure> {
+    let too_large = || (413, format!("Body is larger than {} bytes", MAX_BODY_BYTES));
+    if request.body_length().is_some_and(|length| length > MAX_BODY_BYTES) {
+        return Err(too_large());
+    }
+    // The length header is optional, so stop reading one byte past the limit.
+    let mut body = String::new();
+    request
+        .as_reader()
+        .take(MAX_BODY_BYTES as u64 + 1)
+        .read_to_string(&mut body)
+        .map_err(|err| (400, format!("Failed to read body: {}", err)))?;
+    if body.len() > MAX_BODY_BYTES {
+        return Err(too_large());
+    }
+    Ok(body)
+}
+
+/// The body of a POST as `{"content": …, "date": …}`.
+fn read_body(request: &mut Request) -> Result<Value, Failure> {
+    let body = read_body_text(request)?;
+    serde_json::from_str(&body).map_err(|err| (400, format!("Body is not JSON: {}", err)))
+}
+
+fn body_content(body: &Value) -> Result<&str, Failure> {
+    body.get("content")
+        .and_then(|v| v.as_str())
+        .filter(|content| !content.trim().is_empty())
+        .ok_or_else(|| (400, "Body needs a non-empty \"content\"".to_string()))
+}
+
+struct Gateway {
+    client: Client,
+    config: GatewayConfig,
+    audit_log: PathBuf,
+}
+
+impl Gateway {
+    fn authenticate(&self, request: &Request) -> Option<&GatewayClient> {
+        let header = request.headers().iter().find(|header| header.field.equiv("Authorization"))?;
+        let token = header.value.as_str().strip_prefix("Bearer ")?.trim();
+        self.config.clients.iter().find(|client| tokens_match(token, &client.token))
+    }
+
+    /// The block and the page it is on, if the client may use that page.
+    fn allowed_block(&self, caller: &GatewayClient, uuid: &str, children: bool) -> Result<Block, Failure> {
+        let not_found = || (404, format!("Block {} not found", uuid));
+        let block = self
+            .client
+            .call_as::<Option<Block>>("logseq.Editor.getBlock", json!([uuid, {"includeChildren": children}]))
+            .map_err(logseq_failure)?
+            .ok_or_else(not_found)?;
+        let page_id = block.page.as_ref().and_then(|page| page.id).ok_or_else(not_found)?;
+        let page = self
+            .client
+            .call_as::<Option<Page>>("logseq.Editor.getPage", json!([page_id]))
+            .map_err(logseq_failure)?
+            .ok_or_else(not_found)?;
+        // Blocks on pages the client may not use look the same as missing
+        // ones.
+        if !caller.may_use_page(&page) {
+            return Err(not_found());
+        }
+        Ok(block)
+    }
+
+    fn route(&self, caller: &GatewayClient, request: &mut Request) -> Result<(u16, Value), Failure> {
+        let url = request.url().to_string();
+        let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+        let method = request.method().clone();
+
+        match (&method, segments.as_slice()) {
+            (Method::Get, ["pages", name]) => {
+                caller.may(Permission::Read)?;
+                let name = decode(name);
+                let not_found = || (404, format!("Page {} not found", name));
+                let page = self
+                    .client
+                    .call_as::<Option<Page>>("logseq.Editor.getPage", json!([name]))
+                    .map_err(logseq_failure)?
+                    .ok_or_else(not_found)?;
+                if !caller.may_use_page(&page) {
+                    return Err(not_found());
+                }
+                let blocks = self.client.page_blocks(&name).map_err(logseq_failure)?;
+                Ok((
+                    200,
+                    json!({
+                        "name": page.display_name(),
+                        "uuid": page.uuid,
+                        "blocks": blocks.iter().map(block_json).collect::<Vec<_>>(),
+                    }),
+                ))
+            }
+            (Method::Get, ["blocks", uuid]) => {
+                caller.may(Permission::Read)?;
+                let mut block = self.allowed_block(caller, &decode(uuid), true)?;
+                self.client.load_children(&mut block).map_err(logseq_failure)?;
+                Ok((200, block_json(&block)))
+            }
+            (Method::Get, ["search"]) => {
+                caller.may(Permission::Read)?;
+                let text = query
+                    .split('&')
+                    .find_map(|pair| pair.strip_prefix("q="))
+                    .map(|text| decode(&text.replace('+', " ")))
+                    .filter(|text| !text.is_empty())
+                    .ok_or_else(|| (400, "Missing ?q=".to_string()))?;
+                let blocks = self.client.search_blocks(&text).map_err(logseq_failure)?;
+                let results: Vec<Value> = blocks
+                    .iter()
+                    .filter(|block| {
+                        block.page.as_ref().is_some_and(|page| match page.journal_day {
+                            Some(_) => caller.journals,
+                            None => caller.may_use_page_name(page.display_name()),
+                        })
+                    })
+                    .map(|block| {
+                        json!({
+                            "uuid": block.uuid,
+                            "page": block.page.as_ref().map(|page| page.display_name()),
+                            "content": block.content,
+                        })
+                    })
+                    .collect();
+                Ok((200, json!(results)))
+            }
+            (Method::Post, ["journal"]) => {
+                caller.may(Permission::Append)?;
+                if !caller.journals {
+                    return Err((403, format!("Token '{}' may not use journal pages", caller.name)));
+                }
+                let body = read_body(request)?;
+                let date = match body.get("date").and_then(|v| v.as_str()) {
+                    Some(date) => journal::parse_date(date).map_err(|err| (400, err))?,
+                    None => Local::now().date_naive(),
+                };
+                let uuid = self.append_to_journal(date, body_content(&body)?).map_err(logseq_failure)?;
+                Ok((201, json!({"uuid": uuid, "date": date.to_string()})))
+            }
+            (Method::Post, ["pages", name]) => {
+                caller.may(Permission::Append)?;
+                let name = decode(name);
+                let existing = self
+                    .client
+                    .call_as::<Option<Page>>("logseq.Editor.getPage", json!([name]))
+                    .map_err(logseq_failure)?;
+                let allowed = match &existing {
+                    Some(page) => caller.may_use_page(page),
+                    None => caller.may_use_page_name(&name),
+                };
+                if !allowed {
+                    return Err((403, format!("Token '{}' may not use page {}", caller.name, name)));
+                }
+                let body = read_body(request)?;
+                let content = body_content(&body)?;
+                let page = self.client.get_or_create_page(&name).map_err(logseq_failure)?;
+                let uuid = self.client.insert_note(&page, content).map_err(logseq_failure)?;
+                Ok((201, json!({"uuid": uuid, "page": name})))
+            }
+            (Method::Post, ["blocks", uuid, "children"]) => {
+                caller.may(Permission::Append)?;
+                let uuid = decode(uuid);
+                self.allowed_block(caller, &uuid, false)?;
+                let body = read_body(request)?;
+                let nodes = parse_outline(body_content(&body)?);
+                self.client.insert_block_tree(&uuid, &nodes, false).map_err(logseq_failure)?;
+                Ok((201, json!({"parent": uuid, "blocks": nodes.len()})))
+            }
+            (_, ["pages", _]) | (_, ["blocks", _]) | (_, ["blocks", _, "children"]) | (_, ["journal"]) | (_, ["search"]) => {
+                Err((405, format!("{} is not allowed on {}", method, path)))
+            }
+            _ => Err((404, format!("No route for {}", path))),
+        }
+    }
+
+    fn append_to_journal(&self, date: NaiveDate, content: &str) -> Result<Option<String>, String> {
+        let page = self.client.journal_page_uuid(date)?;
+        self.client.insert_note(&page, content)
+    }
+
+    fn audit(&self, caller: Option<&str>, request: &Request, status: u16, detail: &str) {
+        let entry = json!({
+            "time": Local::now().to_rfc3339(),
+            "client": caller,
+            "remote": request.remote_addr().map(|addr| addr.to_string()),
+            "method": request.method().as_str(),
+            "path": request.url(),
+            "status": status,
+            "detail": detail,
+        });
+        let written = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.audit_log)
+            .and_then(|mut file| writeln!(file, "{}", entry));
+        if let Err(err) = written {
+            eprintln!("Failed to write {}: {}", self.audit_log.display(), err);
+        }
+    }
+
+    fn handle(&self, mut request: Request) {
+        let caller = self.authenticate(&request);
+        let result = match caller {
+            Some(caller) => self.route(caller, &mut request),
+            None => Err((401, "Missing or unknown bearer token".to_string())),
+        };
+
+        let (status, body, detail) = match result {
+            Ok((status, body)) => (status, body, String::new()),
+            Err((status, message)) => (status, json!({"error": message}), message),
+        };
+        debug!("{} {} -> {}", request.method(), request.url(), status);
+        self.audit(caller.map(|caller| caller.name.as_str()), &request, status, &detail);
+
+        let content_type = Header::from_bytes("Content-Type", "application/json").expect("valid header");
+        let response = Response::from_string(body.to_string())
+            .with_status_code(status)
+            .with_header(content_type);
+        if let Err(err) = request.respond(response) {
+            debug!("Failed to respond: {}", err);
+        }
+    }
+}
+
+pub fn run(client: Client, listen: &str, config: &Path, audit_log: &Path) -> Result<(), String> {
+    let config = load_config(config)?;
+    if let Some(dir) = audit_log.parent() {
+        fs::create_dir_all(dir).map_err(|err| format!("Failed to create {}: {}", dir.display(), err))?;
+    }
+
+    let server = Server::http(listen).map_err(|err| format!("Failed to listen on {}: {}", listen, err))?;
+    eprintln!(
+        "Serving {} clients on http://{}, auditing to {}.",
+        config.clients.len(),
+        listen,
+        audit_log.display()
+    );
+
+    let gateway = Gateway { client, config, audit_log: audit_log.to_path_buf() };
+    for request in server.incoming_requests() {
+        gateway.handle(request);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tiny_http::TestRequest;
+
+    const CONFIG: &str = r#"
+        [[clients]]
+        name = "ci"
+        token = "0123456789abcdef"
+        permissions = ["append"]
+        pages = ["Builds"]
+        journals = false
+
+        [[clients]]
+        name = "reader"
+        token = "fedcba9876543210"
+        permissions = ["read"]
+    "#;
+
+    fn config(text: &str) -> Result<GatewayConfig, String> {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+        load_config(file.path())
+    }
+
+    fn config_error(text: &str) -> String {
+        config(text).err().expect("the config is refused")
+    }
+
+    fn gateway() -> Gateway {
+        Gateway { client: Client::direct(), config: config(CONFIG).unwrap(), audit_log: PathBuf::new() }
+    }
+
+    fn page(name: &str, journal: bool) -> Page {
+        Page { original_name: Some(name.to_string()), journal, ..Page::default() }
+    }
+
+    /// The status a request from client `index` gets, for requests that are
+    /// refused before Logseq is asked anything.
+    fn status(gateway: &Gateway, index: usize, method: Method, path: &str) -> u16 {
+        let mut request: Request = TestRequest::new().with_method(method).with_path(path).into();
+        match gateway.route(&gateway.config.clients[index], &mut request) {
+            Ok((status, _)) => status,
+            Err((status, _)) => status,
+        }
+    }
+
+    #[test]
+    fn matches_tokens_exactly() {
+        assert!(tokens_match("0123456789abcdef", "0123456789abcdef"));
+        assert!(!tokens_match("0123456789abcdeF", "0123456789abcdef"));
+        assert!(!tokens_match("0123456789abcde", "0123456789abcdef"));
+        assert!(!tokens_match("", "0123456789abcdef"));
+    }
+
+    #[test]
+    fn authenticates_bearer_tokens() {
+        let gateway = gateway();
+        let bearer = |value: &str| -> Request {
+            TestRequest::new().with_header(Header::from_bytes("Authorization", value).unwrap()).into()
+        };
+        assert_eq!(gateway.authenticate(&bearer("Bearer fedcba9876543210")).map(|c| c.name.as_str()), Some("reader"));
+        assert!(gateway.authenticate(&bearer("fedcba9876543210")).is_none());
+        assert!(gateway.authenticate(&bearer("Bearer fedcba987654321")).is_none());
+        assert!(gateway.authenticate(&TestRequest::new().into()).is_none());
+    }
+
+    #[test]
+    fn limits_pages_to_the_allowlist() {
+        let gateway = gateway();
+        let (ci, reader) = (&gateway.config.clients[0], &gateway.config.clients[1]);
+        assert!(ci.may_use_page_name("Builds"));
+        assert!(ci.may_use_page_name("builds"));
+        assert!(!ci.may_use_page_name("Deploys"));
+        assert!(!ci.may_use_page_name("Builds/Nightly"));
+        assert!(!ci.may_use_page_name("Work/Builds"));
+        assert!(ci.may_use_page(&page("BUILDS", false)));
+        assert!(!ci.may_use_page(&page("May 22nd, 2024", true)));
+
+        assert!(reader.may_use_page_name("Builds/Nightly"));
+        assert!(reader.may_use_page(&page("May 22nd, 2024", true)));
+    }
+
+    #[test]
+    fn validates_the_config() {
+        assert_eq!(config(CONFIG).unwrap().clients.len(), 2);
+        assert!(config_error("").contains("defines no [[clients]]"));
+        let short = "[[clients]]\nname = \"x\"\ntoken = \"short\"\npermissions = [\"read\"]";
+        assert!(config_error(short).contains("shorter than 16"));
+        let unknown = "[[clients]]\nname = \"x\"\ntoken = \"0123456789abcdef\"\npermissions = [\"read\"]\npage = [\"A\"]";
+        assert!(config_error(unknown).contains("unknown field"));
+        let permission = "[[clients]]\nname = \"x\"\ntoken = \"0123456789abcdef\"\npermissions = [\"write\"]";
+        assert!(config(permission).is_err());
+        assert!(load_config(Path::new("/nonexistent/gateway.toml")).is_err_and(|err| err.starts_with("Failed to read")));
+    }
+
+    #[test]
+    fn checks_permissions_per_route() {
+        let gateway = gateway();
+        // `ci` may only append, and not to journals.
+        assert_eq!(status(&gateway, 0, Method::Get, "/pages/Builds"), 403);
+        assert_eq!(status(&gateway, 0, Method::Get, "/blocks/b1"), 403);
+        assert_eq!(status(&gateway, 0, Method::Get, "/search?q=x"), 403);
+        assert_eq!(status(&gateway, 0, Method::Post, "/journal"), 403);
+        // `reader` may not append anywhere.
+        assert_eq!(status(&gateway, 1, Method::Post, "/journal"), 403);
+        assert_eq!(status(&gateway, 1, Method::Post, "/pages/Builds"), 403);
+        assert_eq!(status(&gateway, 1, Method::Post, "/blocks/b1/children"), 403);
+        assert_eq!(status(&gateway, 1, Method::Get, "/search"), 400);
+    }
+
+    #[test]
+    fn answers_unknown_routes_and_methods() {
+        let gateway = gateway();
+        assert_eq!(status(&gateway, 1, Method::Delete, "/pages/Builds"), 405);
+        assert_eq!(status(&gateway, 1, Method::Put, "/journal"), 405);
+        assert_eq!(status(&gateway, 1, Method::Get, "/graphs"), 404);
+        assert_eq!(status(&gateway, 1, Method::Get, "/pages/a/b"), 404);
+    }
+}
@@ -0,0 +1,392 @@
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use ical::parser::ical::component::IcalEvent;
+use ical::IcalParser;
+use log::debug;
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::BufReader;
+
+use crate::Client;
+
+/// The property imported blocks are de-duplicated by.
+const SOURCE_ID_PROPERTY: &str = "source-id";
+
+/// A block to create on the journal page of `date`.
+struct ImportEntry {
+    date: NaiveDate,
+    content: String,
+    source_id: String,
+    properties: Vec<(String, String)>,
+}
+
+impl ImportEntry {
+    fn block_content(&self) -> String {
+        let mut content = self.content.clone();
+        for (key, value) in &self.properties {
+            content.push_str(&format!("\n{}:: {}", key, value));
+        }
+        content.push_str(&format!("\n{}:: {}", SOURCE_ID_PROPERTY, self.source_id));
+        content
+    }
+}
+
+/// FNV-1a, used to derive a stable source id for entries without one.
+pub fn fingerprint(parts: &[&str]) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in parts.join("\u{1f}").bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+/// Undo iCalendar text escaping.
+fn ics_unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => unescaped.push('\n'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+/// Local date and, unless it is an all-day value, time of a `DTSTART`.
+/// UTC times are converted to local time, other time zones are taken as is;
+/// `event_entry` warns about those.
+fn ics_start(value: &str) -> Option<(NaiveDate, Option<NaiveTime>)> {
+    if let Some(utc) = value.strip_suffix('Z') {
+        let time = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").ok()?;
+        let local: DateTime<Local> = DateTime::<Utc>::from_naive_utc_and_offset(time, Utc).into();
+        return Some((local.date_naive(), Some(local.time())));
+    }
+    if let Ok(time) = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S") {
+        return Some((time.date(), Some(time.time())));
+    }
+    NaiveDate::parse_from_str(value, "%Y%m%d").ok().map(|date| (date, None))
+}
+
+/// The `TZID` parameter of an event's `DTSTART`.
+fn start_time_zone(event: &IcalEvent) -> Option<&str> {
+    let start = event.properties.iter().find(|property| property.name == "DTSTART")?;
+    let (_, values) = start.params.as_ref()?.iter().find(|(name, _)| name == "TZID")?;
+    values.first().map(String::as_str)
+}
+
+fn event_entry(event: &IcalEvent) -> Option<ImportEntry> {
+    let property = |name: &str| {
+        event
+            .properties
+            .iter()
+            .find(|property| property.name == name)
+            .and_then(|property| property.value.as_deref())
+    };
+
+    let start = property("DTSTART")?;
+    let (date, time) = ics_start(start)?;
+    let summary = ics_unescape(property("SUMMARY").unwrap_or("(no title)"));
+    if let (Some(zone), Some(time)) = (start_time_zone(event), time) {
+        eprintln!(
+            "Warning: {}: the time zone {} is not converted, {} is taken as local time.",
+            summary,
+            zone,
+            time.format("%H:%M")
+        );
+    }
+    if property("RRULE").is_some() {
+        eprintln!("Warning: {}: repeats, only the first occurrence on {} is imported.", summary, date);
+    }
+    let content = match time {
+        Some(time) => format!("{} {}", time.format("%H:%M"), summary),
+        None => summary.clone(),
+    };
+
+    let source_id = match (property("UID"), property("RECURRENCE-ID")) {
+        (Some(uid), Some(recurrence)) => format!("{}/{}", uid, recurrence),
+        (Some(uid), None) => uid.to_string(),
+        (None, _) => fingerprint(&[start, &summary]),
+    };
+
+    let mut properties = Vec::new();
+    if let Some(location) = property("LOCATION").map(ics_unescape).filter(|location| !location.is_empty()) {
+        properties.push(("location".to_string(), location.replace('\n', " ")));
+    }
+
+    Some(ImportEntry { date, content, source_id, properties })
+}
+
+fn read_ics(path: &str) -> Result<Vec<ImportEntry>, String> {
+    let file = File::open(path).map_err(|err| format!("Failed to open {}: {}", path, err))?;
+    let mut entries = Vec::new();
+    for calendar in IcalParser::new(BufReader::new(file)) {
+        let calendar = calendar.map_err(|err| format!("Failed to parse {}: {}", path, err))?;
+        for event in &calendar.events {
+            match event_entry(event) {
+                Some(entry) => entries.push(entry),
+                None => eprintln!("Skipping an event without a usable DTSTART."),
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Fill `{Column}` placeholders in `template` from a CSV row.
+fn fill_columns(template: &str, headers: &csv::StringRecord, row: &csv::StringRecord) -> String {
+    let mut filled = template.to_string();
+    for (header, value) in headers.iter().zip(row.iter()) {
+        filled = filled.replace(&format!("{{{}}}", header), value);
+    }
+    filled
+}
+
+/// A `--map` value is a column name, or a template with `{Column}`
+/// placeholders when it contains braces.
+fn mapped_value(mapping: &str, headers: &csv::StringRecord, row: &csv::StringRecord) -> Option<String> {
+    if mapping.contains('{') {
+        return Some(fill_columns(mapping, headers, row));
+    }
+    let index = headers.iter().position(|header| header == mapping)?;
+    row.get(index).map(str::to_string)
+}
+
+fn parse_csv_date(value: &str, format: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(value.trim(), format)
+        .or_else(|_| NaiveDateTime::parse_from_str(value.trim(), format).map(|time| time.date()))
+        .ok()
+}
+
+fn read_csv(path: &str, map: &[(String, String)], date_format: &str, delimiter: u8) -> Result<Vec<ImportEntry>, String> {
+    let mapping: HashMap<&str, &str> = map.iter().map(|(key, value)| (key.as_str(), value.as_str())).collect();
+    for required in ["date", "content"] {
+        if !mapping.contains_key(required) {
+            return Err(format!("--map {}=<column> is required", required));
+        }
+    }
+
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .from_path(path)
+        .map_err(|err| format!("Failed to open {}: {}", path, err))?;
+    let headers = reader.headers().map_err(|err| format!("Failed to read CSV header: {}", err))?.clone();
+
+    for (key, column) in map {
+        if !column.contains('{') && !headers.iter().any(|header| header == column) {
+            return Err(format!("--map {}: no column named '{}'", key, column));
+        }
+    }
+
+    let mut entries = Vec::new();
+    for (line, row) in reader.records().enumerate() {
+        // Line 1 is the header.
+        let line = line + 2;
+        let row = row.map_err(|err| format!("Failed to read line {}: {}", line, err))?;
+
+        let raw_date = mapped_value(mapping["date"], &headers, &row).unwrap_or_default();
+        let Some(date) = parse_csv_date(&raw_date, date_format) else {
+            eprintln!("Skipping line {}: '{}' does not match the date format {}", line, raw_date, date_format);
+            continue;
+        };
+        let content = mapped_value(mapping["content"], &headers, &row).unwrap_or_default();
+
+        let source_id = match mapping.get("id").and_then(|column| mapped_value(column, &headers, &row)) {
+            Some(id) if !id.is_empty() => id,
+            _ => format!("csv-{}", fingerprint(&row.iter().collect::<Vec<_>>())),
+        };
+
+        let properties = map
+            .iter()
+            .filter(|(key, _)| !matches!(key.as_str(), "date" | "content" | "id"))
+            .filter_map(|(key, column)| Some((key.clone(), mapped_value(column, &headers, &row)?)))
+            .filter(|(_, value)| !value.is_empty())
+            .collect();
+
+        entries.push(ImportEntry { date, content, source_id, properties });
+    }
+    Ok(entries)
+}
+
+impl Client {
+    /// Values of the `property::` property of all blocks, e.g. the
+    /// `source-id` imported blocks are de-duplicated by.
+    pub fn property_values(&self, property: &str) -> Result<HashSet<String>, String> {
+        let query = format!(
+            "[:find ?value
+             :where
+             [?b :block/properties ?props]
+             [(get ?props :{}) ?value]]",
+            property
+        );
+        let rows = self.datascript_query(&query, &[])?;
+
+        let mut values = HashSet::new();
+        for row in rows {
+            match row.get(0) {
+                Some(serde_json::Value::String(value)) => {
+                    values.insert(value.clone());
+                }
+                // Values Logseq read as page references come back as a set.
+                Some(serde_json::Value::Array(items)) => {
+                    values.extend(items.iter().filter_map(|v| v.as_str()).map(str::to_string));
+                }
+                Some(other) => {
+                    values.insert(other.to_string());
+                }
+                None => {}
+            }
+        }
+        Ok(values)
+    }
+
+    /// Create a block per entry on its journal day, skipping entries whose
+    /// source id is already in the graph.
+    fn import_entries(&self, entries: Vec<ImportEntry>) -> Result<(), String> {
+        let mut seen = self.property_values(SOURCE_ID_PROPERTY)?;
+        let mut journals: HashMap<NaiveDate, String> = HashMap::new();
+        let (mut imported, mut skipped) = (0, 0);
+
+        for entry in entries {
+            if !seen.insert(entry.source_id.clone()) {
+                debug!("Already imported: {}", entry.source_id);
+                skipped += 1;
+                continue;
+            }
+
+            let page = match journals.get(&entry.date) {
+                Some(page) => page.clone(),
+                None => {
+                    let page = self.journal_page_uuid(entry.date)?;
+                    journals.insert(entry.date, page.clone());
+                    page
+                }
+            };
+            self.call("logseq.Editor.insertBlock", json!([page, entry.block_content(), {"isPageBlock": true}]))?;
+            debug!("Imported {} into {}", entry.source_id, entry.date);
+            imported += 1;
+        }
+
+        eprintln!("Imported {} entries, skipped {} already imported.", imported, skipped);
+        Ok(())
+    }
+
+    pub fn import_ics(&self, path: &str) {
+        if let Err(err) = read_ics(path).and_then(|entries| self.import_entries(entries)) {
+            eprintln!("Import failed: {}", err);
+            std::process::exit(1);
+        }
+    }
+
+    pub fn import_csv(&self, path: &str, map: &[(String, String)], date_format: &str, delimiter: char) {
+        // `u8::try_from` would also let Latin-1 characters through.
+        let delimiter = if delimiter.is_ascii() {
+            Ok(delimiter as u8)
+        } else {
+            Err(format!("The delimiter '{}' is not an ASCII character", delimiter))
+        };
+        let result = delimiter
+            .and_then(|delimiter| read_csv(path, map, date_format, delimiter))
+            .and_then(|entries| self.import_entries(entries));
+        if let Err(err) = result {
+            eprintln!("Import failed: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ical::property::Property;
+
+    fn record(fields: &[&str]) -> csv::StringRecord {
+        csv::StringRecord::from(fields.to_vec())
+    }
+
+    /// An event from `NAME;PARAM=value` and value pairs.
+    fn event(properties: &[(&str, &str)]) -> IcalEvent {
+        let mut event = IcalEvent::new();
+        for (name, value) in properties {
+            let (name, param) = name.split_once(';').unwrap_or((name, ""));
+            event.properties.push(Property {
+                name: name.to_string(),
+                params: param.split_once('=').map(|(key, value)| vec![(key.to_string(), vec![value.to_string()])]),
+                value: Some(value.to_string()),
+            });
+        }
+        event
+    }
+
+    #[test]
+    fn reads_event_starts() {
+        assert_eq!(ics_start("20240522"), Some((NaiveDate::from_ymd_opt(2024, 5, 22).unwrap(), None)));
+        assert_eq!(
+            ics_start("20240522T140000"),
+            Some((NaiveDate::from_ymd_opt(2024, 5, 22).unwrap(), NaiveTime::from_hms_opt(14, 0, 0)))
+        );
+        let utc = NaiveDate::from_ymd_opt(2024, 5, 22).unwrap().and_hms_opt(14, 0, 0).unwrap();
+        let local: DateTime<Local> = DateTime::<Utc>::from_naive_utc_and_offset(utc, Utc).into();
+        assert_eq!(ics_start("20240522T140000Z"), Some((local.date_naive(), Some(local.time()))));
+        assert_eq!(ics_start("May 22"), None);
+    }
+
+    #[test]
+    fn unescapes_ics_text() {
+        assert_eq!(ics_unescape(r"a\, b\; c\\d\nnext\N"), "a, b; c\\d\nnext\n");
+        assert_eq!(ics_unescape(r"trailing\"), "trailing\\");
+    }
+
+    #[test]
+    fn builds_entries_from_events() {
+        let entry = event_entry(&event(&[
+            ("DTSTART;TZID=Europe/Berlin", "20240522T090000"),
+            ("SUMMARY", r"Review\, weekly"),
+            ("UID", "abc"),
+            ("RRULE", "FREQ=WEEKLY"),
+            ("LOCATION", "Room 1"),
+        ]))
+        .unwrap();
+        assert_eq!(entry.content, "09:00 Review, weekly");
+        assert_eq!(entry.source_id, "abc");
+        assert_eq!(entry.properties, [("location".to_string(), "Room 1".to_string())]);
+        assert!(event_entry(&event(&[("SUMMARY", "no start")])).is_none());
+    }
+
+    #[test]
+    fn finds_the_start_time_zone() {
+        let zoned = event(&[("DTSTART;TZID=Europe/Berlin", "20240522T090000")]);
+        assert_eq!(start_time_zone(&zoned), Some("Europe/Berlin"));
+        assert_eq!(start_time_zone(&event(&[("DTSTART", "20240522T090000Z")])), None);
+    }
+
+    #[test]
+    fn fills_columns() {
+        let headers = record(&["Task", "Hours"]);
+        let row = record(&["Write docs", "2"]);
+        assert_eq!(fill_columns("{Task} ({Hours}h) {Missing}", &headers, &row), "Write docs (2h) {Missing}");
+        assert_eq!(mapped_value("Hours", &headers, &row).as_deref(), Some("2"));
+        assert_eq!(mapped_value("Nope", &headers, &row), None);
+    }
+
+    #[test]
+    fn parses_csv_dates() {
+        let date = NaiveDate::from_ymd_opt(2024, 5, 22);
+        assert_eq!(parse_csv_date(" 2024-05-22 ", "%Y-%m-%d"), date);
+        assert_eq!(parse_csv_date("22/05/2024 14:30", "%d/%m/%Y %H:%M"), date);
+        assert_eq!(parse_csv_date("2024-05-22", "%d/%m/%Y"), None);
+    }
+
+    #[test]
+    fn fingerprints_are_stable_and_separate_parts() {
+        assert_eq!(fingerprint(&[]), "cbf29ce484222325");
+        assert_eq!(fingerprint(&["a", "b"]), fingerprint(&["a", "b"]));
+        assert_ne!(fingerprint(&["ab", ""]), fingerprint(&["a", "b"]));
+        assert_eq!(fingerprint(&["x"]).len(), 16);
+    }
+}
@@ -0,0 +1,183 @@
+use chrono::{Datelike, NaiveDate};
+use log::debug;
+use serde_json::json;
+
+use crate::model::Page;
+use crate::{daemon, Client};
+
+/// Logseq's default `:journal/page-title-format`.
+const DEFAULT_TITLE_FORMAT: &str = "MMM do, yyyy";
+
+pub fn parse_date(date: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|err| format!("Invalid date format '{}', expected YYYY-MM-DD: {}", date, err))
+}
+
+/// Render `date` with a date-fns style format as used by Logseq's
+/// `preferredDateFormat`, e.g. `MMM do, yyyy` or `yyyy-MM-dd`.
+pub fn format_journal_title(date: NaiveDate, format: &str) -> String {
+    let mut title = String::new();
+    let chars: Vec<char> = format.chars().collect();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c == '\'' {
+            // Quoted literal text; '' is an escaped quote.
+            i += 1;
+            while i < chars.len() {
+                if chars[i] == '\'' {
+                    if chars.get(i + 1) == Some(&'\'') {
+                        title.push('\'');
+                        i += 2;
+                        continue;
+                    }
+                    break;
+                }
+                title.push(chars[i]);
+                i += 1;
+            }
+            i += 1;
+            continue;
+        }
+
+        let mut run = 1;
+        while i + run < chars.len() && chars[i + run] == c {
+            run += 1;
+        }
+        let ordinal = chars.get(i + run) == Some(&'o');
+
+        match (c, run, ordinal) {
+            ('y', 2, _) => title.push_str(&format!("{:02}", date.year() % 100)),
+            ('y', _, _) => title.push_str(&date.year().to_string()),
+            ('M', 1, true) | ('d', 1, true) => {
+                let n = if c == 'M' { date.month() } else { date.day() };
+                title.push_str(&ordinal_suffix(n));
+                run += 1;
+            }
+            ('M', 1, _) => title.push_str(&date.month().to_string()),
+            ('M', 2, _) => title.push_str(&format!("{:02}", date.month())),
+            ('M', 3, _) => title.push_str(&date.format("%b").to_string()),
+            ('M', _, _) => title.push_str(&date.format("%B").to_string()),
+            ('d', 1, _) => title.push_str(&date.day().to_string()),
+            ('d', _, _) => title.push_str(&format!("{:02}", date.day())),
+            ('E', 4, _) => title.push_str(&date.format("%A").to_string()),
+            ('E', 5, _) => title.push_str(&date.format("%A").to_string()[..1]),
+            ('E', _, _) => title.push_str(&date.format("%a").to_string()),
+            _ => title.extend(std::iter::repeat_n(c, run)),
+        }
+
+        i += run;
+    }
+
+    title
+}
+
+fn ordinal_suffix(n: u32) -> String {
+    let suffix = match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", n, suffix)
+}
+
+impl Client {
+    /// The journal title format configured in Logseq, falling back to the
+    /// Logseq default when it cannot be read.
+    pub fn journal_title_format(&self) -> String {
+        match self.call("logseq.App.getUserConfigs", json!([])) {
+            Ok(configs) => configs
+                .get("preferredDateFormat")
+                .and_then(|v| v.as_str())
+                .unwrap_or(DEFAULT_TITLE_FORMAT)
+                .to_string(),
+            Err(err) => {
+                debug!("Failed to get user configs: {}", err);
+                DEFAULT_TITLE_FORMAT.to_string()
+            }
+        }
+    }
+
+    pub fn journal_title(&self, date: NaiveDate) -> String {
+        format_journal_title(date, &self.journal_title_format())
+    }
+
+    /// Uuid of the journal page for `date`, creating the page if it does not
+    /// exist yet.
+    pub fn journal_page_uuid(&self, date: NaiveDate) -> Result<String, String> {
+        if let Some(result) = self.daemon_request("journal_page_uuid", json!({"date": date.to_string()})) {
+            return daemon::uuid_result(result);
+        }
+
+        let journal_day = date.format("%Y%m%d").to_string();
+        let pages: Vec<Page> = self.query_pulled(
+            "[:find (pull ?p [:block/uuid])
+             :in $ ?day
+             :where
+             [?p :block/journal-day ?day]]",
+            &[json!(journal_day)],
+        )?;
+
+        if let Some(uuid) = pages.into_iter().find_map(|page| page.uuid) {
+            return Ok(uuid);
+        }
+
+        let title = self.journal_title(date);
+        debug!("Creating journal page: {}", title);
+        let page: Page = self.call_as(
+            "logseq.Editor.createPage",
+            json!([title, {}, {"journal": true, "redirect": false, "createFirstBlock": false}]),
+        )?;
+
+        page.uuid.ok_or_else(|| format!("Could not create journal page for {}", date))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn formats_journal_titles() {
+        let cases = [
+            ("MMM do, yyyy", date(2024, 3, 1), "Mar 1st, 2024"),
+            ("MMM do, yyyy", date(2024, 3, 2), "Mar 2nd, 2024"),
+            ("MMM do, yyyy", date(2024, 3, 3), "Mar 3rd, 2024"),
+            ("MMM do, yyyy", date(2024, 3, 11), "Mar 11th, 2024"),
+            ("MMM do, yyyy", date(2024, 3, 12), "Mar 12th, 2024"),
+            ("MMM do, yyyy", date(2024, 3, 13), "Mar 13th, 2024"),
+            ("MMM do, yyyy", date(2024, 3, 21), "Mar 21st, 2024"),
+            ("MMM do, yyyy", date(2024, 3, 22), "Mar 22nd, 2024"),
+            ("MMM do, yyyy", date(2024, 3, 23), "Mar 23rd, 2024"),
+            ("MMM do, yyyy", date(2024, 3, 31), "Mar 31st, 2024"),
+            ("yyyy-MM-dd", date(2024, 3, 5), "2024-03-05"),
+            ("yyyy/M/d", date(2024, 3, 5), "2024/3/5"),
+            ("dd-MM-yy", date(2009, 11, 5), "05-11-09"),
+            ("MMMM d, yyyy", date(2024, 3, 5), "March 5, 2024"),
+            ("EEEE, MMMM do, yyyy", date(2024, 3, 5), "Tuesday, March 5th, 2024"),
+            ("EEE, dd.MM.yyyy", date(2024, 3, 5), "Tue, 05.03.2024"),
+            ("EEEEE yyyyMMdd", date(2024, 3, 5), "T 20240305"),
+            ("Mo 'of' yyyy", date(2024, 3, 5), "3rd of 2024"),
+            ("yyyy 'week''s' MM", date(2024, 3, 5), "2024 week's 03"),
+        ];
+        for (format, date, expected) in cases {
+            assert_eq!(format_journal_title(date, format), expected, "format {:?}", format);
+        }
+    }
+
+    #[test]
+    fn parses_iso_dates_only() {
+        assert_eq!(parse_date("2024-03-05"), Ok(date(2024, 3, 5)));
+        assert!(parse_date("2024-02-30").is_err());
+        assert!(parse_date("05.03.2024").is_err());
+        assert!(parse_date("").is_err());
+    }
+}
@@ -0,0 +1,481 @@
+//! `rlu lint`: check pages and journals against a team's conventions, set
+//! in `~/.rlu/lint.toml`:
+//!
+//! ```toml
+//! empty-blocks = true            # on by default
+//! trailing-whitespace = true     # on by default
+//!
+//! [task-priority]                # open tasks need a priority
+//! default = "C"                  # optional, what --fix gives them
+//!
+//! [[required-properties]]
+//! pages = "^meetings/"           # regex, matched against page names
+//! properties = ["type", "date"]
+//! defaults = { type = "meeting" }
+//! ```
+//!
+//! `--fix` only makes rewrites that cannot lose anything: it trims trailing
+//! whitespace, keeping the two spaces of a Markdown line break, and fills
+//! in priorities and page properties that have a configured default.
+//! Everything else is left to be fixed by hand.
+
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::cache::Cache;
+use crate::clock::TASK_PREFIX;
+use crate::model::{Block, BlockChild, Page};
+use crate::{edn_string, Client};
+
+const LINT_PULL: &str = "[:db/id :block/uuid :block/content {:block/parent [:db/id]} {:block/left [:db/id]}
+                         {:block/page [:db/id :block/name :block/original-name :block/journal-day]}]";
+
+/// `~/.rlu/lint.toml`.
+pub fn default_config_path() -> PathBuf {
+    PathBuf::from(env::var("HOME").unwrap_or_default()).join(".rlu").join("lint.toml")
+}
+
+fn enabled() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct LintConfig {
+    #[serde(default = "enabled")]
+    empty_blocks: bool,
+    #[serde(default = "enabled")]
+    trailing_whitespace: bool,
+    task_priority: Option<TaskPriorityRule>,
+    #[serde(default)]
+    required_properties: Vec<RequiredProperties>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TaskPriorityRule {
+    default: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RequiredProperties {
+    pages: String,
+    properties: Vec<String>,
+    #[serde(default)]
+    defaults: BTreeMap<String, String>,
+}
+
+/// The configuration with its page patterns compiled.
+struct Rules {
+    config: LintConfig,
+    page_patterns: Vec<Regex>,
+}
+
+fn load_rules(path: &Path, explicit: bool) -> Result<Rules, String> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        // Without a config file the built-in checks still run.
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound && !explicit => String::new(),
+        Err(err) => return Err(format!("Failed to read {}: {}", path.display(), err)),
+    };
+    parse_rules(&text).map_err(|err| format!("Invalid {}: {}", path.display(), err))
+}
+
+/// The rules in a `lint.toml`, checked and with their patterns compiled.
+fn parse_rules(text: &str) -> Result<Rules, String> {
+    let config: LintConfig = toml::from_str(text).map_err(|err| err.to_string())?;
+    if let Some(priority) = config.task_priority.as_ref().and_then(|rule| rule.default.as_ref()) {
+        if priority.len() != 1 || !priority.chars().all(|c| c.is_ascii_uppercase()) {
+            return Err(format!("task-priority default must be a letter like A, B or C, not '{}'", priority));
+        }
+    }
+    let page_patterns = config
+        .required_properties
+        .iter()
+        .map(|rule| Regex::new(&format!("(?i){}", rule.pages)).map_err(|err| format!("Invalid pattern '{}': {}", rule.pages, err)))
+        .collect::<Result<_, _>>()?;
+    Ok(Rules { config, page_patterns })
+}
+
+/// A page with all of its blocks, the first top-level one first when it is
+/// known.
+struct LintPage {
+    name: String,
+    blocks: Vec<Block>,
+    has_first: bool,
+}
+
+/// `key` of a `key:: value` line.
+fn property_key(line: &str) -> Option<&str> {
+    let (key, _) = line.trim().split_once("::")?;
+    (!key.is_empty() && key.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_')).then_some(key)
+}
+
+/// Keys of a block made of nothing but properties, as page properties are.
+fn page_property_keys(block: &Block) -> Option<Vec<String>> {
+    let lines: Vec<&str> = block.content.lines().filter(|line| !line.trim().is_empty()).collect();
+    let keys: Vec<String> = lines.iter().filter_map(|line| property_key(line)).map(str::to_lowercase).collect();
+    (!lines.is_empty() && keys.len() == lines.len()).then_some(keys)
+}
+
+/// `block` and its loaded descendants, in outline order.
+fn flatten(mut block: Block, blocks: &mut Vec<Block>) {
+    let children = std::mem::take(&mut block.children);
+    blocks.push(block);
+    for child in children {
+        if let BlockChild::Loaded(child) = child {
+            flatten(*child, blocks);
+        }
+    }
+}
+
+/// `line` without trailing whitespace, except for exactly two spaces after
+/// text, which are a Markdown hard line break.
+fn trim_line_end(line: &str) -> &str {
+    let trimmed = line.trim_end();
+    if !trimmed.is_empty() && &line[trimmed.len()..] == "  " {
+        line
+    } else {
+        trimmed
+    }
+}
+
+struct Violation {
+    uuid: String,
+    page: String,
+    rule: &'static str,
+    message: String,
+    fixable: bool,
+}
+
+/// What `--fix` would change: new content for blocks, and property blocks
+/// to add before a page's first block.
+#[derive(Default)]
+struct Fixes {
+    updates: BTreeMap<String, String>,
+    inserts: Vec<(String, String)>,
+}
+
+fn check_page(page: &LintPage, rules: &Rules, violations: &mut Vec<Violation>, fixes: &mut Fixes) {
+    let config = &rules.config;
+    let mut violation = |uuid: &str, rule: &'static str, message: String, fixable: bool| {
+        violations.push(Violation { uuid: uuid.to_string(), page: page.name.clone(), rule, message, fixable });
+    };
+
+    for block in &page.blocks {
+        let mut fixed = block.content.clone();
+
+        // A page's only block is the empty placeholder Logseq creates.
+        if config.empty_blocks && block.content.trim().is_empty() && page.blocks.len() > 1 {
+            violation(&block.uuid, "empty-blocks", "empty block".to_string(), false);
+        }
+
+        if let Some(rule) = &config.task_priority {
+            let first_line = block.content.lines().next().unwrap_or_default();
+            if let Some(captures) = TASK_PREFIX.captures(first_line) {
+                let open = captures.get(1).filter(|marker| !matches!(marker.as_str(), "DONE" | "CANCELED" | "CANCELLED"));
+                if let (Some(marker), None) = (open, captures.get(2)) {
+                    violation(&block.uuid, "task-priority", format!("{} task has no priority", marker.as_str()), rule.default.is_some());
+                    if let Some(priority) = &rule.default {
+                        fixed = format!("{} [#{}] {}", marker.as_str(), priority, fixed[captures[0].len()..].trim_start());
+                    }
+                }
+            }
+        }
+
+        let numbers: Vec<String> = block
+            .content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| line.len() != trim_line_end(line).len())
+            .map(|(index, _)| (index + 1).to_string())
+            .collect();
+        if config.trailing_whitespace && !numbers.is_empty() {
+            violation(&block.uuid, "trailing-whitespace", format!("whitespace at the end of line {}", numbers.join(", ")), true);
+            fixed = fixed.lines().map(trim_line_end).collect::<Vec<_>>().join("\n");
+        }
+
+        if fixed != block.content {
+            fixes.updates.insert(block.uuid.clone(), fixed);
+        }
+    }
+
+    let Some(first) = page.blocks.first().filter(|_| page.has_first) else {
+        return;
+    };
+    let existing = page_property_keys(first);
+    let mut additions = Vec::new();
+    for (rule, pattern) in config.required_properties.iter().zip(&rules.page_patterns) {
+        if !pattern.is_match(&page.name) {
+            continue;
+        }
+        for property in &rule.properties {
+            let key = property.to_lowercase();
+            if existing.as_ref().is_some_and(|keys| keys.contains(&key)) || additions.iter().any(|(added, _)| *added == key) {
+                continue;
+            }
+            let default = rule.defaults.get(property);
+            violation(&first.uuid, "required-properties", format!("page has no {}:: property", property), default.is_some());
+            if let Some(default) = default {
+                additions.push((key, format!("{}:: {}", property, default)));
+            }
+        }
+    }
+    if additions.is_empty() {
+        return;
+    }
+    let lines: Vec<String> = additions.into_iter().map(|(_, line)| line).collect();
+    if existing.is_some() {
+        let content = fixes.updates.entry(first.uuid.clone()).or_insert_with(|| first.content.clone());
+        content.push('\n');
+        content.push_str(&lines.join("\n"));
+    } else {
+        fixes.inserts.push((first.uuid.clone(), lines.join("\n")));
+    }
+}
+
+impl Client {
+    /// All blocks, grouped by page, in one query.
+    fn lint_pages_live(&self, page: Option<&str>) -> Result<Vec<LintPage>, String> {
+        let blocks: Vec<Block> = match page {
+            Some(name) => self.query_pulled(
+                &format!(
+                    "[:find (pull ?b {pull})
+                     :in $ ?name
+                     :where
+                     [?p :block/name ?name]
+                     [?b :block/page ?p]]",
+                    pull = LINT_PULL,
+                ),
+                &[json!(edn_string(&name.to_lowercase()))],
+            )?,
+            None => self.query_pulled(&format!("[:find (pull ?b {}) :where [?b :block/page]]", LINT_PULL), &[])?,
+        };
+
+        let mut pages: BTreeMap<i64, LintPage> = BTreeMap::new();
+        for block in blocks {
+            let Some(page) = block.page.clone() else {
+                continue;
+            };
+            let page_id = page.id.unwrap_or_default();
+            let first = block.parent.is_some_and(|parent| parent.id == page_id) && block.left.is_some_and(|left| left.id == page_id);
+            let entry = pages.entry(page_id).or_insert_with(|| LintPage {
+                name: page.display_name().to_string(),
+                blocks: Vec::new(),
+                has_first: false,
+            });
+            if first {
+                entry.blocks.insert(0, block);
+                entry.has_first = true;
+            } else {
+                entry.blocks.push(block);
+            }
+        }
+        Ok(pages.into_values().collect())
+    }
+
+    /// The pages in the local cache as last synced, without contacting
+    /// Logseq.
+    fn lint_pages_cached(&self, page: Option<&str>) -> Result<Vec<LintPage>, String> {
+        let cache = Cache::open()?;
+        let mut pages = Vec::new();
+        for found in cache.pages()? {
+            let Page { uuid: Some(uuid), .. } = &found else {
+                continue;
+            };
+            if page.is_some_and(|name| !found.display_name().eq_ignore_ascii_case(name)) {
+                continue;
+            }
+            let mut blocks = Vec::new();
+            for block in cache.top_level_blocks(uuid)? {
+                flatten(block, &mut blocks);
+            }
+            if !blocks.is_empty() {
+                pages.push(LintPage { name: found.display_name().to_string(), has_first: true, blocks });
+            }
+        }
+        Ok(pages)
+    }
+
+    pub fn lint(&self, config: Option<&Path>, page: Option<&str>, cached: bool, fix: bool) {
+        match self.lint_inner(config, page, cached, fix) {
+            Ok(0) => {}
+            Ok(_) => std::process::exit(1),
+            Err(err) => {
+                eprintln!("Failed to lint: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    /// Report and optionally fix violations; returns how many remain.
+    fn lint_inner(&self, config: Option<&Path>, page: Option<&str>, cached: bool, fix: bool) -> Result<usize, String> {
+        let rules = match config {
+            Some(path) => load_rules(path, true)?,
+            None => load_rules(&default_config_path(), false)?,
+        };
+        let pages = if cached { self.lint_pages_cached(page)? } else { self.lint_pages_live(page)? };
+
+        let mut violations = Vec::new();
+        let mut fixes = Fixes::default();
+        for page in &pages {
+            check_page(page, &rules, &mut violations, &mut fixes);
+        }
+        violations.sort_by(|a, b| a.page.to_lowercase().cmp(&b.page.to_lowercase()).then_with(|| a.rule.cmp(b.rule)));
+
+        // Blocks whose fix could not be written.
+        let mut failed: HashMap<String, String> = HashMap::new();
+        if fix {
+            for (uuid, content) in &fixes.updates {
+                if let Err(err) = self.call("logseq.Editor.updateBlock", json!([uuid, content])) {
+                    failed.insert(uuid.clone(), err);
+                }
+            }
+            for (uuid, properties) in &fixes.inserts {
+                if let Err(err) = self.call("logseq.Editor.insertBlock", json!([uuid, properties, {"sibling": true, "before": true}])) {
+                    failed.insert(uuid.clone(), err);
+                }
+            }
+        }
+
+        let mut remaining = 0;
+        for violation in &violations {
+            let fixed = fix && violation.fixable && !failed.contains_key(&violation.uuid);
+            if !fixed {
+                remaining += 1;
+            }
+            println!(
+                "{} {}: {}: {}{}",
+                violation.uuid,
+                violation.page,
+                violation.rule,
+                violation.message,
+                if fixed { " (fixed)" } else { "" }
+            );
+        }
+        for (uuid, err) in &failed {
+            eprintln!("Failed to fix {}: {}", uuid, err);
+        }
+
+        let fixable = violations.iter().filter(|violation| violation.fixable).count();
+        if violations.is_empty() {
+            eprintln!("No problems in {} pages.", pages.len());
+        } else if fix {
+            eprintln!("{} problems in {} pages, {} left.", violations.len(), pages.len(), remaining);
+        } else {
+            eprintln!("{} problems in {} pages, {} fixable with --fix.", violations.len(), pages.len(), fixable);
+        }
+        Ok(remaining)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(uuid: &str, content: &str) -> Block {
+        Block { uuid: uuid.to_string(), content: content.to_string(), ..Block::default() }
+    }
+
+    fn rules(toml: &str) -> Rules {
+        parse_rules(toml).unwrap()
+    }
+
+    fn rules_error(toml: &str) -> String {
+        parse_rules(toml).err().expect("the rules are refused")
+    }
+
+    #[test]
+    fn enables_the_built_in_checks_by_default() {
+        let defaults = rules("");
+        assert!(defaults.config.empty_blocks && defaults.config.trailing_whitespace);
+        assert!(defaults.config.task_priority.is_none() && defaults.page_patterns.is_empty());
+        assert!(!rules("empty-blocks = false").config.empty_blocks);
+    }
+
+    #[test]
+    fn refuses_bad_priority_letters() {
+        for letter in ["c", "AB", "1", ""] {
+            let error = rules_error(&format!("[task-priority]\ndefault = \"{}\"", letter));
+            assert!(error.contains("must be a letter"), "{}", error);
+        }
+        assert!(rules("[task-priority]\ndefault = \"C\"").config.task_priority.is_some());
+    }
+
+    #[test]
+    fn refuses_bad_page_patterns_and_unknown_keys() {
+        let error = rules_error("[[required-properties]]\npages = \"^meetings/(\"\nproperties = [\"type\"]");
+        assert!(error.starts_with("Invalid pattern '^meetings/('"), "{}", error);
+        assert!(rules_error("empty-block = true").contains("unknown field"));
+        let meetings = rules("[[required-properties]]\npages = \"^Meetings/\"\nproperties = [\"type\"]");
+        assert!(meetings.page_patterns[0].is_match("meetings/standup"));
+    }
+
+    fn check(name: &str, blocks: Vec<Block>, rules: &Rules) -> (Vec<(String, &'static str, bool)>, Fixes) {
+        let page = LintPage { name: name.to_string(), blocks, has_first: true };
+        let (mut violations, mut fixes) = (Vec::new(), Fixes::default());
+        check_page(&page, rules, &mut violations, &mut fixes);
+        (violations.into_iter().map(|v| (v.uuid, v.rule, v.fixable)).collect(), fixes)
+    }
+
+    #[test]
+    fn reports_empty_blocks_but_not_a_lone_placeholder() {
+        let rules = rules("");
+        let (violations, _) = check("page", vec![block("a", "text"), block("b", "")], &rules);
+        assert_eq!(violations, vec![("b".to_string(), "empty-blocks", false)]);
+        let (violations, _) = check("page", vec![block("a", "")], &rules);
+        assert!(violations.is_empty());
+    }
+
+    #[test]
+    fn trims_trailing_whitespace_but_keeps_hard_line_breaks() {
+        let rules = rules("");
+        let (violations, fixes) = check("page", vec![block("a", "one \ntwo  \nthree\t\nfour   "), block("b", "line  \nbreak")], &rules);
+        assert_eq!(violations, vec![("a".to_string(), "trailing-whitespace", true)]);
+        assert_eq!(fixes.updates.get("a").map(String::as_str), Some("one\ntwo  \nthree\nfour"));
+        assert!(!fixes.updates.contains_key("b"));
+    }
+
+    #[test]
+    fn inserts_the_default_priority_into_open_tasks() {
+        let with_default = rules("[task-priority]\ndefault = \"C\"");
+        let blocks = vec![
+            block("a", "TODO write report\nnotes"),
+            block("b", "LATER   call back"),
+            block("c", "DONE shipped"),
+            block("d", "NOW [#A] urgent"),
+        ];
+        let (violations, fixes) = check("page", blocks, &with_default);
+        assert_eq!(violations, vec![("a".to_string(), "task-priority", true), ("b".to_string(), "task-priority", true)]);
+        assert_eq!(fixes.updates.get("a").map(String::as_str), Some("TODO [#C] write report\nnotes"));
+        assert_eq!(fixes.updates.get("b").map(String::as_str), Some("LATER [#C] call back"));
+        assert_eq!(fixes.updates.len(), 2);
+
+        let (violations, fixes) = check("page", vec![block("a", "TODO task")], &rules("[task-priority]"));
+        assert_eq!(violations, vec![("a".to_string(), "task-priority", false)]);
+        assert!(fixes.updates.is_empty());
+    }
+
+    #[test]
+    fn fills_in_required_page_properties() {
+        let rules = rules(
+            "[[required-properties]]\npages = \"^meetings/\"\nproperties = [\"type\", \"date\"]\ndefaults = { type = \"meeting\" }",
+        );
+        let (violations, fixes) = check("Meetings/Standup", vec![block("a", "date:: today"), block("b", "agenda")], &rules);
+        assert_eq!(violations, vec![("a".to_string(), "required-properties", true)]);
+        assert_eq!(fixes.updates.get("a").map(String::as_str), Some("date:: today\ntype:: meeting"));
+
+        let (violations, fixes) = check("meetings/retro", vec![block("a", "agenda")], &rules);
+        assert_eq!(violations.len(), 2);
+        assert_eq!(fixes.inserts, vec![("a".to_string(), "type:: meeting".to_string())]);
+
+        let (violations, _) = check("notes", vec![block("a", "agenda")], &rules);
+        assert!(violations.is_empty());
+    }
+}
@@ -1,59 +1,42 @@
 use chrono::{Local, NaiveDate};
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 use reqwest::header::{self, HeaderMap};
-use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
 use serde_json::json;
-use std::collections::HashMap;
 use std::env;
 use std::io::{self, Read};
+use std::path::PathBuf;
+use std::sync::OnceLock;
 use log::{debug};
-use env_logger;
-
-#[derive(Debug, Deserialize, Serialize, Default)]
-pub struct Block {
-    format: Option<String>,
-    #[serde(rename = "journal?")]
-    journal: Option<bool>,
-    uuid: Option<String>,
-    page: Option<Page>,
-    id: Option<i64>,
-    #[serde(rename = "journalDay")]
-    journal_day: Option<i64>,
-    parent: Option<Parent>,
-    #[serde(default)]
-    children: Vec<Vec<serde_json::Value>>,
-    #[serde(default)]
-    properties: HashMap<String, serde_json::Value>,
-    warning: Option<String>,
-    #[serde(default)]
-    #[serde(rename = "PathRefs")]
-    path_refs: Vec<PathRef>,
-    content: Option<String>,
-    #[serde(default)]
-    #[serde(rename = "propertiesOrder")]
-    properties_order: Vec<serde_json::Value>,
-    left: Option<Left>,
-}
 
-#[derive(Debug, Deserialize, Serialize, Default)]
-struct Page {
-    id: Option<i64>,
-}
-
-#[derive(Debug, Deserialize, Serialize, Default)]
-struct Parent {
-    id: Option<i64>,
-}
-
-#[derive(Debug, Deserialize, Serialize)]
-struct PathRef {
-    id: Option<i64>,
-}
-
-#[derive(Debug, Deserialize, Serialize)]
-struct Left {
-    id: Option<i64>,
-}
+mod agenda;
+mod assets;
+mod cache;
+mod capture;
+mod cards;
+mod clock;
+mod completions;
+mod daemon;
+mod edit;
+mod gateway;
+mod import;
+mod journal;
+mod lint;
+mod model;
+mod outline;
+mod pages;
+mod refs;
+mod rename;
+mod replace;
+mod serve;
+mod stats;
+mod template;
+mod watch;
+mod webhook;
+mod worklog;
+
+use completions::CompletionKind;
+use model::{Block, BlockChild, BlockNode, JournalDay, Page};
 
 #[derive(Parser)]
 #[command(name = "rlu")]
@@ -94,6 +77,8 @@ enum Commands {
         content: Option<String>,
         #[arg(long)]
         date: Option<String>,
+        #[command(flatten)]
+        options: CombineArgs,
     },
     AppendToEnd {
         #[arg(long)]
@@ -102,6 +87,8 @@ enum Commands {
         content: Option<String>,
         #[arg(long)]
         date: Option<String>,
+        #[command(flatten)]
+        options: CombineArgs,
     },
     AddChildNode {
         #[arg(long)]
@@ -117,25 +104,506 @@ enum Commands {
         #[arg(long)]
         date: Option<String>,
     },
+    /// Replace the whole content of a block
+    Replace {
+        entry_id: String,
+        #[arg(long)]
+        content: Option<String>,
+    },
+    /// Regex-substitute content in a block, subtree, page or search results
+    Sub {
+        pattern: String,
+        /// Replacement text; `$1` or `${name}` insert capture groups
+        replacement: String,
+        #[command(flatten)]
+        scope: replace::SubScope,
+        /// Update the blocks instead of only showing the diff
+        #[arg(long)]
+        apply: bool,
+    },
+    /// Edit a block in $EDITOR and apply the changes
+    Edit {
+        uuid: String,
+        /// Edit the block together with its children as an outline
+        #[arg(long)]
+        children: bool,
+    },
+    /// List tags (referenced pages) with their usage counts
+    Tags,
+    /// Show the blocks referencing a page, grouped by page
+    Refs {
+        page: String,
+        /// Also include blocks nested below a referencing block
+        #[arg(long)]
+        include_children: bool,
+    },
+    /// Show the blocks referencing a block
+    Backlinks {
+        uuid: String,
+    },
+    /// List pages, optionally filtered
+    Pages {
+        #[command(flatten)]
+        filter: pages::PageFilter,
+    },
+    /// Show the namespace hierarchy of pages
+    Tree {
+        /// Only show the pages below this namespace, e.g. `eng/rlu`
+        namespace: Option<String>,
+    },
+    /// Create, show, inspect, rename, alias or merge pages
+    Page {
+        #[command(subcommand)]
+        command: PageCommands,
+    },
+    /// Show upcoming and overdue scheduled and deadline blocks by day
+    Agenda {
+        /// How many days ahead to look
+        #[arg(long, default_value_t = 7)]
+        days: i64,
+        /// Print an iCalendar feed instead
+        #[arg(long)]
+        ics: bool,
+    },
+    /// Import calendar events into their journal days
+    ImportIcs {
+        file: String,
+    },
+    /// Import CSV rows into their journal days
+    ImportCsv {
+        file: String,
+        /// Field mapping as field=Column, or field="{Column} text" to combine
+        /// columns. `date` and `content` are required, `id` is stored as
+        /// source-id::, anything else becomes a property
+        #[arg(long = "map", value_parser = template::parse_var, required = true)]
+        map: Vec<(String, String)>,
+        /// chrono format of the date column
+        #[arg(long, default_value = "%Y-%m-%d")]
+        date_format: String,
+        #[arg(long, default_value_t = ',')]
+        delimiter: char,
+    },
+    /// Queue a note for the journal; works while Logseq is closed
+    Capture {
+        #[arg(long)]
+        content: Option<String>,
+        /// Journal day for the note instead of the day it was captured
+        #[arg(long)]
+        date: Option<String>,
+    },
+    /// Add queued notes to their journal days
+    Flush,
+    /// Serve Logseq calls and cached page lookups to other rlu commands over
+    /// a Unix socket, and keep adding queued notes whenever Logseq is reachable
+    Daemon {
+        /// Seconds between attempts to add queued notes
+        #[arg(long, default_value_t = 30)]
+        interval: u64,
+    },
+    /// Serve search, reading, note taking and task listing as Model Context
+    /// Protocol tools for assistants
+    Serve {
+        /// Speak JSON-RPC over stdin and stdout
+        #[arg(long, required = true)]
+        stdio: bool,
+    },
+    /// Serve a REST API with its own scoped tokens and an audit log in front
+    /// of the Logseq API
+    Gateway {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8765")]
+        listen: String,
+        /// Client tokens and permissions [default: ~/.rlu/gateway.toml]
+        #[arg(long)]
+        config: Option<PathBuf>,
+        /// File each request is logged to [default: ~/.rlu/gateway-audit.log]
+        #[arg(long)]
+        audit_log: Option<PathBuf>,
+    },
+    /// Turn JSON POSTs into journal notes according to a rules file
+    Webhook {
+        /// Address to listen on
+        #[arg(long)]
+        listen: String,
+        /// TOML file mapping request paths and payload fields to notes
+        #[arg(long)]
+        rules: PathBuf,
+    },
+    /// Add new lines of a file, or new files dropped into a folder, as blocks
+    Watch {
+        /// A file to follow or a folder of Markdown files
+        path: PathBuf,
+        /// Add to this page instead of today's journal
+        #[arg(long, conflicts_with = "journal")]
+        page: Option<String>,
+        /// Add to today's journal (the default)
+        #[arg(long)]
+        journal: bool,
+        /// Seconds between checks
+        #[arg(long, default_value_t = 2)]
+        interval: u64,
+        /// Also add what is already there the first time a path is watched
+        #[arg(long)]
+        from_start: bool,
+        /// File the read offsets are kept in [default: ~/.rlu/watch-state.json]
+        #[arg(long)]
+        state: Option<PathBuf>,
+    },
+    /// Log git commits under a Commits block on the journal day they were
+    /// made, grouped by repository
+    LogGit {
+        /// Repository to read commits from; repeat for several
+        #[arg(long = "repo", default_value = ".")]
+        repos: Vec<PathBuf>,
+        /// First day to log commits of, YYYY-MM-DD [default: today]
+        #[arg(long)]
+        since: Option<String>,
+        /// Only commits whose author matches this pattern
+        #[arg(long)]
+        author: Option<String>,
+    },
+    /// Log a finished shell command under a Commands block on today's
+    /// journal; used by the shell hook
+    LogCmd {
+        /// The command line that ran
+        #[arg(long, required_unless_present = "hook")]
+        command: Option<String>,
+        /// Its exit status
+        #[arg(long = "exit", required_unless_present = "hook", allow_negative_numbers = true)]
+        exit_status: Option<i32>,
+        /// When it started, in seconds since the Unix epoch
+        #[arg(long, required_unless_present = "hook")]
+        started: Option<i64>,
+        /// Commands that finish sooner than this many seconds are not logged
+        #[arg(long, default_value_t = 60)]
+        min_duration: i64,
+        /// Print the hook for a shell's rc file instead, e.g.
+        /// `eval "$(rlu log-cmd --hook bash)"`
+        #[arg(long, exclusive = true)]
+        hook: Option<worklog::HookShell>,
+    },
+    /// Track time on tasks with LOGBOOK clock lines
+    Clock {
+        #[command(subcommand)]
+        command: ClockCommands,
+    },
+    /// Review flashcards, blocks tagged #card
+    Cards {
+        #[command(subcommand)]
+        command: CardsCommands,
+    },
+    /// Copy a file into the graph's assets folder and link it from a new
+    /// block
+    Attach {
+        file: PathBuf,
+        /// Add the link as the last child of this block
+        #[arg(long, conflicts_with = "journal")]
+        to: Option<String>,
+        /// Add the link to today's journal (the default)
+        #[arg(long)]
+        journal: bool,
+        /// Graph folder [default: the graph open in Logseq, or RLU_GRAPH_DIR]
+        #[arg(long)]
+        graph: Option<PathBuf>,
+    },
+    /// Inspect the files in the graph's assets folder
+    Assets {
+        #[command(subcommand)]
+        command: AssetsCommands,
+    },
+    /// Show page, block and task counts, journal streaks and broken links
+    Stats {
+        /// How many of the most linked pages to list
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+        /// Print JSON instead
+        #[arg(long)]
+        json: bool,
+    },
+    /// Check pages and journals against the conventions in a rules file
+    Lint {
+        /// TOML rules [default: ~/.rlu/lint.toml, or only the built-in checks]
+        #[arg(long)]
+        config: Option<PathBuf>,
+        /// Only check this page
+        #[arg(long)]
+        page: Option<String>,
+        /// Read the graph from the local cache as last synced, without Logseq
+        #[arg(long)]
+        cached: bool,
+        /// Rewrite blocks where the fix is safe
+        #[arg(long)]
+        fix: bool,
+    },
+    /// Manage the local copy of the graph used by show, output-content and
+    /// completions
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommands,
+    },
+    /// Insert Logseq templates or local template files
+    Template {
+        #[command(subcommand)]
+        command: TemplateCommands,
+    },
+    /// Generate a shell completion script
+    Completions {
+        shell: clap_complete::Shell,
+    },
+    /// List dynamic completion candidates (used by the completion scripts)
+    #[command(name = "__complete", hide = true)]
+    Complete {
+        kind: CompletionKind,
+        #[arg(default_value = "")]
+        prefix: String,
+    },
+}
+
+/// How `add-to-start` and `append-to-end` join new content with a block.
+#[derive(Args)]
+#[group(multiple = false)]
+pub struct CombineArgs {
+    /// Text placed between the existing and the new content [default: a
+    /// space, or a newline when the new content has several lines]
+    #[arg(long)]
+    separator: Option<String>,
+    /// Join with a newline instead of a space
+    #[arg(long)]
+    newline: bool,
+    /// Insert the content as the block's first child instead
+    #[arg(long)]
+    as_child_first: bool,
+    /// Insert the content as the block's last child instead
+    #[arg(long)]
+    as_child_last: bool,
+}
+
+impl CombineArgs {
+    fn separator(&self, new_content: &str) -> String {
+        match &self.separator {
+            Some(separator) => separator.clone(),
+            None if self.newline || new_content.contains('\n') => "\n".to_string(),
+            None => " ".to_string(),
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum PageCommands {
+    /// Create a page, including missing parent namespace pages
+    Add {
+        name: String,
+        /// Content for the page, as a Markdown outline
+        #[arg(long)]
+        content: Option<String>,
+    },
+    /// Print the blocks of a page
+    Show {
+        name: String,
+    },
+    /// Show a page's properties, aliases, file and block and backlink counts
+    Info {
+        name: String,
+    },
+    /// Rename a page; Logseq updates the links to it
+    Rename {
+        old: String,
+        new: String,
+    },
+    /// Add an alias to a page's `alias::` property
+    Alias {
+        page: String,
+        alias: String,
+        /// Remove the alias instead
+        #[arg(long)]
+        remove: bool,
+    },
+    /// Move all blocks of one page into another and rewrite links to it
+    Merge {
+        src: String,
+        dst: String,
+        /// Only show what would be moved and rewritten
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ClockCommands {
+    /// Start the clock on a task, stopping any other running clock
+    In {
+        uuid: String,
+    },
+    /// Stop the running clock
+    Out {
+        /// Only stop the clock of this block
+        uuid: Option<String>,
+        /// Mark the task DONE instead of returning it to TODO
+        #[arg(long)]
+        done: bool,
+    },
+    /// Summarize clocked time per task, page and tag
+    Report {
+        /// First day, YYYY-MM-DD [default: today]
+        #[arg(long)]
+        from: Option<String>,
+        /// Last day, YYYY-MM-DD [default: today]
+        #[arg(long)]
+        to: Option<String>,
+        #[arg(long, value_enum, default_value_t = clock::ReportFormat::Text)]
+        format: clock::ReportFormat,
+    },
+}
+
+#[derive(Subcommand)]
+enum AssetsCommands {
+    /// List assets that no block links to
+    Orphaned {
+        /// Graph folder [default: the graph open in Logseq, or RLU_GRAPH_DIR]
+        #[arg(long)]
+        graph: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum CardsCommands {
+    /// Go through the cards that are due and rate how well you remembered
+    Review {
+        /// Review at most this many cards
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheCommands {
+    /// Bring the cache up to date, including deleted blocks and pages
+    Sync {
+        /// Rebuild the cache from scratch
+        #[arg(long)]
+        full: bool,
+    },
+    /// Remove everything from the cache
+    Clear,
+}
+
+#[derive(Subcommand)]
+enum TemplateCommands {
+    /// List Logseq templates and local template files
+    List,
+    /// Insert a template into a page or a journal day (today by default)
+    Apply {
+        name: String,
+        #[arg(long, conflicts_with = "date")]
+        page: Option<String>,
+        #[arg(long)]
+        date: Option<String>,
+        /// Value for a `<% key %>` placeholder, as key=value
+        #[arg(long = "var", value_parser = template::parse_var)]
+        vars: Vec<(String, String)>,
+    },
 }
 
 pub struct Client {
-    client: reqwest::blocking::Client,
+    /// Built on first use: commands the daemon answers never need it, nor the
+    /// API key.
+    http: OnceLock<Result<reqwest::blocking::Client, String>>,
     current_journal: Option<String>,
+    /// Socket of `rlu daemon`, tried before talking to Logseq directly.
+    daemon: Option<PathBuf>,
 }
 
 impl Client {
     pub fn new() -> Self {
         Self {
-            client: reqwest::blocking::Client::builder()
-                .default_headers(Self::client_headers())
-                .build()
-                .unwrap(),
+            daemon: env::var_os("RLU_NO_DAEMON").is_none().then(daemon::socket_path),
+            ..Self::direct()
+        }
+    }
+
+    /// A client that always talks to Logseq itself, never to the daemon.
+    pub fn direct() -> Self {
+        Self {
+            http: OnceLock::new(),
             current_journal: None,
+            daemon: None,
+        }
+    }
+
+    fn http_client(&self) -> Result<&reqwest::blocking::Client, String> {
+        self.http
+            .get_or_init(|| {
+                reqwest::blocking::Client::builder()
+                    .default_headers(Self::client_headers()?)
+                    .build()
+                    .map_err(|err| format!("Failed to create the HTTP client: {}", err))
+            })
+            .as_ref()
+            .map_err(Clone::clone)
+    }
+
+    fn call(&self, method: &str, args: serde_json::Value) -> Result<serde_json::Value, String> {
+        if let Some(result) = self.daemon_request("logseq", json!({"method": method, "args": args})) {
+            return result;
         }
+
+        let body = json!({
+            "method": method,
+            "args": args
+        });
+
+        debug!("Request body: {}", body);
+
+        let response = self
+            .http_client()?
+            .post(api_url())
+            .json(&body)
+            .send()
+            .map_err(|error| format!("Error: {:?}", error))?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().unwrap_or_default();
+            return Err(format!("{} failed: {}", method, error_text));
+        }
+
+        let raw_response = response.text().map_err(|error| format!("Error: {:?}", error))?;
+        debug!("Raw response: {}", raw_response);
+
+        if raw_response.trim().is_empty() {
+            return Ok(serde_json::Value::Null);
+        }
+        serde_json::from_str(&raw_response).map_err(|err| format!("Failed to parse response: {:?}", err))
+    }
+
+    fn datascript_query(&self, query: &str, inputs: &[serde_json::Value]) -> Result<Vec<serde_json::Value>, String> {
+        let mut args = vec![json!(query)];
+        args.extend(inputs.iter().cloned());
+
+        match self.call("logseq.DB.datascriptQuery", serde_json::Value::Array(args))? {
+            serde_json::Value::Array(rows) => Ok(rows),
+            serde_json::Value::Null => Ok(Vec::new()),
+            other => Err(format!("Unexpected query result: {}", other)),
+        }
+    }
+
+    /// `call`, deserializing the result into `T`.
+    fn call_as<T: DeserializeOwned>(&self, method: &str, args: serde_json::Value) -> Result<T, String> {
+        let value = self.call(method, args)?;
+        serde_json::from_value(value).map_err(|err| format!("Failed to parse {} result: {}", method, err))
+    }
+
+    /// Run a query whose first `:find` element is a pull and deserialize the
+    /// pulled entities.
+    fn query_pulled<T: DeserializeOwned>(&self, query: &str, inputs: &[serde_json::Value]) -> Result<Vec<T>, String> {
+        self.datascript_query(query, inputs)?
+            .into_iter()
+            .filter_map(|row| row.get(0).cloned())
+            .map(|pulled| serde_json::from_value(pulled).map_err(|err| format!("Failed to parse query result: {}", err)))
+            .collect()
     }
 
-    fn client_headers() -> HeaderMap {
+    fn client_headers() -> Result<HeaderMap, String> {
+        let api_key = env::var("LOGSEQ_API_KEY").map_err(|_| "LOGSEQ_API_KEY is not set".to_string())?;
         let mut headers = header::HeaderMap::new();
         headers.insert(
             "Content-Type",
@@ -143,214 +611,133 @@ impl Client {
         );
         headers.insert(
             header::AUTHORIZATION,
-            header::HeaderValue::from_str(&env::var("LOGSEQ_API_KEY").unwrap()).unwrap(),
+            header::HeaderValue::from_str(&api_key).map_err(|_| "LOGSEQ_API_KEY is not a valid header value".to_string())?,
         );
-        headers
+        Ok(headers)
     }
 
-    pub fn add_journal_note_from_stdin(&mut self, _date: Option<String>) {
+    pub fn add_journal_note_from_stdin(&mut self, date: Option<String>) {
         let mut content = String::new();
         io::stdin().read_to_string(&mut content).unwrap();
         debug!("Content from stdin: {}", content);
-        self.add_journal_note(&content);
+        self.add_journal_note(&content, date.as_deref());
     }
 
-    pub fn add_journal_note_from_flag(&mut self, content: &str, _date: Option<String>) {
+    pub fn add_journal_note_from_flag(&mut self, content: &str, date: Option<String>) {
         debug!("Content from flag: {}", content);
-        self.add_journal_note(content);
+        self.add_journal_note(content, date.as_deref());
     }
 
     fn read_content(&self, input_content: Option<String>) -> String {
-        match input_content {
-            Some(content) => content,
-            None => {
-                let mut content = String::new();
-                io::stdin().read_to_string(&mut content).unwrap();
-                content
+        read_input(input_content)
+    }
+
+    /// Add a note to the journal page of `date`, today by default.
+    fn add_journal_note(&mut self, note_text: &str, date: Option<&str>) {
+        let journal_id = match date {
+            Some(date) => match journal::parse_date(date) {
+                Ok(date) => self.journal_page_uuid(date),
+                Err(err) => {
+                    eprintln!("{}", err);
+                    std::process::exit(1);
+                }
+            },
+            None => self.current_journal(),
+        };
+        let journal_id = match journal_id {
+            Ok(journal_id) => journal_id,
+            Err(err) => {
+                eprintln!("Could not get the journal page: {}", err);
+                eprintln!("Use `rlu capture` to queue the note until Logseq is reachable.");
+                std::process::exit(1);
             }
+        };
+        debug!("Journal ID: {}", journal_id);
+
+        if let Err(err) = self.insert_note(&journal_id, note_text) {
+            eprintln!("Failed to add task to journal: {}", err);
+            std::process::exit(1);
         }
     }
 
-    fn add_journal_note(&mut self, note_text: &str) {
+    /// Append a note to the page `page_id`: the first line becomes a page
+    /// block, `#` headings nest the lines after them. Returns the uuid of
+    /// the first block.
+    fn insert_note(&self, page_id: &str, note_text: &str) -> Result<Option<String>, String> {
         let formatted_text = format_output(note_text);
-        let journal_id = self.current_journal();
-        debug!("Journal ID: {}", journal_id);
-
         let mut lines = formatted_text.lines();
-        if let Some(first_line) = lines.next() {
-            let body = json!({
-                "method": "logseq.Editor.insertBlock",
-                "args": [
-                    journal_id,
-                    first_line,
-                    {"isPageBlock": true}
-                ]
-            });
-
-            debug!("Request body: {}", body);
-
-            let res = self.client.post(api_url()).json(&body).send();
-
-            match res {
-                Ok(response) => {
-                    if response.status().is_success() {
-                        debug!("Task added to journal!");
-
-                        if let Some(block_id) = response.json::<HashMap<String, serde_json::Value>>()
-                            .unwrap().get("uuid").and_then(|v| v.as_str()) {
-
-                            // Process the remaining lines
-                            let mut current_parent_id = block_id.to_string();
-                            let mut stack = vec![current_parent_id.clone()];
-
-                            for line in lines {
-                                let current_level = line.chars().take_while(|&c| c == '#').count();
-                                if current_level > 0 {
-                                    while stack.len() > current_level {
-                                        stack.pop();
-                                    }
-                                    current_parent_id = stack.last().cloned().unwrap_or_else(|| block_id.to_string());
-                                } else if line.starts_with("- ") {
-                                    current_parent_id = stack.last().cloned().unwrap_or_else(|| block_id.to_string());
-                                }
-
-                                let body = json!({
-                                    "method": "logseq.Editor.insertBlock",
-                                    "args": [
-                                        current_parent_id,
-                                        line,
-                                        {"isPageBlock": false}
-                                    ]
-                                });
-
-                                let res = self.client.post(api_url()).json(&body).send();
-                                if let Ok(response) = res {
-                                    if response.status().is_success() {
-                                        debug!("Sub-block added: {}", line);
-                                        if let Some(new_id) = response.json::<HashMap<String, serde_json::Value>>()
-                                            .unwrap().get("uuid").and_then(|v| v.as_str()) {
-                                            if current_level > 0 {
-                                                stack.push(new_id.to_string());
-                                            }
-                                        }
-                                    } else {
-                                        debug!("Failed to add sub-block: {:?}", response.text().unwrap());
-                                    }
-                                } else {
-                                    debug!("Error adding sub-block: {:?}", res.unwrap_err());
-                                }
-                            }
-                        }
-                    } else {
-                        let error_text = response.text().unwrap();
-                        debug!("Failed to add task to journal: {:?}", error_text);
-                    }
-                }
-                Err(error) => {
-                    debug!("Error: {:?}", error);
-                }
-            }
+        let Some(first_line) = lines.next() else {
+            return Ok(None);
+        };
+
+        let block: Block = self.call_as("logseq.Editor.insertBlock", json!([page_id, first_line, {"isPageBlock": true}]))?;
+        debug!("Task added to journal!");
+        self.insert_nested_lines(&block.uuid, lines).map_err(|err| self.discard_partial(&block.uuid, err))?;
+        Ok(Some(block.uuid))
+    }
+
+    fn current_journal(&mut self) -> Result<String, String> {
+        if let Some(journal_id) = &self.current_journal {
+            return Ok(journal_id.clone());
         }
+        let journal_id = self.journal_page_uuid(Local::now().date_naive())?;
+        self.current_journal = Some(journal_id.clone());
+        Ok(journal_id)
     }
 
-    fn current_journal(&mut self) -> String {
-        self.current_journal
-            .get_or_insert_with(|| get_journal_uuid(&self.client).expect("Could not get journal id"))
-            .to_string()
+    /// The block tree of the journal page of `day` as Logseq has it, without
+    /// creating the page when there is none.
+    fn live_journal_blocks(&self, day: JournalDay) -> Result<Vec<Block>, String> {
+        let pages: Vec<Page> = self.query_pulled(
+            "[:find (pull ?p [:block/uuid])
+             :in $ ?day
+             :where
+             [?p :block/journal-day ?day]]",
+            &[json!(day.0.to_string())],
+        )?;
+        match pages.into_iter().find_map(|page| page.uuid) {
+            Some(uuid) => self.get_page_blocks_tree(&uuid),
+            None => Ok(Vec::new()),
+        }
     }
 
     pub fn show_journal_entries(&self, date: &str) {
         debug!("Showing journal entries for date: {}", date);
 
-        let query_date = NaiveDate::parse_from_str(date, "%Y-%m-%d")
-            .expect("Invalid date format")
-            .format("%Y%m%d")
-            .to_string();
-
-        debug!("Formatted date for query: {}", query_date);
-
-        let query = json!({
-            "method": "logseq.DB.datascriptQuery",
-            "args": [
-                "[:find (pull ?h [*])
-                :in $ ?today
-                :where
-                [?h :block/page ?p]
-                [?p :block/journal? true]
-                [?p :block/journal-day ?d]
-                [(== ?d ?today)] ]",
-                query_date
-            ]
-        });
-
-        debug!("Request body: {}", query);
-
-        let res = self.client.post(api_url()).json(&query).send();
-
-        match res {
-            Ok(response) => {
-                let raw_response = response.text().unwrap();
-                debug!("Raw response: {}", raw_response);
-
-                let entries: Result<Vec<Vec<HashMap<String, serde_json::Value>>>, _> = serde_json::from_str(&raw_response);
-                match entries {
-                    Ok(entries) => {
-                        debug!("Entries: {:?}", entries);
-                        for entry_list in entries {
-                            for entry in entry_list {
-                                if let Some(uuid) = entry.get("uuid").and_then(|v| v.as_str()) {
-                                    if let Some(content) = entry.get("content").and_then(|v| v.as_str()) {
-                                        if !content.trim().is_empty() {
-                                            let preview = content.split_whitespace().take(10).collect::<Vec<_>>().join(" ");
-                                            println!("{} {}", uuid, preview);
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    Err(err) => {
-                        debug!("Failed to parse journal entries: {:?}", err);
-                    }
-                }
+        let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").expect("Invalid date format");
+        let day = JournalDay::from_date(date);
+        let blocks = match self.cached_graph().and_then(|cache| cache.journal_blocks(day)) {
+            Ok(blocks) => Ok(blocks),
+            Err(err) => {
+                debug!("Cache unavailable: {}", err);
+                self.live_journal_blocks(day)
             }
-            Err(error) => {
-                debug!("Error: {:?}", error);
+        };
+        let blocks = match blocks {
+            Ok(blocks) => blocks,
+            Err(err) => {
+                eprintln!("Failed to get journal entries: {}", err);
+                return;
             }
+        };
+
+        fn print_entry(entry: &Block) {
+            if !entry.content.trim().is_empty() {
+                println!("{} {}", entry.uuid, preview(&entry.content));
+            }
+            entry.loaded_children().for_each(print_entry);
         }
+        blocks.iter().for_each(print_entry);
     }
 
     pub fn get_journal_entry(&self, entry_id: &str, _date: Option<String>) {
         eprintln!("Getting journal entry with ID: {}", entry_id);
 
-        let query = json!({
-            "method": "logseq.Editor.getBlock",
-            "args": [
-                entry_id
-            ]
-        });
-
-        debug!("Request body: {}", query);
-
-        let res = self.client.post(api_url()).json(&query).send();
-
-        match res {
-            Ok(response) => {
-                let raw_response = response.text().unwrap();
-                debug!("Raw response: {}", raw_response);
-
-                let entry: Result<HashMap<String, serde_json::Value>, _> = serde_json::from_str(&raw_response);
-                match entry {
-                    Ok(entry) => {
-                        println!("{:?}", entry);
-                    }
-                    Err(err) => {
-                        eprintln!("Failed to parse journal entry: {:?}", err);
-                    }
-                }
-            }
-            Err(error) => {
-                eprintln!("Error: {:?}", error);
+        match self.get_block_by_uuid(entry_id) {
+            Ok(entry) => println!("{:?}", entry),
+            Err(err) => {
+                eprintln!("Failed to get journal entry: {}", err);
+                std::process::exit(1);
             }
         }
     }
@@ -358,375 +745,227 @@ impl Client {
     pub fn output_content(&self, entry_id: &str, _date: Option<String>) {
         debug!("Getting content for entry with ID: {}", entry_id);
 
-        // Get the block details by the entry ID
-        let query = json!({
-            "method": "logseq.Editor.getBlock",
-            "args": [
-                entry_id
-            ]
-        });
-
-        let res = self.client.post(api_url()).json(&query).send();
-
-        match res {
-            Ok(response) => {
-                let raw_response = response.text().unwrap();
-                debug!("Raw response: {}", raw_response);
-
-                let entry: Result<Block, _> = serde_json::from_str(&raw_response);
-                match entry {
-                    Ok(entry) => {
-                        let mut content = String::new();
-                        self.collect_block_content(&entry, &mut content, 0);
-                        println!("{}", content);
-                    }
-                    Err(err) => {
-                        eprintln!("Failed to parse block: {:?}", err);
-                    }
-                }
+        let block = match self.cached_graph().and_then(|cache| cache.block_tree(entry_id)) {
+            Ok(Some(block)) => Ok(block),
+            Ok(None) => {
+                debug!("Block {} is not cached", entry_id);
+                self.get_block_by_uuid(entry_id)
             }
-            Err(error) => {
-                eprintln!("Error: {:?}", error);
+            Err(err) => {
+                debug!("Cache unavailable: {}", err);
+                self.get_block_by_uuid(entry_id)
             }
-        }
-    }
+        };
 
-    fn collect_block_content(&self, block: &Block, content: &mut String, indent_level: usize) {
-        if let Some(block_content) = &block.content {
-            content.push_str(&"  ".repeat(indent_level));
-            content.push_str(block_content);
-            content.push('\n');
-        }
-        for child in &block.children {
-            if let Some(uuid) = child.get(1).and_then(|v| v.as_str()) {
-                let child_block = self.get_block_by_uuid(uuid);
-                self.collect_block_content(&child_block, content, indent_level + 1);
+        let mut content = String::new();
+        match block.and_then(|block| self.collect_block_content(&block, &mut content, 0)) {
+            Ok(()) => println!("{}", content),
+            Err(err) => {
+                eprintln!("Failed to get block content: {}", err);
+                std::process::exit(1);
             }
         }
     }
 
-    fn get_block_by_uuid(&self, uuid: &str) -> Block {
-        let query = json!({
-            "method": "logseq.Editor.getBlock",
-            "args": [
-                uuid
-            ]
-        });
-
-        let res = self.client.post(api_url()).json(&query).send();
-
-        match res {
-            Ok(response) => {
-                let raw_response = response.text().unwrap();
-                debug!("Raw response for child block: {}", raw_response);
-
-                let block: Result<Block, _> = serde_json::from_str(&raw_response);
-                match block {
-                    Ok(block) => block,
-                    Err(err) => {
-                        debug!("Failed to parse child block: {:?}", err);
-                        Block::default()
-                    }
+    fn collect_block_content(&self, block: &Block, content: &mut String, indent_level: usize) -> Result<(), String> {
+        content.push_str(&"  ".repeat(indent_level));
+        content.push_str(&block.content);
+        content.push('\n');
+        for child in &block.children {
+            match child {
+                BlockChild::Loaded(child_block) => self.collect_block_content(child_block, content, indent_level + 1)?,
+                BlockChild::Ref(child_ref) => {
+                    let child_block = self.get_block_by_uuid(&child_ref.uuid)?;
+                    self.collect_block_content(&child_block, content, indent_level + 1)?;
                 }
             }
-            Err(error) => {
-                debug!("Error retrieving child block: {:?}", error);
-                Block::default()
-            }
         }
+        Ok(())
+    }
+
+    fn get_block_by_uuid(&self, uuid: &str) -> Result<Block, String> {
+        self.call_as::<Option<Block>>("logseq.Editor.getBlock", json!([uuid]))?
+            .ok_or_else(|| format!("Block {} not found", uuid))
     }
 
-    pub fn add_to_start(&mut self, entry_id: &str, input_content: Option<String>, _date: Option<String>) {
+    pub fn add_to_start(&mut self, entry_id: &str, input_content: Option<String>, options: &CombineArgs) -> Result<(), String> {
         let new_content = self.read_content(input_content);
         debug!("Adding content to the start of entry with ID: {}", entry_id);
 
-        let query = json!({
-            "method": "logseq.Editor.getBlock",
-            "args": [
-                entry_id
-            ]
-        });
-
-        let res = self.client.post(api_url()).json(&query).send();
-
-        match res {
-            Ok(response) => {
-                let raw_response = response.text().unwrap();
-                debug!("Raw response: {}", raw_response);
-
-                let entry: Block = serde_json::from_str(&raw_response).unwrap();
-                if let Some(content) = entry.content {
-                    let updated_content = format!("{} {}", new_content, content);
-                    let update_query = json!({
-                        "method": "logseq.Editor.updateBlock",
-                        "args": [
-                            entry_id,
-                            updated_content
-                        ]
-                    });
-
-                    let update_res = self.client.post(api_url()).json(&update_query).send();
-
-                    match update_res {
-                        Ok(update_response) => {
-                            if update_response.status().is_success() {
-                                eprintln!("Content added to the start of the entry.");
-                            } else {
-                                eprintln!("Failed to update entry: {:?}", update_response.text().unwrap());
-                            }
-                        }
-                        Err(update_error) => {
-                            eprintln!("Error: {:?}", update_error);
-                        }
-                    }
-                } else {
-                    eprintln!("No content found for this entry.");
-                }
-            }
-            Err(error) => {
-                eprintln!("Error: {:?}", error);
-            }
-        }
+        self.combine_content(entry_id, &new_content, options, |content, separator| {
+            format!("{}{}{}", new_content, separator, content)
+        })?;
+        eprintln!("Content added to the start of the entry.");
+        Ok(())
     }
 
-    pub fn append_to_end(&mut self, entry_id: &str, input_content: Option<String>, _date: Option<String>) {
+    pub fn append_to_end(&mut self, entry_id: &str, input_content: Option<String>, options: &CombineArgs) -> Result<(), String> {
         let new_content = self.read_content(input_content);
         debug!("Appending content to the end of entry with ID: {}", entry_id);
 
-        let query = json!({
-            "method": "logseq.Editor.getBlock",
-            "args": [
-                entry_id
-            ]
-        });
+        self.combine_content(entry_id, &new_content, options, |content, separator| {
+            format!("{}{}{}", content, separator, new_content)
+        })?;
+        eprintln!("Content appended to the end of the entry.");
+        Ok(())
+    }
 
-        let res = self.client.post(api_url()).json(&query).send();
-
-        match res {
-            Ok(response) => {
-                let raw_response = response.text().unwrap();
-                debug!("Raw response: {}", raw_response);
-
-                let entry: Block = serde_json::from_str(&raw_response).unwrap();
-                if let Some(content) = entry.content {
-                    let updated_content = format!("{} {}", content, new_content);
-                    let update_query = json!({
-                        "method": "logseq.Editor.updateBlock",
-                        "args": [
-                            entry_id,
-                            updated_content
-                        ]
-                    });
-
-                    let update_res = self.client.post(api_url()).json(&update_query).send();
-
-                    match update_res {
-                        Ok(update_response) => {
-                            if update_response.status().is_success() {
-                                eprintln!("Content appended to the end of the entry.");
-                            } else {
-                                eprintln!("Failed to update entry: {:?}", update_response.text().unwrap());
-                            }
-                        }
-                        Err(update_error) => {
-                            eprintln!("Error: {:?}", update_error);
-                        }
-                    }
-                } else {
-                    eprintln!("No content found for this entry.");
-                }
-            }
-            Err(error) => {
-                eprintln!("Error: {:?}", error);
-            }
+    /// Shared by `add_to_start` and `append_to_end`: either insert
+    /// `new_content` as a first or last child, or update the block with
+    /// `combine(existing content, separator)`.
+    fn combine_content(
+        &self,
+        entry_id: &str,
+        new_content: &str,
+        options: &CombineArgs,
+        combine: impl Fn(&str, &str) -> String,
+    ) -> Result<(), String> {
+        let entry = self
+            .call_as::<Option<Block>>("logseq.Editor.getBlock", json!([entry_id]))?
+            .ok_or_else(|| format!("Entry {} not found.", entry_id))?;
+
+        if options.as_child_last {
+            self.call("logseq.Editor.insertBlock", json!([entry_id, new_content, {"sibling": false}]))?;
+            return Ok(());
+        }
+        if options.as_child_first {
+            match entry.children.first().map(BlockChild::uuid) {
+                Some(first_child) => self.call(
+                    "logseq.Editor.insertBlock",
+                    json!([first_child, new_content, {"sibling": true, "before": true}]),
+                )?,
+                None => self.call("logseq.Editor.insertBlock", json!([entry_id, new_content, {"sibling": false}]))?,
+            };
+            return Ok(());
         }
+
+        let content = entry.content;
+        let updated_content = if content.trim().is_empty() {
+            new_content.to_string()
+        } else {
+            combine(&content, &options.separator(new_content))
+        };
+
+        self.call("logseq.Editor.updateBlock", json!([entry_id, updated_content]))?;
+        Ok(())
     }
 
     pub fn add_child_node(&mut self, entry_id: &str, input_content: Option<String>, _date: Option<String>) {
         let new_content = self.read_content(input_content);
         debug!("Adding child node to entry with ID: {}", entry_id);
 
-        self.process_lines_as_children(entry_id, &new_content);
+        if let Err(err) = self.process_lines_as_children(entry_id, &new_content) {
+            eprintln!("Failed to add child node: {}", err);
+            std::process::exit(1);
+        }
     }
 
-    fn process_lines_as_children(&mut self, parent_id: &str, note_text: &str) {
+    /// Insert the first line of `note_text` as the last child of
+    /// `parent_id`, with the remaining lines nested below it. Returns the
+    /// uuid of the first block.
+    fn process_lines_as_children(&self, parent_id: &str, note_text: &str) -> Result<Option<String>, String> {
         let mut lines = note_text.lines();
-        if let Some(first_line) = lines.next() {
-            let body = json!({
-                "method": "logseq.Editor.insertBlock",
-                "args": [
-                    parent_id,
-                    first_line,
-                    {"isPageBlock": false}
-                ]
-            });
-
-            debug!("Request body: {}", body);
-
-            let res = self.client.post(api_url()).json(&body).send();
-
-            match res {
-                Ok(response) => {
-                    if response.status().is_success() {
-                        debug!("Child node added!");
-
-                        if let Some(block_id) = response.json::<HashMap<String, serde_json::Value>>()
-                            .unwrap().get("uuid").and_then(|v| v.as_str()) {
-
-                            // Process the remaining lines
-                            let mut current_parent_id = block_id.to_string();
-                            let mut stack = vec![current_parent_id.clone()];
-
-                            for line in lines {
-                                let current_level = line.chars().take_while(|&c| c == '#').count();
-                                if current_level > 0 {
-                                    while stack.len() > current_level {
-                                        stack.pop();
-                                    }
-                                    current_parent_id = stack.last().cloned().unwrap_or_else(|| block_id.to_string());
-                                } else if line.starts_with("- ") {
-                                    current_parent_id = stack.last().cloned().unwrap_or_else(|| block_id.to_string());
-                                }
-
-                                let body = json!({
-                                    "method": "logseq.Editor.insertBlock",
-                                    "args": [
-                                        current_parent_id,
-                                        line,
-                                        {"isPageBlock": false}
-                                    ]
-                                });
-
-                                let res = self.client.post(api_url()).json(&body).send();
-                                if let Ok(response) = res {
-                                    if response.status().is_success() {
-                                        debug!("Sub-block added: {}", line);
-                                        if let Some(new_id) = response.json::<HashMap<String, serde_json::Value>>()
-                                            .unwrap().get("uuid").and_then(|v| v.as_str()) {
-                                            if current_level > 0 {
-                                                stack.push(new_id.to_string());
-                                            }
-                                        }
-                                    } else {
-                                        debug!("Failed to add sub-block: {:?}", response.text().unwrap());
-                                    }
-                                } else {
-                                    debug!("Error adding sub-block: {:?}", res.unwrap_err());
-                                }
-                            }
-                        }
-                    } else {
-                        let error_text = response.text().unwrap();
-                        debug!("Failed to add child node: {:?}", error_text);
-                    }
-                }
-                Err(error) => {
-                    debug!("Error: {:?}", error);
-                }
-            }
-        }
+        let Some(first_line) = lines.next() else {
+            return Ok(None);
+        };
+
+        let block: Block = self.call_as("logseq.Editor.insertBlock", json!([parent_id, first_line, {"isPageBlock": false}]))?;
+        debug!("Child node added!");
+        self.insert_nested_lines(&block.uuid, lines).map_err(|err| self.discard_partial(&block.uuid, err))?;
+        Ok(Some(block.uuid))
     }
 
-    pub fn delete_entry(&self, entry_id: &str, _date: Option<String>) {
-        eprintln!("Deleting entry with ID: {}", entry_id);
-
-        let query = json!({
-            "method": "logseq.Editor.removeBlock",
-            "args": [
-                entry_id
-            ]
-        });
-
-        let res = self.client.post(api_url()).json(&query).send();
+    /// Remove a block whose nested lines could not all be inserted, so that
+    /// trying again does not add it twice. Returns `err` for `map_err`.
+    fn discard_partial(&self, uuid: &str, err: String) -> String {
+        match self.call("logseq.Editor.removeBlock", json!([uuid])) {
+            Ok(_) => err,
+            Err(remove_err) => format!("{}; the partly added block {} could not be removed: {}", err, uuid, remove_err),
+        }
+    }
 
-        match res {
-            Ok(response) => {
-                if response.status().is_success() {
-                    eprintln!("Entry deleted.");
-                } else {
-                    eprintln!("Failed to delete entry: {:?}", response.text().unwrap());
+    /// Insert `lines` below `block_id`: `#` headings nest the lines after
+    /// them, other lines go below the closest heading. Stops at the first
+    /// line that cannot be inserted.
+    fn insert_nested_lines<'a>(&self, block_id: &str, lines: impl Iterator<Item = &'a str>) -> Result<(), String> {
+        let mut current_parent_id = block_id.to_string();
+        let mut stack = vec![current_parent_id.clone()];
+
+        for line in lines {
+            let current_level = line.chars().take_while(|&c| c == '#').count();
+            if current_level > 0 {
+                while stack.len() > current_level {
+                    stack.pop();
                 }
+                current_parent_id = stack.last().cloned().unwrap_or_else(|| block_id.to_string());
+            } else if line.starts_with("- ") {
+                current_parent_id = stack.last().cloned().unwrap_or_else(|| block_id.to_string());
             }
-            Err(error) => {
-                eprintln!("Error: {:?}", error);
+
+            let new_block = self
+                .call_as::<Block>("logseq.Editor.insertBlock", json!([current_parent_id, line, {"isPageBlock": false}]))
+                .map_err(|err| format!("Failed to add '{}': {}", preview(line), err))?;
+            debug!("Sub-block added: {}", line);
+            if current_level > 0 {
+                stack.push(new_block.uuid);
             }
         }
+        Ok(())
     }
 
-    pub fn get_page_blocks_tree(&self, page_id: &str) -> Vec<Block> {
-        let query = json!({
-            "method": "logseq.Editor.getPageBlocksTree",
-            "args": [
-                page_id
-            ]
-        });
+    /// Uuid of the page called `name`, creating the page if needed.
+    fn get_or_create_page(&self, name: &str) -> Result<String, String> {
+        if let Some(result) = self.daemon_request("page_uuid", json!({"name": name})) {
+            return daemon::uuid_result(result);
+        }
 
-        let res = self.client.post(api_url()).json(&query).send();
+        let page = match self.call_as::<Option<Page>>("logseq.Editor.getPage", json!([name]))? {
+            Some(page) => page,
+            None => {
+                debug!("Creating page: {}", name);
+                self.call_as::<Page>(
+                    "logseq.Editor.createPage",
+                    json!([name, {}, {"redirect": false, "createFirstBlock": false}]),
+                )?
+            }
+        };
 
-        match res {
-            Ok(response) => {
-                let raw_response = response.text().unwrap();
-                debug!("Raw response: {}", raw_response);
+        page.uuid.ok_or_else(|| format!("Could not find or create page '{}'", name))
+    }
 
-                let blocks: Result<Vec<Block>, _> = serde_json::from_str(&raw_response);
-                match blocks {
-                    Ok(blocks) => blocks,
-                    Err(err) => {
-                        debug!("Failed to parse page blocks: {:?}", err);
-                        Vec::new()
-                    }
-                }
-            }
-            Err(error) => {
-                debug!("Error: {:?}", error);
-                Vec::new()
+    /// Insert `nodes` below `target`, keeping their nesting. With
+    /// `as_page_block` the top-level nodes are appended to the page `target`
+    /// instead of becoming children of a block.
+    fn insert_block_tree(&self, target: &str, nodes: &[BlockNode], as_page_block: bool) -> Result<(), String> {
+        for node in nodes {
+            let inserted: Block = self.call_as(
+                "logseq.Editor.insertBlock",
+                json!([target, node.content, {"isPageBlock": as_page_block, "sibling": false}]),
+            )?;
+            debug!("Block added: {}", node.content);
+
+            if !node.children.is_empty() {
+                self.insert_block_tree(&inserted.uuid, &node.children, false)?;
             }
         }
+        Ok(())
     }
-}
 
-fn get_journal_uuid(client: &reqwest::blocking::Client) -> Result<String, String> {
-    let now = Local::now();
-    let query = json!({
-        "method": "logseq.DB.datascriptQuery",
-        "args": [
-            "[:find (pull ?p [*])
-            :in $ ?today
-            :where [?b :block/page ?p]
-                   [?p :block/journal? true]
-                   [?p :block/journal-day ?d]
-                   [(>= ?d ?today)] ]",
-        &now.format("%Y%m%d").to_string()
-        ]
-    });
-
-    debug!("Request body for journal UUID: {}", query);
-
-    let journal_res = client.post(api_url()).json(&query).send();
-
-    match journal_res {
-        Ok(response) => {
-            let raw_response = response.text().unwrap();
-            debug!("Raw response: {}", raw_response);
-
-            let json: Result<Vec<Vec<HashMap<String, serde_json::Value>>>, _> = serde_json::from_str(&raw_response);
-            match json {
-                Ok(json) => {
-                    debug!("Journal response JSON: {:?}", json);
-                    let journal_id = json[0][0]["uuid"].as_str().unwrap().to_string();
-                    debug!("Journal UUID: {}", journal_id);
-                    Ok(journal_id)
-                }
-                Err(err) => {
-                    eprintln!("Failed to parse journal ID: {:?}", err);
-                    Err(format!("Failed to parse journal ID: {:?}", err))
-                }
+    pub fn delete_entry(&self, entry_id: &str, _date: Option<String>) {
+        eprintln!("Deleting entry with ID: {}", entry_id);
+
+        match self.call("logseq.Editor.removeBlock", json!([entry_id])) {
+            Ok(_) => eprintln!("Entry deleted."),
+            Err(err) => {
+                eprintln!("Failed to delete entry: {}", err);
+                std::process::exit(1);
             }
         }
-        Err(error) => {
-            eprintln!("Error getting journal ID: {:?}", error);
-            Err(format!("Error: {:?}", error))
-        }
+    }
+
+    pub fn get_page_blocks_tree(&self, page_id: &str) -> Result<Vec<Block>, String> {
+        let blocks = self.call_as::<Option<Vec<Block>>>("logseq.Editor.getPageBlocksTree", json!([page_id]))?;
+        Ok(blocks.unwrap_or_default())
     }
 }
 
@@ -734,10 +973,53 @@ fn api_url() -> String {
     env::var("LOGSEQ_API_URL").unwrap_or_else(|_| "http://127.0.0.1:12315/api".to_string())
 }
 
+impl Default for Client {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 fn main() {
     env_logger::init();
 
     let cli = Cli::parse();
+
+    if let Commands::Completions { shell } = &cli.command {
+        completions::print_completions(*shell);
+        return;
+    }
+    // Capturing must work without Logseq or its API key.
+    if let Commands::Capture { content, date } = &cli.command {
+        match capture::capture(content.clone(), date.clone()) {
+            Ok(path) => eprintln!("Note queued as {}.", path.display()),
+            Err(err) => {
+                eprintln!("Failed to capture: {}", err);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    // The shell hook runs after every command; only long ones reach Logseq.
+    if let Commands::LogCmd { hook, started, min_duration, .. } = &cli.command {
+        if let Some(shell) = hook {
+            print!("{}", worklog::hook_script(*shell));
+            return;
+        }
+        if started.is_some_and(|started| worklog::elapsed_since(started) < *min_duration) {
+            return;
+        }
+    }
+    if let Commands::Cache { command: CacheCommands::Clear } = &cli.command {
+        cache::clear_cache();
+        return;
+    }
+    // Completion runs on every Tab and must never fail loudly, so errors,
+    // such as a missing API key, are only logged.
+    if let Commands::Complete { kind, prefix } = &cli.command {
+        Client::new().print_completion_candidates(*kind, prefix);
+        return;
+    }
+
     let mut client = Client::new();
 
     match &cli.command {
@@ -757,11 +1039,17 @@ fn main() {
         Commands::OutputContent { entry_id, date } => {
             client.output_content(entry_id, date.clone());
         }
-        Commands::AddToStart { entry_id, content, date } => {
-            client.add_to_start(entry_id, content.clone(), date.clone());
+        Commands::AddToStart { entry_id, content, date: _, options } => {
+            if let Err(err) = client.add_to_start(entry_id, content.clone(), options) {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
         }
-        Commands::AppendToEnd { entry_id, content, date } => {
-            client.append_to_end(entry_id, content.clone(), date.clone());
+        Commands::AppendToEnd { entry_id, content, date: _, options } => {
+            if let Err(err) = client.append_to_end(entry_id, content.clone(), options) {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
         }
         Commands::AddChildNode { entry_id, content, date } => {
             client.add_child_node(entry_id, content.clone(), date.clone());
@@ -769,9 +1057,153 @@ fn main() {
         Commands::Delete { entry_id, date } => {
             client.delete_entry(entry_id, date.clone());
         }
+        Commands::Replace { entry_id, content } => {
+            client.replace_content(entry_id, content.clone());
+        }
+        Commands::Sub { pattern, replacement, scope, apply } => {
+            client.substitute(pattern, replacement, scope, *apply);
+        }
+        Commands::Edit { uuid, children } => {
+            client.edit_block(uuid, *children);
+        }
+        Commands::Tags => {
+            client.show_tags();
+        }
+        Commands::Refs { page, include_children } => {
+            client.show_page_refs(page, *include_children);
+        }
+        Commands::Backlinks { uuid } => {
+            client.show_backlinks(uuid);
+        }
+        Commands::Pages { filter } => {
+            client.list_pages(filter);
+        }
+        Commands::Tree { namespace } => {
+            client.show_namespace_tree(namespace.as_deref());
+        }
+        Commands::Page { command } => match command {
+            PageCommands::Add { name, content } => client.add_page(name, content.as_deref()),
+            PageCommands::Show { name } => client.show_page(name),
+            PageCommands::Info { name } => client.show_page_info(name),
+            PageCommands::Rename { old, new } => client.rename_page(old, new),
+            PageCommands::Alias { page, alias, remove } => client.alias_page(page, alias, *remove),
+            PageCommands::Merge { src, dst, dry_run } => client.merge_pages(src, dst, *dry_run),
+        },
+        Commands::Agenda { days, ics } => {
+            client.show_agenda(*days, *ics);
+        }
+        Commands::ImportIcs { file } => {
+            client.import_ics(file);
+        }
+        Commands::ImportCsv { file, map, date_format, delimiter } => {
+            client.import_csv(file, map, date_format, *delimiter);
+        }
+        Commands::Template { command } => match command {
+            TemplateCommands::List => client.list_templates(),
+            TemplateCommands::Apply { name, page, date, vars } => {
+                client.apply_template(name, page.clone(), date.clone(), vars.clone());
+            }
+        },
+        Commands::Serve { .. } => {
+            client.serve_stdio();
+        }
+        Commands::Gateway { listen, config, audit_log } => {
+            let config = config.clone().unwrap_or_else(gateway::default_config_path);
+            let audit_log = audit_log.clone().unwrap_or_else(gateway::default_audit_log_path);
+            if let Err(err) = gateway::run(client, listen, &config, &audit_log) {
+                eprintln!("Failed to start gateway: {}", err);
+                std::process::exit(1);
+            }
+        }
+        Commands::Webhook { listen, rules } => {
+            if let Err(err) = webhook::run(client, listen, rules) {
+                eprintln!("Failed to start webhook receiver: {}", err);
+                std::process::exit(1);
+            }
+        }
+        Commands::Watch { path, page, journal: _, interval, from_start, state } => {
+            let target = match page {
+                Some(page) => watch::WatchTarget::Page(page.clone()),
+                None => watch::WatchTarget::Journal,
+            };
+            let state = state.clone().unwrap_or_else(watch::default_state_path);
+            client.watch(path, target, *interval, *from_start, &state);
+        }
+        Commands::LogGit { repos, since, author } => {
+            client.log_git(repos, since.as_deref(), author.as_deref());
+        }
+        Commands::LogCmd { command, exit_status, started, .. } => {
+            let run = worklog::CommandRun {
+                command: command.clone().unwrap_or_default(),
+                exit: exit_status.unwrap_or_default(),
+                started: started.unwrap_or_default(),
+            };
+            client.log_cmd(&run);
+        }
+        Commands::Clock { command } => match command {
+            ClockCommands::In { uuid } => client.clock_in(uuid),
+            ClockCommands::Out { uuid, done } => client.clock_out(uuid.as_deref(), *done),
+            ClockCommands::Report { from, to, format } => client.clock_report(from.as_deref(), to.as_deref(), *format),
+        },
+        Commands::Attach { file, to, journal: _, graph } => {
+            let target = match to {
+                Some(uuid) => assets::AttachTarget::Block(uuid.clone()),
+                None => assets::AttachTarget::Journal,
+            };
+            client.attach(file, target, graph.as_deref());
+        }
+        Commands::Assets { command } => match command {
+            AssetsCommands::Orphaned { graph } => client.list_orphaned_assets(graph.as_deref()),
+        },
+        Commands::Lint { config, page, cached, fix } => {
+            client.lint(config.as_deref(), page.as_deref(), *cached, *fix);
+        }
+        Commands::Stats { top, json } => {
+            client.show_stats(*top, *json);
+        }
+        Commands::Cards { command } => match command {
+            CardsCommands::Review { limit } => client.review_cards(*limit),
+        },
+        Commands::Cache { command } => match command {
+            CacheCommands::Sync { full } => client.sync_cache_command(*full),
+            CacheCommands::Clear => unreachable!(),
+        },
+        Commands::Completions { .. } | Commands::Capture { .. } | Commands::Complete { .. } => unreachable!(),
+        Commands::Flush => {
+            client.flush();
+        }
+        Commands::Daemon { interval } => {
+            if let Err(err) = daemon::run(*interval) {
+                eprintln!("Failed to start daemon: {}", err);
+                std::process::exit(1);
+            }
+        }
     }
 }
 
+/// Content from the flag, or from stdin without its trailing newlines.
+fn read_input(input_content: Option<String>) -> String {
+    let content = match input_content {
+        Some(content) => content,
+        None => {
+            let mut content = String::new();
+            io::stdin().read_to_string(&mut content).unwrap();
+            content
+        }
+    };
+    content.trim_end_matches(['\r', '\n']).to_string()
+}
+
+/// The first few words of a block, for one-line listings.
+fn preview(content: &str) -> String {
+    content.split_whitespace().take(10).collect::<Vec<_>>().join(" ")
+}
+
+/// Quote `value` as an EDN string for use as a datascript query input.
+fn edn_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
 fn format_output(content: &str) -> String {
     let mut formatted_content = String::new();
     let mut header_stack: Vec<(usize, String)> = Vec::new();
@@ -794,10 +1226,6 @@ fn format_output(content: &str) -> String {
             formatted_content.push_str(&"  ".repeat(current_header_level));
             formatted_content.push_str(&trimmed_line);
             formatted_content.push('\n');
-        } else if trimmed_line.starts_with('-') {
-            formatted_content.push_str(&"  ".repeat(last_indent_level + 1));
-            formatted_content.push_str(&trimmed_line);
-            formatted_content.push('\n');
         } else {
             formatted_content.push_str(&"  ".repeat(last_indent_level + 1));
             formatted_content.push_str(&trimmed_line);
@@ -0,0 +1,363 @@
+//! Typed views of the entities returned by the Logseq API and by
+//! datascript pulls. Keys arrive camelCased (`pathRefs`, `journalDay`) with
+//! the `?` of boolean attributes kept (`journal?`, `collapsed?`).
+
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// A reference to another entity by its database id, e.g. `{"id": 42}`.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Hash)]
+pub struct DbRef {
+    pub id: i64,
+}
+
+/// A child block that was not loaded, sent as `["uuid", "…"]`.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(from = "(String, String)", into = "(String, String)")]
+pub struct BlockRef {
+    pub uuid: String,
+}
+
+impl From<(String, String)> for BlockRef {
+    fn from((_, uuid): (String, String)) -> Self {
+        BlockRef { uuid }
+    }
+}
+
+impl From<BlockRef> for (String, String) {
+    fn from(block_ref: BlockRef) -> Self {
+        ("uuid".to_string(), block_ref.uuid)
+    }
+}
+
+/// Children are only uuids unless the block was fetched with
+/// `includeChildren` or as part of a page tree.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum BlockChild {
+    Ref(BlockRef),
+    Loaded(Box<Block>),
+}
+
+impl BlockChild {
+    pub fn uuid(&self) -> &str {
+        match self {
+            BlockChild::Loaded(block) => &block.uuid,
+            BlockChild::Ref(block_ref) => &block_ref.uuid,
+        }
+    }
+}
+
+/// A day as Logseq stores it for journals, scheduled and deadline dates:
+/// the integer `yyyymmdd`.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(transparent)]
+pub struct JournalDay(pub i64);
+
+impl JournalDay {
+    pub fn from_date(date: NaiveDate) -> Self {
+        JournalDay(date.format("%Y%m%d").to_string().parse().expect("formatted date is numeric"))
+    }
+
+    pub fn date(self) -> Option<NaiveDate> {
+        NaiveDate::from_ymd_opt((self.0 / 10000) as i32, (self.0 / 100 % 100) as u32, (self.0 % 100) as u32)
+    }
+}
+
+impl fmt::Display for JournalDay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.0 / 10000, self.0 / 100 % 100, self.0 % 100)
+    }
+}
+
+/// Milliseconds since the epoch, as in `createdAt` and `updatedAt`.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(transparent)]
+pub struct Timestamp(pub i64);
+
+impl Timestamp {
+    pub fn to_local(self) -> Option<DateTime<Local>> {
+        Local.timestamp_millis_opt(self.0).single()
+    }
+}
+
+/// Task marker at the start of a block, e.g. `TODO` or `DONE`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(from = "String", into = "String")]
+pub enum Marker {
+    Todo,
+    Doing,
+    Done,
+    Later,
+    Now,
+    Waiting,
+    Wait,
+    Canceled,
+    InProgress,
+    Started,
+    Other(String),
+}
+
+impl Marker {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Marker::Todo => "TODO",
+            Marker::Doing => "DOING",
+            Marker::Done => "DONE",
+            Marker::Later => "LATER",
+            Marker::Now => "NOW",
+            Marker::Waiting => "WAITING",
+            Marker::Wait => "WAIT",
+            Marker::Canceled => "CANCELED",
+            Marker::InProgress => "IN-PROGRESS",
+            Marker::Started => "STARTED",
+            Marker::Other(marker) => marker,
+        }
+    }
+
+    /// Whether the task is finished, one way or another.
+    pub fn is_closed(&self) -> bool {
+        matches!(self, Marker::Done | Marker::Canceled)
+    }
+}
+
+impl From<String> for Marker {
+    fn from(marker: String) -> Self {
+        match marker.as_str() {
+            "TODO" => Marker::Todo,
+            "DOING" => Marker::Doing,
+            "DONE" => Marker::Done,
+            "LATER" => Marker::Later,
+            "NOW" => Marker::Now,
+            "WAITING" => Marker::Waiting,
+            "WAIT" => Marker::Wait,
+            "CANCELED" | "CANCELLED" => Marker::Canceled,
+            "IN-PROGRESS" => Marker::InProgress,
+            "STARTED" => Marker::Started,
+            _ => Marker::Other(marker),
+        }
+    }
+}
+
+impl From<Marker> for String {
+    fn from(marker: Marker) -> Self {
+        marker.as_str().to_string()
+    }
+}
+
+impl fmt::Display for Marker {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A property value: Logseq sends text, numbers, booleans, or lists for
+/// comma separated page references.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(untagged)]
+pub enum PropertyValue {
+    Bool(bool),
+    Number(serde_json::Number),
+    Text(String),
+    List(Vec<PropertyValue>),
+    Other(serde_json::Value),
+}
+
+impl PropertyValue {
+    /// The value as it would be written after `key:: `.
+    pub fn as_text(&self) -> String {
+        match self {
+            PropertyValue::Bool(value) => value.to_string(),
+            PropertyValue::Number(value) => value.to_string(),
+            PropertyValue::Text(value) => value.clone(),
+            PropertyValue::List(values) => values.iter().map(PropertyValue::as_text).collect::<Vec<_>>().join(", "),
+            PropertyValue::Other(value) => value.to_string(),
+        }
+    }
+
+    /// Individual values, splitting lists.
+    pub fn values(&self) -> Vec<String> {
+        match self {
+            PropertyValue::List(values) => values.iter().map(PropertyValue::as_text).collect(),
+            value => vec![value.as_text()],
+        }
+    }
+}
+
+pub type Properties = BTreeMap<String, PropertyValue>;
+
+/// Only `uuid` is required: pulls and API calls return partial blocks, but
+/// every one of them names the block it describes.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Block {
+    pub id: Option<i64>,
+    pub uuid: String,
+    #[serde(default)]
+    pub content: String,
+    pub format: Option<String>,
+    pub marker: Option<Marker>,
+    pub priority: Option<String>,
+    pub scheduled: Option<JournalDay>,
+    pub deadline: Option<JournalDay>,
+    pub page: Option<Page>,
+    pub parent: Option<DbRef>,
+    pub left: Option<DbRef>,
+    #[serde(default)]
+    pub refs: Vec<DbRef>,
+    #[serde(default)]
+    pub path_refs: Vec<DbRef>,
+    #[serde(default)]
+    pub properties: Properties,
+    #[serde(default)]
+    pub properties_order: Vec<String>,
+    pub created_at: Option<Timestamp>,
+    pub updated_at: Option<Timestamp>,
+    #[serde(default, rename = "collapsed?")]
+    pub collapsed: bool,
+    /// The first block of a page when it only holds page properties.
+    #[serde(default, rename = "preBlock?")]
+    pub pre_block: bool,
+    #[serde(default)]
+    pub children: Vec<BlockChild>,
+}
+
+impl Block {
+    /// Children that were loaded along with this block.
+    pub fn loaded_children(&self) -> impl Iterator<Item = &Block> {
+        self.children.iter().filter_map(|child| match child {
+            BlockChild::Loaded(block) => Some(block.as_ref()),
+            BlockChild::Ref(_) => None,
+        })
+    }
+
+    /// The property written as `key::`. The API camelCases property names
+    /// (`card-ease-factor` arrives as `cardEaseFactor`), so both are tried.
+    pub fn property(&self, key: &str) -> Option<&PropertyValue> {
+        self.properties.get(key).or_else(|| self.properties.get(&camel_case(key)))
+    }
+}
+
+/// `card-ease-factor` as `cardEaseFactor`.
+fn camel_case(key: &str) -> String {
+    let mut parts = key.split('-');
+    let mut camel = parts.next().unwrap_or_default().to_string();
+    for part in parts {
+        let mut chars = part.chars();
+        if let Some(first) = chars.next() {
+            camel.extend(first.to_uppercase());
+            camel.push_str(chars.as_str());
+        }
+    }
+    camel
+}
+
+/// A page. When pulled as `{:block/page [:block/original-name]}` or
+/// returned as a block's `page`, only some of the fields are present.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Page {
+    pub id: Option<i64>,
+    pub uuid: Option<String>,
+    pub name: Option<String>,
+    pub original_name: Option<String>,
+    #[serde(rename = "journal?")]
+    pub journal: bool,
+    pub journal_day: Option<JournalDay>,
+    pub format: Option<String>,
+    pub properties: Properties,
+    pub file: Option<DbRef>,
+    pub namespace: Option<DbRef>,
+    pub created_at: Option<Timestamp>,
+    pub updated_at: Option<Timestamp>,
+}
+
+impl Page {
+    /// The name as the user wrote it, falling back to the lower-cased one.
+    pub fn display_name(&self) -> &str {
+        self.original_name.as_deref().or(self.name.as_deref()).unwrap_or("")
+    }
+}
+
+/// Content for a block and its children, used when inserting whole trees.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BlockNode {
+    pub content: String,
+    pub children: Vec<BlockNode>,
+}
+
+impl From<&Block> for BlockNode {
+    fn from(block: &Block) -> Self {
+        BlockNode {
+            content: block.content.clone(),
+            children: block.loaded_children().map(BlockNode::from).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn deserializes_a_block() {
+        let block: Block = serde_json::from_value(json!({
+            "id": 7,
+            "uuid": "b1",
+            "content": "TODO [#A] write tests",
+            "marker": "TODO",
+            "priority": "A",
+            "scheduled": 20240115,
+            "page": {"id": 3, "originalName": "Work", "journal?": false},
+            "pathRefs": [{"id": 3}],
+            "properties": {"cardEaseFactor": 2.5, "tags": ["rust", "cli"]},
+            "collapsed?": true,
+            "preBlock?": false,
+            "updatedAt": 1705312800000i64
+        }))
+        .unwrap();
+        assert_eq!(block.id, Some(7));
+        assert_eq!(block.uuid, "b1");
+        assert_eq!(block.marker, Some(Marker::Todo));
+        assert_eq!(block.scheduled, Some(JournalDay(20240115)));
+        assert_eq!(block.page.as_ref().map(Page::display_name), Some("Work"));
+        assert_eq!(block.path_refs, vec![DbRef { id: 3 }]);
+        assert_eq!(block.property("card-ease-factor").map(PropertyValue::as_text).as_deref(), Some("2.5"));
+        assert_eq!(block.property("tags").map(PropertyValue::values), Some(vec!["rust".to_string(), "cli".to_string()]));
+        assert!(block.collapsed);
+        assert!(block.children.is_empty());
+    }
+
+    #[test]
+    fn requires_a_uuid() {
+        let err = serde_json::from_value::<Block>(json!({"content": "no uuid"})).unwrap_err();
+        assert!(err.to_string().contains("uuid"), "{}", err);
+    }
+
+    #[test]
+    fn tells_child_references_from_loaded_children() {
+        let block: Block = serde_json::from_value(json!({
+            "uuid": "parent",
+            "children": [
+                ["uuid", "unloaded"],
+                {"uuid": "loaded", "content": "child", "children": [["uuid", "grandchild"]]}
+            ]
+        }))
+        .unwrap();
+        assert!(matches!(&block.children[0], BlockChild::Ref(BlockRef { uuid }) if uuid == "unloaded"));
+        assert!(matches!(&block.children[1], BlockChild::Loaded(child) if child.content == "child"));
+        assert_eq!(block.children.iter().map(BlockChild::uuid).collect::<Vec<_>>(), ["unloaded", "loaded"]);
+        let loaded: Vec<&Block> = block.loaded_children().collect();
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded[0].children[0].uuid(), "grandchild");
+    }
+
+    #[test]
+    fn serializes_child_references_back_as_pairs() {
+        let child = BlockChild::Ref(BlockRef { uuid: "u1".to_string() });
+        assert_eq!(serde_json::to_value(&child).unwrap(), json!(["uuid", "u1"]));
+    }
+}
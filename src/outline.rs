@@ -0,0 +1,140 @@
+use crate::model::BlockNode;
+
+/// Parse a Markdown outline (`- ` bullets indented by two spaces or tabs)
+/// into a block tree. Lines without a bullet continue the previous block,
+/// keeping any indentation beyond the bullet's own.
+pub fn parse_outline(text: &str) -> Vec<BlockNode> {
+    let mut roots: Vec<BlockNode> = Vec::new();
+    // Path of child indexes from the roots to the last inserted node, with
+    // the indentation level of each.
+    let mut path: Vec<(usize, usize)> = Vec::new();
+
+    for line in text.lines() {
+        let expanded = line.replace('\t', "  ");
+        let trimmed = expanded.trim_start_matches(' ');
+        let indent = expanded.len() - trimmed.len();
+
+        let content = match trimmed.strip_prefix("- ").or_else(|| (trimmed == "-").then_some("")) {
+            Some(content) => content,
+            None => {
+                if let Some(&(level, _)) = path.last() {
+                    let node = node_at(&mut roots, &path).expect("path points at a node");
+                    node.content.push('\n');
+                    node.content.push_str(&expanded[indent.min(level + 2)..]);
+                    continue;
+                }
+                if trimmed.trim().is_empty() {
+                    continue;
+                }
+                trimmed
+            }
+        };
+
+        while path.last().is_some_and(|(level, _)| *level >= indent) {
+            path.pop();
+        }
+
+        let node = BlockNode { content: content.to_string(), children: Vec::new() };
+        let siblings = match node_at(&mut roots, &path) {
+            Some(parent) => &mut parent.children,
+            None => &mut roots,
+        };
+        siblings.push(node);
+        path.push((indent, siblings.len() - 1));
+    }
+
+    trim_contents(&mut roots);
+    roots
+}
+
+/// Blank lines are kept as part of a block, so drop the ones trailing it.
+fn trim_contents(nodes: &mut [BlockNode]) {
+    for node in nodes {
+        let trimmed_len = node.content.trim_end().len();
+        node.content.truncate(trimmed_len);
+        trim_contents(&mut node.children);
+    }
+}
+
+fn node_at<'a>(roots: &'a mut [BlockNode], path: &[(usize, usize)]) -> Option<&'a mut BlockNode> {
+    let ((_, first), rest) = path.split_first()?;
+    let mut node = roots.get_mut(*first)?;
+    for (_, index) in rest {
+        node = node.children.get_mut(*index)?;
+    }
+    Some(node)
+}
+
+/// Render a block tree as a Markdown outline that `parse_outline` reads
+/// back. Continuation lines of multi-line blocks are indented under their
+/// bullet.
+pub fn render_outline(nodes: &[BlockNode]) -> String {
+    let mut text = String::new();
+    render_nodes(nodes, 0, &mut text);
+    text
+}
+
+fn render_nodes(nodes: &[BlockNode], level: usize, text: &mut String) {
+    let indent = "  ".repeat(level);
+    for node in nodes {
+        let mut lines = node.content.lines();
+        text.push_str(&indent);
+        text.push_str("- ");
+        text.push_str(lines.next().unwrap_or(""));
+        text.push('\n');
+        for line in lines {
+            text.push_str(&indent);
+            text.push_str("  ");
+            text.push_str(line);
+            text.push('\n');
+        }
+        render_nodes(&node.children, level + 1, text);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(content: &str, children: Vec<BlockNode>) -> BlockNode {
+        BlockNode { content: content.to_string(), children }
+    }
+
+    #[test]
+    fn parses_what_it_renders() {
+        let tree = vec![
+            node(
+                "first",
+                vec![
+                    node("nested\nsecond line", vec![node("deeper", vec![]), node("  indented text", vec![])]),
+                    node("", vec![]),
+                ],
+            ),
+            node("second", vec![]),
+        ];
+        assert_eq!(parse_outline(&render_outline(&tree)), tree);
+    }
+
+    #[test]
+    fn parses_nested_indentation() {
+        let text = "- a\n  - b\n    - c\n  - d\n\t- e\n- f\n";
+        let expected = vec![
+            node("a", vec![node("b", vec![node("c", vec![])]), node("d", vec![]), node("e", vec![])]),
+            node("f", vec![]),
+        ];
+        assert_eq!(parse_outline(text), expected);
+    }
+
+    #[test]
+    fn continues_blocks_on_lines_without_bullets() {
+        let text = "- a\n  more of a\n\n  - b\n      code in b\n";
+        let expected = vec![node("a\nmore of a", vec![node("b\n  code in b", vec![])])];
+        assert_eq!(parse_outline(text), expected);
+    }
+
+    #[test]
+    fn renders_continuation_lines_under_their_bullet() {
+        let tree = vec![node("a\nmore", vec![node("b", vec![])])];
+        assert_eq!(render_outline(&tree), "- a\n  more\n  - b\n");
+    }
+}
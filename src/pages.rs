@@ -0,0 +1,405 @@
+use chrono::{Duration, Local};
+use clap::Args;
+use log::debug;
+use serde_json::json;
+use std::collections::{BTreeMap, HashSet};
+
+use crate::model::{Page, Timestamp};
+use crate::outline::parse_outline;
+use crate::{edn_string, Client};
+
+/// Filters for `rlu pages`; all given filters have to match.
+#[derive(Args)]
+pub struct PageFilter {
+    /// Only pages in this namespace, e.g. `eng/rlu`
+    #[arg(long)]
+    namespace: Option<String>,
+    /// Only pages with this tag in their `tags::` property
+    #[arg(long)]
+    tag: Option<String>,
+    /// Only journal pages
+    #[arg(long, conflicts_with = "no_journals")]
+    journals: bool,
+    /// Leave out journal pages
+    #[arg(long)]
+    no_journals: bool,
+    /// Only pages no block links to
+    #[arg(long)]
+    orphan: bool,
+    /// Only pages updated in the last DAYS days, most recent first
+    #[arg(long, value_name = "DAYS")]
+    recent: Option<i64>,
+}
+
+impl PageFilter {
+    fn matches(&self, page: &Page) -> bool {
+        let name = page.display_name().to_lowercase();
+
+        if let Some(namespace) = &self.namespace {
+            let prefix = format!("{}/", namespace.trim_end_matches('/').to_lowercase());
+            if !name.starts_with(&prefix) {
+                return false;
+            }
+        }
+        if let Some(tag) = &self.tag {
+            let tagged = page
+                .properties
+                .get("tags")
+                .is_some_and(|tags| tags.values().iter().any(|value| value.eq_ignore_ascii_case(tag)));
+            if !tagged {
+                return false;
+            }
+        }
+        if (self.journals && !page.journal) || (self.no_journals && page.journal) {
+            return false;
+        }
+        if let Some(days) = self.recent {
+            let since = (Local::now() - Duration::days(days)).timestamp_millis();
+            if page.updated_at.is_none_or(|updated| updated.0 < since) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Normalize a namespace path: `eng / rlu/design/` -> `eng/rlu/design`.
+pub fn namespace_path(name: &str) -> String {
+    name.split('/')
+        .map(str::trim)
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// `name` without `prefix`, compared case-insensitively one character at a
+/// time, since lower-casing can change a name's length in bytes.
+fn strip_prefix_ignore_case<'a>(name: &'a str, prefix: &str) -> Option<&'a str> {
+    let mut chars = name.chars();
+    for expected in prefix.chars() {
+        let actual = chars.next()?;
+        if !actual.to_lowercase().eq(expected.to_lowercase()) {
+            return None;
+        }
+    }
+    Some(chars.as_str())
+}
+
+/// One level of the namespace hierarchy, keyed by lower-cased segment.
+#[derive(Default)]
+struct NamespaceNode {
+    name: String,
+    children: BTreeMap<String, NamespaceNode>,
+}
+
+impl NamespaceNode {
+    fn insert(&mut self, segments: &[&str]) {
+        if let Some((first, rest)) = segments.split_first() {
+            let child = self.children.entry(first.to_lowercase()).or_default();
+            // A page's own name wins over how its children spell it.
+            if child.name.is_empty() || rest.is_empty() {
+                child.name = first.to_string();
+            }
+            child.insert(rest);
+        }
+    }
+
+    fn render(&self, content: &mut String, indent_level: usize) {
+        for child in self.children.values() {
+            content.push_str(&"  ".repeat(indent_level));
+            content.push_str(&child.name);
+            content.push('\n');
+            child.render(content, indent_level + 1);
+        }
+    }
+}
+
+fn format_timestamp(timestamp: Option<Timestamp>) -> String {
+    timestamp
+        .and_then(Timestamp::to_local)
+        .map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+impl Client {
+    /// Database ids of all pages referenced by at least one block.
+    fn referenced_page_ids(&self) -> Result<HashSet<i64>, String> {
+        let rows = self.datascript_query(
+            "[:find ?p
+             :where
+             [_ :block/refs ?p]
+             [?p :block/name]]",
+            &[],
+        )?;
+        Ok(rows.iter().filter_map(|row| row.get(0)?.as_i64()).collect())
+    }
+
+    /// Run a `(count ?b)` query for the page called `name`, 0 when nothing
+    /// matches.
+    fn count_for_page(&self, query: &str, name: &str) -> Result<i64, String> {
+        let rows = self.datascript_query(query, &[json!(edn_string(&name.to_lowercase()))])?;
+        Ok(rows.first().and_then(|row| row.get(0)).and_then(|v| v.as_i64()).unwrap_or(0))
+    }
+
+    pub fn list_pages(&self, filter: &PageFilter) {
+        let pages = match self.call_as::<Option<Vec<Page>>>("logseq.Editor.getAllPages", json!([])) {
+            Ok(pages) => pages.unwrap_or_default(),
+            Err(err) => {
+                eprintln!("Failed to list pages: {}", err);
+                return;
+            }
+        };
+        debug!("Got {} pages", pages.len());
+
+        let referenced = if filter.orphan {
+            match self.referenced_page_ids() {
+                Ok(ids) => ids,
+                Err(err) => {
+                    eprintln!("Failed to get page references: {}", err);
+                    return;
+                }
+            }
+        } else {
+            HashSet::new()
+        };
+
+        let mut pages: Vec<Page> = pages
+            .into_iter()
+            .filter(|page| filter.matches(page))
+            .filter(|page| !filter.orphan || page.id.is_some_and(|id| !referenced.contains(&id)))
+            .collect();
+
+        if filter.recent.is_some() {
+            pages.sort_by_key(|page| std::cmp::Reverse(page.updated_at));
+            for page in pages {
+                println!("{}\t{}", format_timestamp(page.updated_at), page.display_name());
+            }
+        } else {
+            pages.sort_by_key(|page| page.display_name().to_lowercase());
+            for page in pages {
+                println!("{}", page.display_name());
+            }
+        }
+    }
+
+    pub fn show_page_info(&self, name: &str) {
+        let page = match self.call_as::<Option<Page>>("logseq.Editor.getPage", json!([name])) {
+            Ok(Some(page)) => page,
+            Ok(None) => {
+                eprintln!("Page {} not found.", name);
+                return;
+            }
+            Err(err) => {
+                eprintln!("Failed to get page: {}", err);
+                return;
+            }
+        };
+
+        let file = self.datascript_query(
+            "[:find ?path
+             :in $ ?name
+             :where
+             [?p :block/name ?name]
+             [?p :block/file ?f]
+             [?f :file/path ?path]]",
+            &[json!(edn_string(&name.to_lowercase()))],
+        );
+        let blocks = self.count_for_page(
+            "[:find (count ?b)
+             :in $ ?name
+             :where
+             [?p :block/name ?name]
+             [?b :block/page ?p]]",
+            name,
+        );
+        let backlinks = self.count_for_page(
+            "[:find (count ?b)
+             :in $ ?name
+             :where
+             [?p :block/name ?name]
+             [?b :block/refs ?p]
+             [?b :block/page ?bp]
+             [(not= ?bp ?p)]]",
+            name,
+        );
+
+        println!("Name: {}", page.display_name());
+        if let Some(uuid) = &page.uuid {
+            println!("UUID: {}", uuid);
+        }
+        match page.journal_day.filter(|_| page.journal) {
+            Some(day) => println!("Journal: {}", day),
+            None => println!("Journal: no"),
+        }
+        match file {
+            Ok(rows) => match rows.first().and_then(|row| row.get(0)).and_then(|v| v.as_str()) {
+                Some(path) => println!("File: {}", path),
+                None => println!("File: -"),
+            },
+            Err(err) => eprintln!("Failed to get file: {}", err),
+        }
+        let aliases = page.properties.get("alias").map(|alias| alias.values()).unwrap_or_default();
+        if !aliases.is_empty() {
+            println!("Aliases: {}", aliases.join(", "));
+        }
+        match blocks {
+            Ok(count) => println!("Blocks: {}", count),
+            Err(err) => eprintln!("Failed to count blocks: {}", err),
+        }
+        match backlinks {
+            Ok(count) => println!("Backlinks: {}", count),
+            Err(err) => eprintln!("Failed to count backlinks: {}", err),
+        }
+        println!("Created: {}", format_timestamp(page.created_at));
+        println!("Updated: {}", format_timestamp(page.updated_at));
+
+        if !page.properties.is_empty() {
+            println!("Properties:");
+            for (key, value) in &page.properties {
+                println!("  {}:: {}", key, value.as_text());
+            }
+        }
+    }
+
+    /// Print the namespace hierarchy of all pages, or only the part below
+    /// `namespace`.
+    pub fn show_namespace_tree(&self, namespace: Option<&str>) {
+        let pages = match self.call_as::<Option<Vec<Page>>>("logseq.Editor.getAllPages", json!([])) {
+            Ok(pages) => pages.unwrap_or_default(),
+            Err(err) => {
+                eprintln!("Failed to list pages: {}", err);
+                return;
+            }
+        };
+
+        let namespace = namespace.map(namespace_path);
+        let prefix = namespace.as_ref().map(|namespace| format!("{}/", namespace));
+        let mut root = NamespaceNode::default();
+        for page in pages.iter().filter(|page| !page.journal) {
+            let name = namespace_path(page.display_name());
+            match &prefix {
+                Some(prefix) => {
+                    if let Some(rest) = strip_prefix_ignore_case(&name, prefix) {
+                        root.insert(&rest.split('/').collect::<Vec<_>>());
+                    }
+                }
+                None => {
+                    root.insert(&name.split('/').collect::<Vec<_>>());
+                }
+            }
+        }
+        if namespace.is_none() {
+            // Pages outside any namespace are not part of the hierarchy.
+            root.children.retain(|_, node| !node.children.is_empty());
+        }
+
+        if root.children.is_empty() {
+            match namespace {
+                Some(namespace) => eprintln!("No pages in namespace {}.", namespace),
+                None => eprintln!("No namespaced pages."),
+            }
+            return;
+        }
+
+        let mut content = String::new();
+        match namespace {
+            Some(namespace) => {
+                content.push_str(&namespace);
+                content.push('\n');
+                root.render(&mut content, 1);
+            }
+            None => root.render(&mut content, 0),
+        }
+        print!("{}", content);
+    }
+
+    /// Uuid of the page `name`, creating it and, for a namespace path like
+    /// `eng/rlu/design`, any missing parent pages (`eng`, `eng/rlu`).
+    pub fn get_or_create_namespace_page(&self, name: &str) -> Result<String, String> {
+        let path = namespace_path(name);
+        let segments: Vec<&str> = path.split('/').collect();
+        if path.is_empty() {
+            return Err("Page name is empty".to_string());
+        }
+
+        let mut uuid = None;
+        for depth in 1..=segments.len() {
+            let name = segments[..depth].join("/");
+            uuid = match self.call_as::<Option<Page>>("logseq.Editor.getPage", json!([name]))? {
+                Some(page) => page.uuid,
+                None => {
+                    let page: Page = self.call_as(
+                        "logseq.Editor.createPage",
+                        json!([name, {}, {"redirect": false, "createFirstBlock": false}]),
+                    )?;
+                    eprintln!("Created page {}.", name);
+                    page.uuid
+                }
+            };
+        }
+        uuid.ok_or_else(|| format!("Could not find or create page '{}'", path))
+    }
+
+    /// Create a page (and its namespace parents) and optionally add content
+    /// to it, written as a Markdown outline.
+    pub fn add_page(&self, name: &str, content: Option<&str>) {
+        let uuid = match self.get_or_create_namespace_page(name) {
+            Ok(uuid) => uuid,
+            Err(err) => {
+                eprintln!("Failed to create page: {}", err);
+                return;
+            }
+        };
+        debug!("Page uuid: {}", uuid);
+
+        if let Some(content) = content {
+            let path = namespace_path(name);
+            match self.insert_block_tree(&path, &parse_outline(content), true) {
+                Ok(()) => eprintln!("Content added to {}.", path),
+                Err(err) => eprintln!("Failed to add content: {}", err),
+            }
+        }
+    }
+
+    /// Print the blocks of a page, indented like `output-content`.
+    pub fn show_page(&self, name: &str) {
+        let path = namespace_path(name);
+        match self.call_as::<Option<Page>>("logseq.Editor.getPage", json!([path])) {
+            Ok(Some(_)) => {}
+            Ok(None) => {
+                eprintln!("Page {} not found.", path);
+                return;
+            }
+            Err(err) => {
+                eprintln!("Failed to get page: {}", err);
+                return;
+            }
+        }
+
+        let mut content = String::new();
+        let collected = self.get_page_blocks_tree(&path).and_then(|blocks| {
+            blocks.iter().try_for_each(|block| self.collect_block_content(block, &mut content, 0))
+        });
+        if let Err(err) = collected {
+            eprintln!("Failed to get page blocks: {}", err);
+            return;
+        }
+        print!("{}", content);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_namespace_prefixes_ignoring_case() {
+        assert_eq!(strip_prefix_ignore_case("Eng/RLU/Design", "eng/rlu/"), Some("Design"));
+        assert_eq!(strip_prefix_ignore_case("eng/rlu/design", "Eng/Rlu/"), Some("design"));
+        // `ẞ` is three bytes long, its lower case `ß` only two.
+        assert_eq!(strip_prefix_ignore_case("ẞtraße/Plan", "ßtraße/"), Some("Plan"));
+        assert_eq!(strip_prefix_ignore_case("Éq/Notes", "éq/"), Some("Notes"));
+        assert_eq!(strip_prefix_ignore_case("eng/rlu", "eng/rlu/"), None);
+        assert_eq!(strip_prefix_ignore_case("engine/x", "eng/"), None);
+    }
+}
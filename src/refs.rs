@@ -0,0 +1,113 @@
+use serde_json::json;
+use std::collections::BTreeMap;
+
+use crate::model::{Block, JournalDay};
+use crate::{edn_string, preview, Client};
+
+pub const REFERENCING_BLOCKS_PULL: &str =
+    "[:block/uuid :block/content {:block/page [:block/original-name :block/journal-day]}]";
+
+/// Print referencing blocks under the page they live on.
+fn print_grouped(references: Vec<Block>) {
+    // Journal days newest first, then the other pages alphabetically.
+    let mut journals: BTreeMap<JournalDay, Vec<Block>> = BTreeMap::new();
+    let mut pages: BTreeMap<String, Vec<Block>> = BTreeMap::new();
+
+    for reference in references {
+        let page = reference.page.as_ref();
+        match page.and_then(|page| page.journal_day) {
+            Some(day) => journals.entry(day).or_default().push(reference),
+            None => {
+                let name = page.map(|page| page.display_name().to_string()).unwrap_or_default();
+                pages.entry(name).or_default().push(reference)
+            }
+        }
+    }
+
+    let groups = journals
+        .into_iter()
+        .rev()
+        .map(|(day, refs)| (day.to_string(), refs))
+        .chain(pages);
+
+    for (heading, refs) in groups {
+        println!("{}", heading);
+        for reference in refs {
+            println!("  {} {}", reference.uuid, preview(&reference.content));
+        }
+    }
+}
+
+impl Client {
+    /// Pages referenced from at least one block (`#tag` or `[[link]]`) with
+    /// the number of referencing blocks, most used first.
+    pub fn tag_counts(&self) -> Result<Vec<(String, i64)>, String> {
+        let rows = self.datascript_query(
+            "[:find ?name (count ?b)
+             :where
+             [?b :block/refs ?p]
+             [?p :block/original-name ?name]]",
+            &[],
+        )?;
+
+        let mut tags: Vec<(String, i64)> = rows
+            .iter()
+            .filter_map(|row| {
+                let name = row.get(0)?.as_str()?.to_string();
+                let count = row.get(1)?.as_i64()?;
+                Some((name, count))
+            })
+            .collect();
+        tags.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        Ok(tags)
+    }
+
+    pub fn show_tags(&self) {
+        match self.tag_counts() {
+            Ok(tags) => {
+                for (name, count) in tags {
+                    println!("{}\t{}", count, name);
+                }
+            }
+            Err(err) => eprintln!("Failed to list tags: {}", err),
+        }
+    }
+
+    /// Linked references of `page`. With `include_children` blocks nested
+    /// below a referencing block are listed too (`:block/path-refs`).
+    pub fn show_page_refs(&self, page: &str, include_children: bool) {
+        let attribute = if include_children { ":block/path-refs" } else { ":block/refs" };
+        let query = format!(
+            "[:find (pull ?b {pull})
+             :in $ ?name
+             :where
+             [?p :block/name ?name]
+             [?b {attribute} ?p]
+             [?b :block/page ?bp]
+             [(not= ?bp ?p)]]",
+            pull = REFERENCING_BLOCKS_PULL,
+            attribute = attribute,
+        );
+
+        match self.query_pulled(&query, &[json!(edn_string(&page.to_lowercase()))]) {
+            Ok(references) => print_grouped(references),
+            Err(err) => eprintln!("Failed to get references to {}: {}", page, err),
+        }
+    }
+
+    pub fn show_backlinks(&self, uuid: &str) {
+        let query = format!(
+            "[:find (pull ?b {pull})
+             :in $ ?uuid
+             :where
+             [?t :block/uuid ?uuid]
+             [?b :block/refs ?t]]",
+            pull = REFERENCING_BLOCKS_PULL,
+        );
+
+        match self.query_pulled(&query, &[json!(format!("#uuid {}", edn_string(uuid)))]) {
+            Ok(references) => print_grouped(references),
+            Err(err) => eprintln!("Failed to get backlinks of {}: {}", uuid, err),
+        }
+    }
+}
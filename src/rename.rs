@@ -0,0 +1,359 @@
+use log::debug;
+use regex::{NoExpand, Regex};
+use serde_json::json;
+use std::collections::BTreeMap;
+
+use crate::model::{Block, Page};
+use crate::refs::REFERENCING_BLOCKS_PULL;
+use crate::{edn_string, preview, Client};
+
+/// Point `[[src]]`, `#[[src]]` and `#src` links at `dst` instead, ignoring
+/// case like Logseq does.
+fn rewrite_page_refs(content: &str, src: &str, dst: &str) -> String {
+    let link = Regex::new(&format!(r"(?i)\[\[{}\]\]", regex::escape(src))).expect("escaped pattern is valid");
+    let content = link.replace_all(content, NoExpand(&format!("[[{}]]", dst)));
+
+    if src.contains(char::is_whitespace) {
+        return content.into_owned();
+    }
+    // The character after the tag is checked rather than matched, so that it
+    // can still start the next tag, as in `#src #src`.
+    let tag = Regex::new(&format!(r"(?i)(^|\s)#{}", regex::escape(src))).expect("escaped pattern is valid");
+    let dst_tag = if dst.contains(char::is_whitespace) { format!("#[[{}]]", dst) } else { format!("#{}", dst) };
+    tag.replace_all(&content, |caps: &regex::Captures| {
+        let found = caps.get(0).expect("the whole match is always present");
+        let ends_tag = content[found.end()..]
+            .chars()
+            .next()
+            .is_none_or(|c| c.is_whitespace() || ",.;:!?)".contains(c));
+        if ends_tag {
+            format!("{}{}", &caps[1], dst_tag)
+        } else {
+            found.as_str().to_string()
+        }
+    })
+    .into_owned()
+}
+
+/// Page properties that belong to the page itself and are never merged.
+const UNMERGED_PROPERTIES: &[&str] = &["title", "id"];
+
+/// `key:: value` lines of a properties block, keys lower-cased.
+fn property_lines(content: &str) -> Vec<(String, String)> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.trim().split_once("::")?;
+            let key = key.trim();
+            (!key.is_empty() && key.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_'))
+                .then(|| (key.to_lowercase(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+/// The properties of `dst` that change when those of `src` are merged in,
+/// with their new values. Values are combined as comma separated lists, the
+/// way `alias::` and `tags::` are written.
+fn merged_properties(dst: &[(String, String)], src: &[(String, String)]) -> Vec<(String, String)> {
+    let mut merged = Vec::new();
+    for (key, value) in src {
+        if UNMERGED_PROPERTIES.contains(&key.as_str()) || value.is_empty() {
+            continue;
+        }
+        let current = merged
+            .iter()
+            .chain(dst.iter())
+            .find(|(existing, _)| existing == key)
+            .map(|(_, value): &(String, String)| value.clone());
+        let Some(current) = current else {
+            merged.push((key.clone(), value.clone()));
+            continue;
+        };
+        let mut values: Vec<&str> = current.split(',').map(str::trim).filter(|value| !value.is_empty()).collect();
+        let before = values.len();
+        for value in value.split(',').map(str::trim) {
+            if !value.is_empty() && !values.iter().any(|existing| existing.eq_ignore_ascii_case(value)) {
+                values.push(value);
+            }
+        }
+        if values.len() > before {
+            let value = values.join(", ");
+            merged.retain(|(existing, _)| existing != key);
+            merged.push((key.clone(), value));
+        }
+    }
+    merged
+}
+
+impl Client {
+    pub fn require_page(&self, name: &str) -> Result<Page, String> {
+        self.call_as::<Option<Page>>("logseq.Editor.getPage", json!([name]))?
+            .ok_or_else(|| format!("Page {} not found.", name))
+    }
+
+    pub fn page_blocks(&self, name: &str) -> Result<Vec<Block>, String> {
+        Ok(self
+            .call_as::<Option<Vec<Block>>>("logseq.Editor.getPageBlocksTree", json!([name]))?
+            .unwrap_or_default())
+    }
+
+    pub fn rename_page(&self, old: &str, new: &str) {
+        let result = self
+            .require_page(old)
+            .and_then(|_| self.call("logseq.Editor.renamePage", json!([old, new])));
+        match result {
+            Ok(_) => eprintln!("Renamed page {} to {}.", old, new),
+            Err(err) => {
+                eprintln!("Failed to rename page: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    /// Add `alias` to (or with `remove` drop it from) the `alias::` property
+    /// of `page`, which lives in the page's first block.
+    pub fn alias_page(&self, page: &str, alias: &str, remove: bool) {
+        if let Err(err) = self.update_aliases(page, alias, remove) {
+            eprintln!("Failed to update aliases: {}", err);
+            std::process::exit(1);
+        }
+    }
+
+    fn update_aliases(&self, name: &str, alias: &str, remove: bool) -> Result<(), String> {
+        let page = self.require_page(name)?;
+        let mut aliases = page.properties.get("alias").map(|value| value.values()).unwrap_or_default();
+        let existing = aliases.iter().position(|existing| existing.eq_ignore_ascii_case(alias));
+
+        match (existing, remove) {
+            (Some(_), false) => {
+                eprintln!("{} is already an alias of {}.", alias, name);
+                return Ok(());
+            }
+            (None, true) => {
+                eprintln!("{} is not an alias of {}.", alias, name);
+                return Ok(());
+            }
+            (Some(index), true) => {
+                aliases.remove(index);
+            }
+            (None, false) => aliases.push(alias.to_string()),
+        }
+
+        let value = aliases.join(", ");
+        let blocks = self.page_blocks(name)?;
+        match blocks.first() {
+            Some(first) if first.pre_block => {
+                debug!("Updating properties block {}", first.uuid);
+                if aliases.is_empty() {
+                    self.call("logseq.Editor.removeBlockProperty", json!([first.uuid, "alias"]))?;
+                } else {
+                    self.call("logseq.Editor.upsertBlockProperty", json!([first.uuid, "alias", value]))?;
+                }
+            }
+            // Only adding can get here: without a properties block there
+            // were no aliases to remove.
+            Some(first) => {
+                self.call(
+                    "logseq.Editor.insertBlock",
+                    json!([first.uuid, format!("alias:: {}", value), {"sibling": true, "before": true}]),
+                )?;
+            }
+            None => {
+                self.call(
+                    "logseq.Editor.insertBlock",
+                    json!([name, format!("alias:: {}", value), {"isPageBlock": true}]),
+                )?;
+            }
+        }
+
+        if aliases.is_empty() {
+            eprintln!("Removed the last alias of {}.", name);
+        } else {
+            eprintln!("Aliases of {}: {}", name, value);
+        }
+        Ok(())
+    }
+
+    /// Move the top-level blocks of `src` to the end of `dst`, merge its page
+    /// properties into those of `dst`, point links to `src` at `dst` and
+    /// delete `src`. With `dry_run` only summarize.
+    pub fn merge_pages(&self, src: &str, dst: &str, dry_run: bool) {
+        if let Err(err) = self.merge_pages_inner(src, dst, dry_run) {
+            eprintln!("Failed to merge pages: {}", err);
+            std::process::exit(1);
+        }
+    }
+
+    fn merge_pages_inner(&self, src: &str, dst: &str, dry_run: bool) -> Result<(), String> {
+        let src_page = self.require_page(src)?;
+        let dst_page = self.require_page(dst)?;
+        if src_page.name == dst_page.name {
+            return Err("Source and destination are the same page".to_string());
+        }
+        let dst_name = dst_page.display_name().to_string();
+
+        let (properties, blocks): (Vec<Block>, Vec<Block>) =
+            self.page_blocks(src)?.into_iter().partition(|block| block.pre_block);
+        let mut dst_blocks = self.page_blocks(dst)?;
+        let merged = merged_properties(
+            &dst_blocks.first().filter(|first| first.pre_block).map(|first| property_lines(&first.content)).unwrap_or_default(),
+            &properties.iter().flat_map(|block| property_lines(&block.content)).collect::<Vec<_>>(),
+        );
+
+        let query = format!(
+            "[:find (pull ?b {pull})
+             :in $ ?name
+             :where
+             [?p :block/name ?name]
+             [?b :block/refs ?p]]",
+            pull = REFERENCING_BLOCKS_PULL,
+        );
+        let referencing: Vec<Block> = self.query_pulled(&query, &[json!(edn_string(&src.to_lowercase()))])?;
+        let rewrites: Vec<(Block, String)> = referencing
+            .into_iter()
+            .filter_map(|block| {
+                let updated = rewrite_page_refs(&block.content, src_page.display_name(), &dst_name);
+                (updated != block.content).then_some((block, updated))
+            })
+            .collect();
+
+        if dry_run {
+            println!("Would move {} top-level blocks from {} to {}.", blocks.len(), src_page.display_name(), dst_name);
+            for block in &blocks {
+                println!("  {} {}", block.uuid, preview(&block.content));
+            }
+            let mut pages: BTreeMap<String, usize> = BTreeMap::new();
+            for (block, _) in &rewrites {
+                let page = block.page.as_ref().map(|page| page.display_name().to_string()).unwrap_or_default();
+                *pages.entry(page).or_default() += 1;
+            }
+            println!("Would rewrite references in {} blocks on {} pages.", rewrites.len(), pages.len());
+            for (page, count) in pages {
+                println!("  {}\t{}", count, page);
+            }
+            if !merged.is_empty() {
+                println!("Would set page properties of {}:", dst_name);
+                for (key, value) in &merged {
+                    println!("  {}:: {}", key, value);
+                }
+            }
+            println!("Would delete page {}.", src_page.display_name());
+            return Ok(());
+        }
+
+        if !merged.is_empty() {
+            let lines: Vec<String> = merged.iter().map(|(key, value)| format!("{}:: {}", key, value)).collect();
+            match dst_blocks.first() {
+                Some(first) if first.pre_block => {
+                    for (key, value) in &merged {
+                        self.call("logseq.Editor.upsertBlockProperty", json!([first.uuid, key, value]))?;
+                    }
+                }
+                Some(first) => {
+                    self.call(
+                        "logseq.Editor.insertBlock",
+                        json!([first.uuid, lines.join("\n"), {"sibling": true, "before": true}]),
+                    )?;
+                }
+                None => {
+                    let inserted: Block =
+                        self.call_as("logseq.Editor.insertBlock", json!([dst, lines.join("\n"), {"isPageBlock": true}]))?;
+                    dst_blocks.push(inserted);
+                }
+            }
+            eprintln!("Merged {} page properties into {}.", merged.len(), dst_name);
+        }
+
+        // Blocks are moved after the last top-level block of `dst`; an empty
+        // page gets a placeholder to move them after.
+        let mut placeholder = None;
+        let mut previous = match dst_blocks.last() {
+            Some(last) => last.uuid.clone(),
+            None if blocks.is_empty() => String::new(),
+            None => {
+                let inserted: Block = self.call_as("logseq.Editor.insertBlock", json!([dst, "", {"isPageBlock": true}]))?;
+                placeholder = Some(inserted.uuid.clone());
+                inserted.uuid
+            }
+        };
+        for block in &blocks {
+            debug!("Moving block {} after {}", block.uuid, previous);
+            self.call("logseq.Editor.moveBlock", json!([block.uuid, previous, {"before": false, "children": false}]))?;
+            previous = block.uuid.clone();
+        }
+        if let Some(placeholder) = placeholder {
+            self.call("logseq.Editor.removeBlock", json!([placeholder]))?;
+        }
+        eprintln!("Moved {} blocks from {} to {}.", blocks.len(), src_page.display_name(), dst_name);
+
+        for (block, updated) in &rewrites {
+            self.call("logseq.Editor.updateBlock", json!([block.uuid, updated]))?;
+        }
+        eprintln!("Rewrote references in {} blocks.", rewrites.len());
+
+        self.call("logseq.Editor.deletePage", json!([src]))?;
+        eprintln!("Deleted page {}.", src_page.display_name());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rewrites_links() {
+        assert_eq!(rewrite_page_refs("see [[src]] and [[Src]]", "src", "dst"), "see [[dst]] and [[dst]]");
+        assert_eq!(rewrite_page_refs("tagged #[[SRC]]", "src", "dst"), "tagged #[[dst]]");
+        assert_eq!(rewrite_page_refs("[[my src]] here", "My Src", "dst"), "[[dst]] here");
+    }
+
+    #[test]
+    fn rewrites_tags() {
+        assert_eq!(rewrite_page_refs("#src", "src", "dst"), "#dst");
+        assert_eq!(rewrite_page_refs("done #Src.", "src", "dst"), "done #dst.");
+        assert_eq!(rewrite_page_refs("#SRC, #src; (x #src)", "src", "dst"), "#dst, #dst; (x #dst)");
+        assert_eq!(rewrite_page_refs("a #src", "src", "new page"), "a #[[new page]]");
+    }
+
+    #[test]
+    fn rewrites_adjacent_tags() {
+        assert_eq!(rewrite_page_refs("#src #src", "src", "dst"), "#dst #dst");
+        assert_eq!(rewrite_page_refs("#src #Src #SRC", "src", "dst"), "#dst #dst #dst");
+        assert_eq!(rewrite_page_refs("#src\n#src", "src", "dst"), "#dst\n#dst");
+    }
+
+    #[test]
+    fn leaves_other_pages_alone() {
+        assert_eq!(rewrite_page_refs("#srcfoo #src-x", "src", "dst"), "#srcfoo #src-x");
+        assert_eq!(rewrite_page_refs("#srcfoo #src", "src", "dst"), "#srcfoo #dst");
+        assert_eq!(rewrite_page_refs("[[srcfoo]] [[foo/src]] a#src", "src", "dst"), "[[srcfoo]] [[foo/src]] a#src");
+        // Tags cannot contain spaces, only links are rewritten.
+        assert_eq!(rewrite_page_refs("#my src [[my src]]", "my src", "dst"), "#my src [[dst]]");
+    }
+
+    #[test]
+    fn reads_property_lines() {
+        assert_eq!(
+            property_lines("alias:: a, b\nTags:: x\nnot a property"),
+            [("alias".to_string(), "a, b".to_string()), ("tags".to_string(), "x".to_string())]
+        );
+    }
+
+    #[test]
+    fn merges_page_properties() {
+        let dst = property_lines("alias:: old\ntags:: rust\ntype:: book");
+        let src = property_lines("alias:: Other, OLD\ntags:: rust\nstatus:: draft\ntitle:: Src\nid:: 64a1");
+        assert_eq!(
+            merged_properties(&dst, &src),
+            [("alias".to_string(), "old, Other".to_string()), ("status".to_string(), "draft".to_string())]
+        );
+    }
+
+    #[test]
+    fn merges_into_a_page_without_properties() {
+        let src = property_lines("alias:: a\nalias:: b");
+        assert_eq!(merged_properties(&[], &src), [("alias".to_string(), "a, b".to_string())]);
+        assert!(merged_properties(&property_lines("tags:: x"), &property_lines("tags:: X")).is_empty());
+    }
+}
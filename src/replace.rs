@@ -0,0 +1,201 @@
+use clap::Args;
+use log::debug;
+use regex::Regex;
+use serde_json::json;
+use similar::TextDiff;
+
+use crate::model::Block;
+use crate::refs::REFERENCING_BLOCKS_PULL;
+use crate::{edn_string, Client};
+
+/// The blocks a substitution runs over; exactly one has to be given.
+#[derive(Args)]
+#[group(required = true, multiple = false)]
+pub struct SubScope {
+    /// A single block
+    #[arg(long)]
+    block: Option<String>,
+    /// A block and all of its children
+    #[arg(long)]
+    tree: Option<String>,
+    /// Every block on a page
+    #[arg(long)]
+    page: Option<String>,
+    /// Every block whose content contains this text
+    #[arg(long)]
+    search: Option<String>,
+}
+
+/// Collect `(uuid, content)` of a block and, recursively, its children as
+/// returned with `includeChildren` or by `getPageBlocksTree`.
+fn flatten_blocks(block: &Block, blocks: &mut Vec<(String, String)>) {
+    blocks.push((block.uuid.clone(), block.content.clone()));
+    for child in block.loaded_children() {
+        flatten_blocks(child, blocks);
+    }
+}
+
+/// `(uuid, content, updated content)` of the blocks `regex` changes.
+fn substitutions(regex: &Regex, replacement: &str, blocks: Vec<(String, String)>) -> Vec<(String, String, String)> {
+    blocks
+        .into_iter()
+        .filter_map(|(uuid, content)| {
+            let updated = regex.replace_all(&content, replacement).into_owned();
+            (updated != content).then_some((uuid, content, updated))
+        })
+        .collect()
+}
+
+impl Client {
+    pub fn replace_content(&self, entry_id: &str, input_content: Option<String>) {
+        let content = self.read_content(input_content);
+        debug!("Replacing content of entry with ID: {}", entry_id);
+
+        match self.call("logseq.Editor.updateBlock", json!([entry_id, content])) {
+            Ok(_) => eprintln!("Content of the entry replaced."),
+            Err(err) => {
+                eprintln!("Failed to update entry: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    fn scope_blocks(&self, scope: &SubScope) -> Result<Vec<(String, String)>, String> {
+        let mut blocks = Vec::new();
+
+        if let Some(uuid) = &scope.block {
+            // Without includeChildren the children are only ids.
+            if let Some(block) = self.call_as::<Option<Block>>("logseq.Editor.getBlock", json!([uuid]))? {
+                flatten_blocks(&block, &mut blocks);
+            }
+        } else if let Some(uuid) = &scope.tree {
+            let block = self.call_as::<Option<Block>>("logseq.Editor.getBlock", json!([uuid, {"includeChildren": true}]))?;
+            if let Some(block) = block {
+                flatten_blocks(&block, &mut blocks);
+            }
+        } else if let Some(page) = &scope.page {
+            let tree = self.call_as::<Option<Vec<Block>>>("logseq.Editor.getPageBlocksTree", json!([page]))?;
+            for block in tree.iter().flatten() {
+                flatten_blocks(block, &mut blocks);
+            }
+        } else if let Some(text) = &scope.search {
+            let found = self.search_blocks(text)?;
+            blocks.extend(found.into_iter().map(|block| (block.uuid, block.content)));
+        }
+
+        Ok(blocks)
+    }
+
+    /// Blocks whose content contains `text`, with the page they are on.
+    pub fn search_blocks(&self, text: &str) -> Result<Vec<Block>, String> {
+        let query = format!(
+            "[:find (pull ?b {pull})
+             :in $ ?text
+             :where
+             [?b :block/content ?content]
+             [(clojure.string/includes? ?content ?text)]]",
+            pull = REFERENCING_BLOCKS_PULL,
+        );
+        self.query_pulled(&query, &[json!(edn_string(text))])
+    }
+
+    /// Run a regex substitution over the blocks in `scope`, printing a
+    /// unified diff per changed block. Blocks are only updated with `apply`.
+    pub fn substitute(&self, pattern: &str, replacement: &str, scope: &SubScope, apply: bool) {
+        let regex = match Regex::new(pattern) {
+            Ok(regex) => regex,
+            Err(err) => {
+                eprintln!("Invalid pattern: {}", err);
+                std::process::exit(1);
+            }
+        };
+
+        let blocks = match self.scope_blocks(scope) {
+            Ok(blocks) => blocks,
+            Err(err) => {
+                eprintln!("Failed to get blocks: {}", err);
+                std::process::exit(1);
+            }
+        };
+
+        let changes = substitutions(&regex, replacement, blocks);
+        let mut failed = 0;
+        for (uuid, content, updated) in &changes {
+            let old = format!("{}\n", content);
+            let new = format!("{}\n", updated);
+            print!("{}", TextDiff::from_lines(&old, &new).unified_diff().header(uuid, uuid));
+
+            if apply {
+                if let Err(err) = self.call("logseq.Editor.updateBlock", json!([uuid, updated])) {
+                    eprintln!("Failed to update {}: {}", uuid, err);
+                    failed += 1;
+                }
+            }
+        }
+
+        if apply && failed > 0 {
+            eprintln!("Updated {} blocks, {} failed.", changes.len() - failed, failed);
+            std::process::exit(1);
+        } else if apply {
+            eprintln!("Updated {} blocks.", changes.len());
+        } else if !changes.is_empty() {
+            eprintln!("{} blocks would change. Re-run with --apply to update them.", changes.len());
+        } else {
+            eprintln!("No blocks match.");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{BlockChild, BlockRef};
+
+    fn block(uuid: &str, content: &str, children: Vec<BlockChild>) -> Block {
+        Block { uuid: uuid.to_string(), content: content.to_string(), children, ..Block::default() }
+    }
+
+    fn loaded(block: Block) -> BlockChild {
+        BlockChild::Loaded(Box::new(block))
+    }
+
+    #[test]
+    fn flattens_loaded_children_only() {
+        let tree = block(
+            "a",
+            "root",
+            vec![
+                loaded(block("b", "child", vec![loaded(block("c", "grandchild", Vec::new()))])),
+                BlockChild::Ref(BlockRef { uuid: "d".to_string() }),
+            ],
+        );
+        let mut blocks = Vec::new();
+        flatten_blocks(&tree, &mut blocks);
+        let uuids: Vec<&str> = blocks.iter().map(|(uuid, _)| uuid.as_str()).collect();
+        assert_eq!(uuids, ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn keeps_only_the_blocks_that_change() {
+        let blocks = vec![
+            ("a".to_string(), "TODO call Alice on 2024-05-01".to_string()),
+            ("b".to_string(), "nothing to see".to_string()),
+            ("c".to_string(), "2024-05-02 and 2024-05-03".to_string()),
+        ];
+        let regex = Regex::new(r"(?<y>\d{4})-(\d{2})-(\d{2})").unwrap();
+        let changes = substitutions(&regex, "$3.$2.${y}", blocks);
+        assert_eq!(
+            changes,
+            vec![
+                ("a".to_string(), "TODO call Alice on 2024-05-01".to_string(), "TODO call Alice on 01.05.2024".to_string()),
+                ("c".to_string(), "2024-05-02 and 2024-05-03".to_string(), "02.05.2024 and 03.05.2024".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn leaves_blocks_a_replacement_does_not_alter() {
+        let blocks = vec![("a".to_string(), "same same".to_string())];
+        assert!(substitutions(&Regex::new("same").unwrap(), "same", blocks).is_empty());
+    }
+}
@@ -0,0 +1,277 @@
+//! `rlu serve --stdio`: a Model Context Protocol server so that assistant
+//! tools can search and edit the graph without parsing rlu's output. It
+//! reads newline-delimited JSON-RPC 2.0 requests from stdin and writes the
+//! responses to stdout; logs go to stderr.
+
+use chrono::Local;
+use log::debug;
+use serde_json::{json, Value};
+use std::io::{self, BufRead, Write};
+
+use crate::agenda::AGENDA_PULL;
+use crate::model::{Block, BlockChild};
+use crate::{journal, Client, CombineArgs};
+
+const PROTOCOL_VERSION: &str = "2024-11-05";
+
+/// Search results returned when the call does not give a `limit`.
+const DEFAULT_SEARCH_LIMIT: u64 = 50;
+
+fn tool_definitions() -> Value {
+    json!([
+        {
+            "name": "search",
+            "description": "Find blocks whose content contains the given text. Returns their uuid, page and content.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "query": {"type": "string", "description": "Text to look for, case-sensitive"},
+                    "limit": {"type": "integer", "description": "Maximum number of blocks to return (default 50)"}
+                },
+                "required": ["query"]
+            }
+        },
+        {
+            "name": "read_page",
+            "description": "Read a page as a tree of blocks with their uuids.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "name": {"type": "string", "description": "Page name"}
+                },
+                "required": ["name"]
+            }
+        },
+        {
+            "name": "read_block",
+            "description": "Read a block and all of its children as a tree with uuids.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "uuid": {"type": "string", "description": "Block uuid"}
+                },
+                "required": ["uuid"]
+            }
+        },
+        {
+            "name": "add_journal_note",
+            "description": "Add a note to a journal page. The first line becomes a block; lines starting with # nest the lines after them.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "content": {"type": "string", "description": "Note text"},
+                    "date": {"type": "string", "description": "Journal day as YYYY-MM-DD (default today)"}
+                },
+                "required": ["content"]
+            }
+        },
+        {
+            "name": "append_to_block",
+            "description": "Append text to the end of a block, or add it as the block's last child.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "uuid": {"type": "string", "description": "Block uuid"},
+                    "content": {"type": "string", "description": "Text to append"},
+                    "as_child": {"type": "boolean", "description": "Insert as the last child instead of appending"}
+                },
+                "required": ["uuid", "content"]
+            }
+        },
+        {
+            "name": "list_tasks",
+            "description": "List task blocks with their marker, priority, planning dates and page. Closed tasks are left out unless requested.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "marker": {"type": "string", "description": "Only tasks with this marker, e.g. TODO or DOING"},
+                    "include_closed": {"type": "boolean", "description": "Also list DONE and CANCELED tasks"}
+                }
+            }
+        }
+    ])
+}
+
+/// A block and its loaded children as `{uuid, content, children}`.
+pub fn block_json(block: &Block) -> Value {
+    json!({
+        "uuid": block.uuid,
+        "content": block.content,
+        "children": block.loaded_children().map(block_json).collect::<Vec<_>>(),
+    })
+}
+
+fn string_arg<'a>(arguments: &'a Value, name: &str) -> Result<&'a str, String> {
+    arguments
+        .get(name)
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| format!("Missing string argument '{}'", name))
+}
+
+impl Client {
+    /// Load the children that only came back as uuids, recursively.
+    pub fn load_children(&self, block: &mut Block) -> Result<(), String> {
+        for child in &mut block.children {
+            if let BlockChild::Ref(child_ref) = child {
+                *child = BlockChild::Loaded(Box::new(self.get_block_by_uuid(&child_ref.uuid)?));
+            }
+            if let BlockChild::Loaded(child) = child {
+                self.load_children(child)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn call_tool(&self, name: &str, arguments: &Value) -> Result<Value, String> {
+        match name {
+            "search" => {
+                let limit = arguments.get("limit").and_then(|v| v.as_u64()).unwrap_or(DEFAULT_SEARCH_LIMIT);
+                let blocks = self.search_blocks(string_arg(arguments, "query")?)?;
+                Ok(blocks
+                    .iter()
+                    .take(limit as usize)
+                    .map(|block| {
+                        json!({
+                            "uuid": block.uuid,
+                            "page": block.page.as_ref().map(|page| page.display_name()),
+                            "content": block.content,
+                        })
+                    })
+                    .collect())
+            }
+            "read_page" => {
+                let name = string_arg(arguments, "name")?;
+                self.require_page(name)?;
+                Ok(self.page_blocks(name)?.iter().map(block_json).collect())
+            }
+            "read_block" => {
+                let uuid = string_arg(arguments, "uuid")?;
+                let mut block = self
+                    .call_as::<Option<Block>>("logseq.Editor.getBlock", json!([uuid, {"includeChildren": true}]))?
+                    .ok_or_else(|| format!("Block {} not found", uuid))?;
+                self.load_children(&mut block)?;
+                Ok(block_json(&block))
+            }
+            "add_journal_note" => {
+                let date = match arguments.get("date").and_then(|v| v.as_str()) {
+                    Some(date) => journal::parse_date(date)?,
+                    None => Local::now().date_naive(),
+                };
+                let page = self.journal_page_uuid(date)?;
+                let uuid = self.insert_note(&page, string_arg(arguments, "content")?)?;
+                Ok(json!({"uuid": uuid, "date": date.to_string()}))
+            }
+            "append_to_block" => {
+                let uuid = string_arg(arguments, "uuid")?;
+                let content = string_arg(arguments, "content")?;
+                let options = CombineArgs {
+                    separator: None,
+                    newline: false,
+                    as_child_first: false,
+                    as_child_last: arguments.get("as_child").and_then(|v| v.as_bool()).unwrap_or(false),
+                };
+                self.combine_content(uuid, content, &options, |existing, separator| {
+                    format!("{}{}{}", existing, separator, content)
+                })?;
+                Ok(json!({"uuid": uuid}))
+            }
+            "list_tasks" => {
+                let marker = arguments.get("marker").and_then(|v| v.as_str());
+                let include_closed = arguments.get("include_closed").and_then(|v| v.as_bool()).unwrap_or(false);
+                let query = format!(
+                    "[:find (pull ?b {pull})
+                     :where
+                     [?b :block/marker]]",
+                    pull = AGENDA_PULL,
+                );
+                let blocks: Vec<Block> = self.query_pulled(&query, &[])?;
+                Ok(blocks
+                    .iter()
+                    .filter_map(|block| Some((block, block.marker.as_ref()?)))
+                    .filter(|(_, found)| marker.is_none_or(|marker| found.as_str().eq_ignore_ascii_case(marker)))
+                    .filter(|(_, found)| include_closed || marker.is_some() || !found.is_closed())
+                    .map(|(block, found)| {
+                        json!({
+                            "uuid": block.uuid,
+                            "marker": found.as_str(),
+                            "priority": block.priority,
+                            "scheduled": block.scheduled.map(|day| day.to_string()),
+                            "deadline": block.deadline.map(|day| day.to_string()),
+                            "page": block.page.as_ref().map(|page| page.display_name()),
+                            "content": block.content,
+                        })
+                    })
+                    .collect())
+            }
+            _ => Err(format!("Unknown tool '{}'", name)),
+        }
+    }
+
+    /// Answer one request. `None` for notifications, which get no response.
+    fn handle_request(&self, request: &Value) -> Option<Value> {
+        let id = request.get("id")?.clone();
+        let method = request.get("method").and_then(|v| v.as_str()).unwrap_or("");
+        let params = request.get("params").cloned().unwrap_or(Value::Null);
+        debug!("Request: {} {}", method, params);
+
+        let result = match method {
+            "initialize" => json!({
+                "protocolVersion": PROTOCOL_VERSION,
+                "capabilities": {"tools": {}},
+                "serverInfo": {"name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION")},
+            }),
+            "ping" => json!({}),
+            "tools/list" => json!({"tools": tool_definitions()}),
+            "tools/call" => {
+                let name = params.get("name").and_then(|v| v.as_str()).unwrap_or("");
+                let arguments = params.get("arguments").cloned().unwrap_or_else(|| json!({}));
+                // Tool failures are results the assistant can read, not
+                // protocol errors.
+                match self.call_tool(name, &arguments) {
+                    Ok(value) => json!({
+                        "content": [{"type": "text", "text": serde_json::to_string_pretty(&value).unwrap_or_default()}],
+                        "isError": false,
+                    }),
+                    Err(err) => json!({
+                        "content": [{"type": "text", "text": err}],
+                        "isError": true,
+                    }),
+                }
+            }
+            _ => {
+                return Some(json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": {"code": -32601, "message": format!("Method not found: {}", method)},
+                }))
+            }
+        };
+        Some(json!({"jsonrpc": "2.0", "id": id, "result": result}))
+    }
+
+    pub fn serve_stdio(&self) {
+        let stdout = io::stdout();
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let response = match serde_json::from_str::<Value>(&line) {
+                Ok(request) => self.handle_request(&request),
+                Err(err) => Some(json!({
+                    "jsonrpc": "2.0",
+                    "id": null,
+                    "error": {"code": -32700, "message": format!("Parse error: {}", err)},
+                })),
+            };
+
+            if let Some(response) = response {
+                let mut out = stdout.lock();
+                if writeln!(out, "{}", response).and_then(|_| out.flush()).is_err() {
+                    break;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,349 @@
+//! `rlu stats`: counts and health checks for the whole graph, all worked
+//! out with datascript queries.
+
+use chrono::{Local, NaiveDate};
+use regex::Regex;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use crate::model::JournalDay;
+use crate::{edn_string, Client};
+
+/// A run of consecutive journal days with content.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Streak {
+    start: NaiveDate,
+    end: NaiveDate,
+}
+
+impl Streak {
+    fn days(self) -> i64 {
+        (self.end - self.start).num_days() + 1
+    }
+
+    fn to_json(self) -> Value {
+        json!({"days": self.days(), "start": self.start.to_string(), "end": self.end.to_string()})
+    }
+}
+
+/// Runs of consecutive days in `days`, in order.
+fn streaks(days: &BTreeSet<NaiveDate>) -> Vec<Streak> {
+    let mut streaks: Vec<Streak> = Vec::new();
+    for day in days {
+        match streaks.last_mut() {
+            Some(streak) if streak.end.succ_opt() == Some(*day) => streak.end = *day,
+            _ => streaks.push(Streak { start: *day, end: *day }),
+        }
+    }
+    streaks
+}
+
+/// The current and the longest streak of `days` as of `today`.
+fn current_and_longest_streak(days: &BTreeSet<NaiveDate>, today: NaiveDate) -> (Option<Streak>, Option<Streak>) {
+    let streaks = streaks(days);
+    // A streak still counts as current before today's entry is written.
+    let current = streaks
+        .last()
+        .copied()
+        .filter(|streak| streak.end == today || streak.end.succ_opt() == Some(today));
+    let longest = streaks.iter().copied().max_by_key(|streak| (streak.days(), streak.end));
+    (current, longest)
+}
+
+/// Where something broken was found: its target and the blocks with it.
+struct Broken {
+    target: String,
+    blocks: Vec<String>,
+}
+
+impl Broken {
+    fn to_json(&self) -> Value {
+        json!({"target": self.target, "blocks": self.blocks})
+    }
+}
+
+fn group_broken(found: Vec<(String, String)>) -> Vec<Broken> {
+    let mut grouped: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (target, uuid) in found {
+        grouped.entry(target).or_default().push(uuid);
+    }
+    grouped.into_iter().map(|(target, blocks)| Broken { target, blocks }).collect()
+}
+
+fn string_column(rows: &[Value], column: usize) -> impl Iterator<Item = &str> {
+    rows.iter().filter_map(move |row| row.get(column)?.as_str())
+}
+
+struct GraphStats {
+    pages: i64,
+    journals: i64,
+    blocks: i64,
+    tasks: Vec<(String, i64)>,
+    most_linked: Vec<(String, i64)>,
+    orphans: Vec<String>,
+    broken_links: Vec<Broken>,
+    broken_refs: Vec<Broken>,
+    case_duplicates: Vec<Vec<String>>,
+    current_streak: Option<Streak>,
+    longest_streak: Option<Streak>,
+}
+
+impl Client {
+    fn count(&self, query: &str) -> Result<i64, String> {
+        let rows = self.datascript_query(query, &[])?;
+        Ok(rows.first().and_then(|row| row.get(0)).and_then(|v| v.as_i64()).unwrap_or(0))
+    }
+
+    /// `(uuid, content)` of the blocks whose content contains `text`.
+    fn blocks_containing(&self, text: &str) -> Result<Vec<(String, String)>, String> {
+        let rows = self.datascript_query(
+            "[:find ?uuid ?content
+             :in $ ?text
+             :where
+             [?b :block/content ?content]
+             [(clojure.string/includes? ?content ?text)]
+             [?b :block/uuid ?uuid]]",
+            &[json!(edn_string(text))],
+        )?;
+        Ok(rows
+            .iter()
+            .filter_map(|row| Some((row.get(0)?.as_str()?.to_string(), row.get(1)?.as_str()?.to_string())))
+            .collect())
+    }
+
+    fn graph_stats(&self, top: usize) -> Result<GraphStats, String> {
+        let pages = self.count("[:find (count ?p) :where [?p :block/name]]")?;
+        let journals = self.count("[:find (count ?p) :where [?p :block/journal? true]]")?;
+        let blocks = self.count("[:find (count ?b) :where [?b :block/page]]")?;
+
+        let mut tasks: Vec<(String, i64)> = self
+            .datascript_query("[:find ?marker (count ?b) :where [?b :block/marker ?marker]]", &[])?
+            .iter()
+            .filter_map(|row| Some((row.get(0)?.as_str()?.to_string(), row.get(1)?.as_i64()?)))
+            .collect();
+        tasks.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        let most_linked = self.tag_counts()?.into_iter().take(top).collect();
+
+        // Pages kept in a file of their own that nothing links to.
+        let rows = self.datascript_query(
+            "[:find ?name
+             :where
+             [?p :block/original-name ?name]
+             [?p :block/file]
+             (not [?p :block/journal? true])
+             (not [_ :block/refs ?p])
+             (not [_ :block/alias ?p])]",
+            &[],
+        )?;
+        let mut orphans: Vec<String> = string_column(&rows, 0).map(str::to_string).collect();
+        orphans.sort_by_key(|name| name.to_lowercase());
+
+        // A link to a page without a file, blocks, or another role only
+        // created an empty page.
+        let rows = self.datascript_query(
+            "[:find ?name
+             :where
+             [?p :block/name ?name]
+             (or [?p :block/file]
+                 [_ :block/page ?p]
+                 [_ :block/alias ?p]
+                 [_ :block/namespace ?p])]",
+            &[],
+        )?;
+        let existing: HashSet<&str> = string_column(&rows, 0).collect();
+        let link = Regex::new(r"\[\[([^\[\]]+)\]\]").expect("valid regex");
+        let mut spellings: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+        let mut broken_links = Vec::new();
+        for (uuid, content) in self.blocks_containing("[[")? {
+            for captures in link.captures_iter(&content) {
+                let name = captures[1].trim().to_string();
+                let key = name.to_lowercase();
+                if !existing.contains(key.as_str()) {
+                    broken_links.push((name.clone(), uuid.clone()));
+                }
+                spellings.entry(key).or_default().insert(name);
+            }
+        }
+
+        let rows = self.datascript_query("[:find ?name :where [?p :block/original-name ?name]]", &[])?;
+        for name in string_column(&rows, 0) {
+            spellings.entry(name.to_lowercase()).or_default().insert(name.to_string());
+        }
+        let case_duplicates = spellings
+            .into_values()
+            .filter(|names| names.len() > 1)
+            .map(|names| names.into_iter().collect())
+            .collect();
+
+        let block_ref = Regex::new(r"\(\(([0-9a-fA-F-]{36})\)\)").expect("valid regex");
+        let mut found_refs = Vec::new();
+        for (uuid, content) in self.blocks_containing("((")? {
+            for captures in block_ref.captures_iter(&content) {
+                found_refs.push((captures[1].to_lowercase(), uuid.clone()));
+            }
+        }
+        let referenced: BTreeSet<&str> = found_refs.iter().map(|(target, _)| target.as_str()).collect();
+        let resolved: HashSet<String> = if referenced.is_empty() {
+            HashSet::new()
+        } else {
+            let uuids: Vec<String> = referenced.iter().map(|uuid| format!("#uuid {}", edn_string(uuid))).collect();
+            let rows = self.datascript_query(
+                "[:find ?uuid
+                 :in $ [?uuid ...]
+                 :where
+                 [_ :block/uuid ?uuid]]",
+                &[json!(format!("[{}]", uuids.join(" ")))],
+            )?;
+            string_column(&rows, 0).map(str::to_lowercase).collect()
+        };
+        let broken_refs = found_refs.into_iter().filter(|(target, _)| !resolved.contains(target)).collect();
+
+        let rows = self.datascript_query(
+            "[:find ?day
+             :where
+             [?p :block/journal-day ?day]
+             [?b :block/page ?p]
+             [?b :block/content ?content]
+             [(not= ?content \"\")]]",
+            &[],
+        )?;
+        let days: BTreeSet<NaiveDate> = rows
+            .iter()
+            .filter_map(|row| JournalDay(row.get(0)?.as_i64()?).date())
+            .collect();
+        let (current_streak, longest_streak) = current_and_longest_streak(&days, Local::now().date_naive());
+
+        Ok(GraphStats {
+            pages,
+            journals,
+            blocks,
+            tasks,
+            most_linked,
+            orphans,
+            broken_links: group_broken(broken_links),
+            broken_refs: group_broken(broken_refs),
+            case_duplicates,
+            current_streak,
+            longest_streak,
+        })
+    }
+
+    pub fn show_stats(&self, top: usize, as_json: bool) {
+        let stats = match self.graph_stats(top) {
+            Ok(stats) => stats,
+            Err(err) => {
+                eprintln!("Failed to get graph statistics: {}", err);
+                std::process::exit(1);
+            }
+        };
+
+        if as_json {
+            let counts = |counts: &[(String, i64)]| {
+                counts.iter().map(|(name, count)| json!({"name": name, "count": count})).collect::<Vec<_>>()
+            };
+            let report = json!({
+                "pages": stats.pages,
+                "journals": stats.journals,
+                "blocks": stats.blocks,
+                "tasks": counts(&stats.tasks),
+                "most_linked": counts(&stats.most_linked),
+                "orphan_pages": stats.orphans,
+                "broken_links": stats.broken_links.iter().map(Broken::to_json).collect::<Vec<_>>(),
+                "broken_refs": stats.broken_refs.iter().map(Broken::to_json).collect::<Vec<_>>(),
+                "case_duplicates": stats.case_duplicates,
+                "current_streak": stats.current_streak.map(Streak::to_json),
+                "longest_streak": stats.longest_streak.map(Streak::to_json),
+            });
+            println!("{}", serde_json::to_string_pretty(&report).unwrap_or_default());
+            return;
+        }
+
+        println!("Pages     {}", stats.pages);
+        println!("Journals  {}", stats.journals);
+        println!("Blocks    {}", stats.blocks);
+        let tasks: Vec<String> = stats.tasks.iter().map(|(marker, count)| format!("{} {}", marker, count)).collect();
+        println!("Tasks     {}", if tasks.is_empty() { "none".to_string() } else { tasks.join(", ") });
+
+        match (stats.current_streak, stats.longest_streak) {
+            (_, None) => println!("Journal streak: none"),
+            (current, Some(longest)) => println!(
+                "Journal streak: current {} days, longest {} days ({} to {})",
+                current.map_or(0, Streak::days),
+                longest.days(),
+                longest.start,
+                longest.end
+            ),
+        }
+
+        if !stats.most_linked.is_empty() {
+            println!("\nMost linked pages");
+            for (name, count) in &stats.most_linked {
+                println!("  {:>5}  {}", count, name);
+            }
+        }
+        if !stats.orphans.is_empty() {
+            println!("\nOrphan pages ({})", stats.orphans.len());
+            for name in &stats.orphans {
+                println!("  {}", name);
+            }
+        }
+        if !stats.broken_links.is_empty() {
+            println!("\nBroken links ({})", stats.broken_links.len());
+            for broken in &stats.broken_links {
+                println!("  [[{}]] in {}", broken.target, broken.blocks.join(", "));
+            }
+        }
+        if !stats.broken_refs.is_empty() {
+            println!("\nBroken block refs ({})", stats.broken_refs.len());
+            for broken in &stats.broken_refs {
+                println!("  (({})) in {}", broken.target, broken.blocks.join(", "));
+            }
+        }
+        if !stats.case_duplicates.is_empty() {
+            println!("\nPage names differing by case ({})", stats.case_duplicates.len());
+            for names in &stats.case_duplicates {
+                println!("  {}", names.join(", "));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 5, day).unwrap()
+    }
+
+    fn streak(start: u32, end: u32) -> Streak {
+        Streak { start: day(start), end: day(end) }
+    }
+
+    #[test]
+    fn splits_streaks_at_gaps() {
+        let days = BTreeSet::from([day(1), day(2), day(3), day(5), day(7), day(8)]);
+        assert_eq!(streaks(&days), vec![streak(1, 3), streak(5, 5), streak(7, 8)]);
+        assert_eq!(current_and_longest_streak(&days, day(8)), (Some(streak(7, 8)), Some(streak(1, 3))));
+    }
+
+    #[test]
+    fn keeps_yesterdays_streak_current() {
+        let days = BTreeSet::from([day(1), day(2), day(3)]);
+        assert_eq!(current_and_longest_streak(&days, day(4)), (Some(streak(1, 3)), Some(streak(1, 3))));
+        assert_eq!(current_and_longest_streak(&days, day(5)), (None, Some(streak(1, 3))));
+    }
+
+    #[test]
+    fn prefers_the_latest_of_equally_long_streaks() {
+        let days = BTreeSet::from([day(1), day(2), day(4), day(5)]);
+        assert_eq!(current_and_longest_streak(&days, day(10)), (None, Some(streak(4, 5))));
+    }
+
+    #[test]
+    fn has_no_streaks_without_entries() {
+        assert!(streaks(&BTreeSet::new()).is_empty());
+        assert_eq!(current_and_longest_streak(&BTreeSet::new(), day(1)), (None, None));
+    }
+}
@@ -0,0 +1,238 @@
+use chrono::{Duration, Local};
+use log::debug;
+use serde_json::json;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::journal;
+use crate::outline::parse_outline;
+use crate::model::{Block, BlockNode};
+use crate::Client;
+
+/// Properties that belong to the template itself and must not be copied.
+const TEMPLATE_PROPERTIES: &[&str] = &["template", "template-including-parent", "id"];
+
+/// Directory holding local template files (`<name>.md`), overridable with
+/// `RLU_TEMPLATES_DIR`.
+fn templates_dir() -> PathBuf {
+    match env::var("RLU_TEMPLATES_DIR") {
+        Ok(dir) => PathBuf::from(dir),
+        Err(_) => PathBuf::from(env::var("HOME").unwrap_or_default()).join(".rlu").join("templates"),
+    }
+}
+
+fn local_templates() -> Vec<(String, PathBuf)> {
+    let mut templates: Vec<(String, PathBuf)> = fs::read_dir(templates_dir())
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().and_then(|ext| ext.to_str()) == Some("md"))
+                .filter_map(|path| {
+                    let name = path.file_stem()?.to_str()?.to_string();
+                    Some((name, path))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    templates.sort();
+    templates
+}
+
+/// Parse `key=value` for `--var`.
+pub fn parse_var(var: &str) -> Result<(String, String), String> {
+    var.split_once('=')
+        .map(|(key, value)| (key.trim().to_string(), value.to_string()))
+        .ok_or_else(|| format!("Invalid variable '{}', expected key=value", var))
+}
+
+/// Drop template bookkeeping properties such as `template::` from a block's
+/// content.
+fn strip_template_properties(content: &str) -> String {
+    content
+        .lines()
+        .filter(|line| {
+            let key = line.trim().split_once("::").map(|(key, _)| key.trim());
+            !matches!(key, Some(key) if TEMPLATE_PROPERTIES.contains(&key))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn block_to_node(block: &Block) -> BlockNode {
+    BlockNode {
+        content: strip_template_properties(&block.content),
+        children: block.loaded_children().map(block_to_node).collect(),
+    }
+}
+
+/// Replace `<% name %>` placeholders. User variables win over the built-in
+/// `today`, `yesterday`, `tomorrow`, `time` and `current page`. Placeholders
+/// without a value are left as they are and returned as well.
+pub fn substitute(text: &str, vars: &HashMap<String, String>, builtins: &HashMap<&str, String>) -> (String, Vec<String>) {
+    let mut result = String::new();
+    let mut missing = Vec::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find("<%") {
+        let Some(end) = rest[start..].find("%>") else {
+            break;
+        };
+        let key = rest[start + 2..start + end].trim();
+
+        result.push_str(&rest[..start]);
+        match vars.get(key).or_else(|| builtins.get(key.to_lowercase().as_str())) {
+            Some(value) => result.push_str(value),
+            None => {
+                missing.push(key.to_string());
+                result.push_str(&rest[start..start + end + 2]);
+            }
+        }
+        rest = &rest[start + end + 2..];
+    }
+
+    result.push_str(rest);
+    (result, missing)
+}
+
+/// `substitute` in every block of `nodes`, collecting the placeholders
+/// without a value into `missing`.
+fn substitute_tree(
+    nodes: &mut [BlockNode],
+    vars: &HashMap<String, String>,
+    builtins: &HashMap<&str, String>,
+    missing: &mut Vec<String>,
+) {
+    for node in nodes {
+        let (content, unfilled) = substitute(&node.content, vars, builtins);
+        node.content = content;
+        missing.extend(unfilled);
+        substitute_tree(&mut node.children, vars, builtins, missing);
+    }
+}
+
+impl Client {
+    pub fn list_templates(&self) {
+        let rows = self.datascript_query(
+            "[:find ?name
+             :where
+             [?b :block/properties ?props]
+             [(get ?props :template) ?name]]",
+            &[],
+        );
+
+        match rows {
+            Ok(rows) => {
+                let mut names: Vec<&str> = rows.iter().filter_map(|row| row.get(0)?.as_str()).collect();
+                names.sort();
+                for name in names {
+                    println!("{}\tlogseq", name);
+                }
+            }
+            Err(err) => eprintln!("Failed to list Logseq templates: {}", err),
+        }
+
+        for (name, path) in local_templates() {
+            println!("{}\t{}", name, path.display());
+        }
+    }
+
+    fn template_tree(&self, name: &str) -> Result<Vec<BlockNode>, String> {
+        let template = self.call_as::<Option<Block>>("logseq.App.getTemplate", json!([name]));
+        let template_uuid = match template {
+            Ok(block) => block.map(|block| block.uuid),
+            Err(err) => {
+                debug!("Failed to get Logseq template: {}", err);
+                None
+            }
+        };
+
+        if let Some(uuid) = template_uuid {
+            debug!("Using Logseq template block {}", uuid);
+            let block: Block = self.call_as("logseq.Editor.getBlock", json!([uuid, {"includeChildren": true}]))?;
+            let root = block_to_node(&block);
+            let including_parent = block
+                .property("template-including-parent")
+                .is_none_or(|value| value.as_text() != "false");
+
+            return Ok(if including_parent { vec![root] } else { root.children });
+        }
+
+        let path = templates_dir().join(format!("{}.md", name));
+        let text = fs::read_to_string(&path)
+            .map_err(|_| format!("No Logseq template or template file named '{}' ({})", name, path.display()))?;
+        debug!("Using template file {}", path.display());
+        Ok(parse_outline(&text))
+    }
+
+    pub fn apply_template(&self, name: &str, page: Option<String>, date: Option<String>, vars: Vec<(String, String)>) {
+        match self.apply_template_inner(name, page, date, vars) {
+            Ok(target_name) => eprintln!("Template '{}' applied to {}.", name, target_name),
+            Err(err) => {
+                eprintln!("Failed to apply template: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    /// Insert the template `name`; returns the name of the page it went to.
+    fn apply_template_inner(
+        &self,
+        name: &str,
+        page: Option<String>,
+        date: Option<String>,
+        vars: Vec<(String, String)>,
+    ) -> Result<String, String> {
+        let mut tree = self.template_tree(name)?;
+
+        let target = match (&page, &date) {
+            (Some(page), _) => self.get_or_create_page(page).map(|uuid| (uuid, page.clone())),
+            (None, date) => {
+                let date = match date {
+                    Some(date) => journal::parse_date(date),
+                    None => Ok(Local::now().date_naive()),
+                };
+                date.and_then(|date| self.journal_page_uuid(date).map(|uuid| (uuid, self.journal_title(date))))
+            }
+        };
+        let (target_uuid, target_name) = target?;
+
+        let title_format = self.journal_title_format();
+        let today = Local::now().date_naive();
+        let link = |date| format!("[[{}]]", journal::format_journal_title(date, &title_format));
+        let builtins = HashMap::from([
+            ("today", link(today)),
+            ("yesterday", link(today - Duration::days(1))),
+            ("tomorrow", link(today + Duration::days(1))),
+            ("time", Local::now().format("%H:%M").to_string()),
+            ("current page", format!("[[{}]]", target_name)),
+        ]);
+        let vars: HashMap<String, String> = vars.into_iter().collect();
+        let mut missing = Vec::new();
+        substitute_tree(&mut tree, &vars, &builtins, &mut missing);
+        missing.sort();
+        missing.dedup();
+        for key in missing {
+            eprintln!("Warning: no value for placeholder '{}'", key);
+        }
+
+        self.insert_block_tree(&target_uuid, &tree, true)?;
+        Ok(target_name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn substitutes_and_reports_missing_placeholders() {
+        let vars = HashMap::from([("team".to_string(), "platform".to_string())]);
+        let builtins = HashMap::from([("time", "09:30".to_string())]);
+        let (text, missing) = substitute("<% team %> at <% Time %>, <%who%> <% team", &vars, &builtins);
+        assert_eq!(text, "platform at 09:30, <%who%> <% team");
+        assert_eq!(missing, vec!["who".to_string()]);
+    }
+}
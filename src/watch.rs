@@ -0,0 +1,372 @@
+//! `rlu watch`: follow a log file, or a folder that Markdown notes are
+//! dropped into, and add what is new to a page or today's journal.
+//!
+//! New content goes below an anchor block on the target page that names
+//! the source in a `watch-source::` property. How far each file was read
+//! and which dropped files were added is kept in a state file, so a
+//! restart picks up where the last run stopped.
+
+use chrono::Local;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::env;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::model::Block;
+use crate::Client;
+
+/// The property that ties an anchor block to the watched path.
+const SOURCE_PROPERTY: &str = "watch-source";
+
+/// Dropped files modified more recently than this may still be written.
+const SETTLE_TIME: Duration = Duration::from_secs(2);
+
+/// `~/.rlu/watch-state.json`.
+pub fn default_state_path() -> PathBuf {
+    PathBuf::from(env::var("HOME").unwrap_or_default()).join(".rlu").join("watch-state.json")
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct WatchState {
+    /// Bytes of each followed file that were already added.
+    #[serde(default)]
+    offsets: BTreeMap<String, u64>,
+    /// Names of the files in each watched folder that were already added.
+    #[serde(default)]
+    seen: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl WatchState {
+    fn load(path: &Path) -> Result<WatchState, String> {
+        match fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text).map_err(|err| format!("Invalid state file {}: {}", path.display(), err)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(WatchState::default()),
+            Err(err) => Err(format!("Failed to read {}: {}", path.display(), err)),
+        }
+    }
+
+    fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|err| format!("Failed to create {}: {}", dir.display(), err))?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(|err| format!("Failed to serialize state: {}", err))?;
+        let partial = path.with_extension("partial");
+        fs::write(&partial, json).map_err(|err| format!("Failed to write {}: {}", partial.display(), err))?;
+        fs::rename(&partial, path).map_err(|err| format!("Failed to write {}: {}", path.display(), err))
+    }
+}
+
+/// Where new content goes.
+pub enum WatchTarget {
+    Page(String),
+    Journal,
+}
+
+/// Complete lines appended to `path` since `offset`, and the offset after
+/// the last of them. A file shorter than `offset` was truncated or rotated
+/// and is read from the start.
+fn new_lines(path: &Path, offset: u64) -> Result<(Vec<(String, u64)>, u64), String> {
+    let mut file = File::open(path).map_err(|err| format!("Failed to open {}: {}", path.display(), err))?;
+    let length = file.metadata().map_err(|err| err.to_string())?.len();
+    let offset = if length < offset {
+        eprintln!("{} got shorter; reading it from the start.", path.display());
+        0
+    } else {
+        offset
+    };
+
+    file.seek(SeekFrom::Start(offset)).map_err(|err| err.to_string())?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes).map_err(|err| err.to_string())?;
+
+    // A line without its newline may still be being written.
+    let mut lines = Vec::new();
+    let mut start = 0;
+    for (index, byte) in bytes.iter().enumerate() {
+        if *byte == b'\n' {
+            let line = String::from_utf8_lossy(&bytes[start..index]).trim_end_matches('\r').to_string();
+            lines.push((line, offset + index as u64 + 1));
+            start = index + 1;
+        }
+    }
+    Ok((lines, offset))
+}
+
+/// The note files in `dir`, by name, finished or not.
+fn folder_files(dir: &Path) -> Result<Vec<(String, PathBuf)>, String> {
+    let entries = fs::read_dir(dir).map_err(|err| format!("Failed to read {}: {}", dir.display(), err))?;
+    let mut files: Vec<(String, PathBuf)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_file()))
+        .filter_map(|entry| Some((entry.file_name().into_string().ok()?, entry.path())))
+        .filter(|(name, _)| !name.starts_with('.'))
+        .filter(|(_, path)| path.extension().is_some_and(|ext| ext == "md" || ext == "markdown" || ext == "txt"))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Files in `dir` that look finished, by name.
+fn dropped_files(dir: &Path) -> Result<Vec<(String, PathBuf)>, String> {
+    let mut files = folder_files(dir)?;
+    files.retain(|(_, path)| {
+        let modified = fs::metadata(path).and_then(|meta| meta.modified()).unwrap_or(SystemTime::UNIX_EPOCH);
+        modified.elapsed().is_ok_and(|age| age >= SETTLE_TIME)
+    });
+    Ok(files)
+}
+
+/// The non-blank lines of a dropped file.
+fn note_text(path: &Path) -> Result<String, String> {
+    let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    Ok(text.lines().filter(|line| !line.trim().is_empty()).collect::<Vec<_>>().join("\n"))
+}
+
+struct Watcher<'a> {
+    client: &'a Client,
+    source: PathBuf,
+    target: WatchTarget,
+    /// Anchor block uuids by target page uuid.
+    anchors: HashMap<String, String>,
+}
+
+impl Watcher<'_> {
+    fn source_key(&self) -> String {
+        self.source.display().to_string()
+    }
+
+    fn page_uuid(&self) -> Result<String, String> {
+        match &self.target {
+            WatchTarget::Page(name) => self.client.get_or_create_page(name),
+            WatchTarget::Journal => self.client.journal_page_uuid(Local::now().date_naive()),
+        }
+    }
+
+    /// The block new content goes below, created on first use.
+    fn anchor(&mut self) -> Result<String, String> {
+        let page = self.page_uuid()?;
+        if let Some(anchor) = self.anchors.get(&page) {
+            return Ok(anchor.clone());
+        }
+
+        let key = self.source_key();
+        let existing = self.client.page_blocks(&page)?.into_iter().find(|block| {
+            block
+                .property(SOURCE_PROPERTY)
+                .is_some_and(|value| value.as_text() == key)
+        });
+        let anchor = match existing {
+            Some(block) => block.uuid,
+            None => {
+                let name = self.source.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_else(|| key.clone());
+                let content = format!("{}\n{}:: {}", name, SOURCE_PROPERTY, key);
+                debug!("Creating anchor block for {} on {}", key, page);
+                let block: Block = self.client.call_as("logseq.Editor.insertBlock", json!([page, content, {"isPageBlock": true}]))?;
+                block.uuid
+            }
+        };
+        self.anchors.insert(page, anchor.clone());
+        Ok(anchor)
+    }
+
+    fn poll_anchor(&mut self, anchor: &mut Option<String>) -> Result<String, String> {
+        if anchor.is_none() {
+            *anchor = Some(self.anchor()?);
+        }
+        Ok(anchor.clone().unwrap_or_default())
+    }
+
+    /// Add what is new in the source, updating `state` as content lands so
+    /// that a failure part way through is retried from there.
+    fn poll(&mut self, state: &mut WatchState) -> Result<usize, String> {
+        let key = self.source_key();
+        let mut added = 0;
+        // Looked up once per poll; the journal day may change between polls.
+        let mut anchor = None;
+
+        if self.source.is_dir() {
+            for (name, path) in dropped_files(&self.source)? {
+                if state.seen.get(&key).is_some_and(|seen| seen.contains(&name)) {
+                    continue;
+                }
+                let text = match note_text(&path) {
+                    Ok(text) => text,
+                    Err(err) => {
+                        // Skipped for good, so that it does not hold up the
+                        // files after it.
+                        eprintln!("Skipping {}: {}", path.display(), err);
+                        state.seen.entry(key.clone()).or_default().insert(name);
+                        continue;
+                    }
+                };
+                if !text.is_empty() {
+                    let anchor = self.poll_anchor(&mut anchor)?;
+                    self.insert(&anchor, &text)?;
+                }
+                state.seen.entry(key.clone()).or_default().insert(name);
+                added += 1;
+            }
+        } else {
+            let offset = state.offsets.get(&key).copied().unwrap_or_default();
+            let (lines, start) = new_lines(&self.source, offset)?;
+            if start != offset {
+                state.offsets.insert(key.clone(), start);
+            }
+            for (line, end) in lines {
+                if !line.trim().is_empty() {
+                    let anchor = self.poll_anchor(&mut anchor)?;
+                    self.insert(&anchor, &line)?;
+                    added += 1;
+                }
+                state.offsets.insert(key.clone(), end);
+            }
+        }
+        Ok(added)
+    }
+
+    fn insert(&mut self, anchor: &str, text: &str) -> Result<(), String> {
+        match self.client.process_lines_as_children(anchor, text) {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                // The anchor may have been deleted; look it up again next time.
+                self.anchors.retain(|_, uuid| uuid != anchor);
+                Err(err)
+            }
+        }
+    }
+}
+
+impl Client {
+    pub fn watch(&self, source: &Path, target: WatchTarget, interval: u64, from_start: bool, state_path: &Path) {
+        if let Err(err) = self.watch_inner(source, target, interval, from_start, state_path) {
+            eprintln!("Failed to watch {}: {}", source.display(), err);
+            std::process::exit(1);
+        }
+    }
+
+    fn watch_inner(
+        &self,
+        source: &Path,
+        target: WatchTarget,
+        interval: u64,
+        from_start: bool,
+        state_path: &Path,
+    ) -> Result<(), String> {
+        let source = fs::canonicalize(source).map_err(|err| err.to_string())?;
+        let key = source.display().to_string();
+        let mut state = WatchState::load(state_path)?;
+
+        // Without saved state, only what arrives from now on is added. That
+        // includes files still being written, which are otherwise only added
+        // once they have settled.
+        let known = state.offsets.contains_key(&key) || state.seen.contains_key(&key);
+        if !known && !from_start {
+            if source.is_dir() {
+                let names = folder_files(&source)?.into_iter().map(|(name, _)| name).collect();
+                state.seen.insert(key.clone(), names);
+            } else {
+                let length = fs::metadata(&source).map_err(|err| err.to_string())?.len();
+                state.offsets.insert(key.clone(), length);
+            }
+            state.save(state_path)?;
+        }
+
+        let mut watcher = Watcher { client: self, source, target, anchors: HashMap::new() };
+        eprintln!("Watching {} every {}s.", key, interval);
+        loop {
+            let result = watcher.poll(&mut state);
+            state.save(state_path)?;
+            match result {
+                Ok(0) => {}
+                Ok(added) => eprintln!("Added {} entries from {}.", added, key),
+                Err(err) => eprintln!("Failed to add from {}, retrying: {}", key, err),
+            }
+            thread::sleep(Duration::from_secs(interval));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn lines(file: &Path, offset: u64) -> (Vec<(String, u64)>, u64) {
+        new_lines(file, offset).unwrap()
+    }
+
+    #[test]
+    fn reads_complete_lines_with_their_end_offsets() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"one\r\ntwo\n\nthree").unwrap();
+
+        let (found, start) = lines(file.path(), 0);
+        assert_eq!(start, 0);
+        assert_eq!(found, vec![("one".to_string(), 5), ("two".to_string(), 9), (String::new(), 10)]);
+
+        // The unfinished last line is picked up once its newline arrives.
+        file.write_all(b"\nfour\n").unwrap();
+        let (found, start) = lines(file.path(), 10);
+        assert_eq!(start, 10);
+        assert_eq!(found, vec![("three".to_string(), 16), ("four".to_string(), 21)]);
+        assert_eq!(lines(file.path(), 21), (Vec::new(), 21));
+    }
+
+    #[test]
+    fn rereads_a_file_that_got_shorter() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"rotated\n").unwrap();
+        assert_eq!(lines(file.path(), 100), (vec![("rotated".to_string(), 8)], 0));
+    }
+
+    #[test]
+    fn lists_note_files_but_drops_only_settled_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["b.md", "a.txt", "c.markdown", "image.png", ".hidden.md"] {
+            fs::write(dir.path().join(name), "text").unwrap();
+        }
+        fs::create_dir(dir.path().join("folder.md")).unwrap();
+
+        let names: Vec<String> = folder_files(dir.path()).unwrap().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["a.txt", "b.md", "c.markdown"]);
+        // Everything was just written, so nothing has settled yet.
+        assert!(dropped_files(dir.path()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn reads_the_non_blank_lines_of_a_note() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("note.md");
+        fs::write(&path, "first\n\n  \nsecond\n").unwrap();
+        assert_eq!(note_text(&path).unwrap(), "first\nsecond");
+        fs::write(&path, b"caf\xe9\n").unwrap();
+        assert!(note_text(&path).is_err());
+    }
+
+    #[test]
+    fn skips_unreadable_dropped_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let settled = SystemTime::now() - Duration::from_secs(60);
+        for (name, bytes) in [("a.md", &b"\xff\xfe"[..]), ("b.md", b"\n\n")] {
+            let path = dir.path().join(name);
+            fs::write(&path, bytes).unwrap();
+            File::options().write(true).open(&path).unwrap().set_modified(settled).unwrap();
+        }
+
+        let client = Client::direct();
+        let mut watcher =
+            Watcher { client: &client, source: dir.path().to_path_buf(), target: WatchTarget::Journal, anchors: HashMap::new() };
+        let mut state = WatchState::default();
+        // Neither file needs Logseq: one cannot be read, the other is blank.
+        assert_eq!(watcher.poll(&mut state).unwrap(), 1);
+        let seen: Vec<&String> = state.seen[&watcher.source_key()].iter().collect();
+        assert_eq!(seen, ["a.md", "b.md"]);
+        assert_eq!(watcher.poll(&mut state).unwrap(), 0);
+    }
+}
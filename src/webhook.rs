@@ -0,0 +1,356 @@
+//! `rlu webhook`: turn JSON POSTs from CI, issue trackers or forms into
+//! journal notes. Rules in a TOML file pick the requests by path and fill
+//! the note from the payload:
+//!
+//! ```toml
+//! [[rules]]
+//! name = "ci"
+//! path = "/ci"
+//! token = "shared secret"        # sent as X-Rlu-Token or ?token=
+//! when = { "build.status" = "failed" }
+//! content = "CI <% build.branch %> failed: <% build.message %>"
+//! page = "Builds"                # optional, today's journal otherwise
+//!
+//! [rules.properties]
+//! url = "<% build.url %>"
+//! ```
+//!
+//! A rule without a `token` has to say `public = true` to accept POSTs from
+//! anyone. Placeholders are the dotted paths of payload fields, with array
+//! elements by index (`commits.0.message`); the ones the payload has no
+//! value for are listed in the response as `missing`. Payload text is put on
+//! one line so that it cannot add blocks or properties to the note.
+
+use chrono::Local;
+use log::debug;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::gateway::{decode, read_body_text, tokens_match};
+use crate::template::substitute;
+use crate::Client;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Rule {
+    name: String,
+    path: String,
+    token: Option<String>,
+    /// Accept requests without a token; only allowed when `token` is unset.
+    #[serde(default)]
+    public: bool,
+    /// Payload fields that must have these values for the rule to apply.
+    #[serde(default)]
+    when: BTreeMap<String, String>,
+    content: String,
+    page: Option<String>,
+    #[serde(default)]
+    properties: BTreeMap<String, String>,
+}
+
+impl Rule {
+    /// Whether every `when` field has the expected value.
+    fn matches(&self, fields: &HashMap<String, String>) -> bool {
+        self.when
+            .iter()
+            .all(|(field, expected)| fields.get(field).is_some_and(|value| value == expected))
+    }
+}
+
+#[derive(Deserialize)]
+struct Rules {
+    #[serde(default)]
+    rules: Vec<Rule>,
+}
+
+fn load_rules(path: &Path) -> Result<Vec<Rule>, String> {
+    let text = fs::read_to_string(path).map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+    let rules: Rules = toml::from_str(&text).map_err(|err| format!("Invalid {}: {}", path.display(), err))?;
+    if rules.rules.is_empty() {
+        return Err(format!("{} defines no [[rules]]", path.display()));
+    }
+    for rule in &rules.rules {
+        match (&rule.token, rule.public) {
+            (None, false) => {
+                return Err(format!(
+                    "Rule '{}' in {} has no token; set one, or public = true to accept any POST",
+                    rule.name,
+                    path.display()
+                ))
+            }
+            (Some(_), true) => {
+                return Err(format!("Rule '{}' in {} has a token but is public", rule.name, path.display()))
+            }
+            _ => {}
+        }
+    }
+    Ok(rules.rules)
+}
+
+/// Payload text on one line: a newline would start another block, or a
+/// `key:: value` property, of the note it is substituted into.
+fn single_line(text: &str) -> String {
+    text.split(['\n', '\r']).filter(|line| !line.trim().is_empty()).collect::<Vec<_>>().join(" ")
+}
+
+/// Payload fields by dotted path. Strings are put on one line, other values
+/// are taken as JSON.
+fn flatten_payload(value: &Value, prefix: &str, fields: &mut HashMap<String, String>) {
+    let key = |name: &str| if prefix.is_empty() { name.to_string() } else { format!("{}.{}", prefix, name) };
+    match value {
+        Value::Object(map) => {
+            for (name, value) in map {
+                flatten_payload(value, &key(name), fields);
+            }
+        }
+        Value::Array(items) => {
+            for (index, value) in items.iter().enumerate() {
+                flatten_payload(value, &key(&index.to_string()), fields);
+            }
+        }
+        Value::String(text) => {
+            fields.insert(prefix.to_string(), single_line(text));
+        }
+        Value::Null => {
+            fields.insert(prefix.to_string(), String::new());
+        }
+        other => {
+            fields.insert(prefix.to_string(), other.to_string());
+        }
+    }
+}
+
+/// An HTTP status and the body sent with it.
+type Reply = (u16, Value);
+
+fn error(status: u16, message: impl Into<String>) -> Reply {
+    (status, json!({"error": message.into()}))
+}
+
+fn request_token(request: &Request) -> Option<String> {
+    if let Some(header) = request.headers().iter().find(|header| header.field.equiv("X-Rlu-Token")) {
+        return Some(header.value.as_str().trim().to_string());
+    }
+    let (_, query) = request.url().split_once('?')?;
+    query.split('&').find_map(|pair| pair.strip_prefix("token=")).map(decode)
+}
+
+struct Webhook {
+    client: Client,
+    rules: Vec<Rule>,
+}
+
+impl Webhook {
+    /// Add the rule's note, returning its uuid and the placeholders the
+    /// payload had no value for.
+    fn apply(&self, rule: &Rule, fields: &HashMap<String, String>) -> Result<(Option<String>, Vec<String>), String> {
+        let (content, mut missing) = substitute(&rule.content, fields, &HashMap::new());
+        let page = match &rule.page {
+            Some(page) => self.client.get_or_create_page(page)?,
+            None => self.client.journal_page_uuid(Local::now().date_naive())?,
+        };
+        let uuid = self.client.insert_note(&page, &content)?;
+
+        if let Some(uuid) = &uuid {
+            for (key, template) in &rule.properties {
+                let (value, unfilled) = substitute(template, fields, &HashMap::new());
+                missing.extend(unfilled);
+                let value = value.replace('\n', " ");
+                if value.is_empty() {
+                    continue;
+                }
+                if let Err(err) = self.client.call("logseq.Editor.upsertBlockProperty", json!([uuid, key, value])) {
+                    eprintln!("Added a '{}' note but could not set its {} property: {}", rule.name, key, err);
+                }
+            }
+        }
+        missing.sort();
+        missing.dedup();
+        Ok((uuid, missing))
+    }
+
+    fn reply(&self, request: &mut Request) -> Reply {
+        if *request.method() != Method::Post {
+            return error(405, "Only POST is accepted");
+        }
+        let url = request.url().to_string();
+        let path = url.split_once('?').map_or(url.as_str(), |(path, _)| path);
+        let candidates: Vec<&Rule> = self.rules.iter().filter(|rule| rule.path == path).collect();
+        if candidates.is_empty() {
+            return error(404, format!("No rule for {}", path));
+        }
+
+        let token = request_token(request);
+        let candidates: Vec<&Rule> = candidates
+            .into_iter()
+            .filter(|rule| match &rule.token {
+                Some(expected) => token.as_deref().is_some_and(|token| tokens_match(token, expected)),
+                None => rule.public,
+            })
+            .collect();
+        if candidates.is_empty() {
+            return error(401, "Missing or wrong token");
+        }
+
+        let body = match read_body_text(request) {
+            Ok(body) => body,
+            Err((status, message)) => return error(status, message),
+        };
+        let payload: Value = match serde_json::from_str(&body) {
+            Ok(payload) => payload,
+            Err(err) => return error(400, format!("Body is not JSON: {}", err)),
+        };
+        let mut fields = HashMap::new();
+        flatten_payload(&payload, "", &mut fields);
+
+        let matching = candidates.into_iter().find(|rule| rule.matches(&fields));
+        let Some(rule) = matching else {
+            debug!("No rule for {} matches the payload", path);
+            return (200, json!({"matched": false}));
+        };
+
+        match self.apply(rule, &fields) {
+            Ok((uuid, missing)) => {
+                eprintln!("Added a '{}' note.", rule.name);
+                if !missing.is_empty() {
+                    debug!("The payload has no value for {}", missing.join(", "));
+                }
+                (201, json!({"matched": true, "rule": rule.name, "uuid": uuid, "missing": missing}))
+            }
+            Err(err) => {
+                eprintln!("Failed to add a '{}' note: {}", rule.name, err);
+                error(502, err)
+            }
+        }
+    }
+}
+
+pub fn run(client: Client, listen: &str, rules: &Path) -> Result<(), String> {
+    let rules = load_rules(rules)?;
+    let server = Server::http(listen).map_err(|err| format!("Failed to listen on {}: {}", listen, err))?;
+    eprintln!("Listening on http://{} with {} rules.", listen, rules.len());
+
+    let webhook = Webhook { client, rules };
+    for mut request in server.incoming_requests() {
+        let (status, body) = webhook.reply(&mut request);
+        debug!("{} {} -> {}", request.method(), request.url(), status);
+
+        let content_type = Header::from_bytes("Content-Type", "application/json").expect("valid header");
+        let response = Response::from_string(body.to_string())
+            .with_status_code(status)
+            .with_header(content_type);
+        if let Err(err) = request.respond(response) {
+            debug!("Failed to respond: {}", err);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn rules(text: &str) -> Result<Vec<Rule>, String> {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+        load_rules(file.path())
+    }
+
+    fn rules_error(text: &str) -> String {
+        rules(text).err().expect("the rules are refused")
+    }
+
+    fn fields(payload: Value) -> HashMap<String, String> {
+        let mut fields = HashMap::new();
+        flatten_payload(&payload, "", &mut fields);
+        fields
+    }
+
+    #[test]
+    fn requires_a_token_or_public() {
+        let loaded = rules(
+            r#"
+            [[rules]]
+            name = "ci"
+            path = "/ci"
+            token = "secret"
+            content = "x"
+
+            [[rules]]
+            name = "form"
+            path = "/form"
+            public = true
+            content = "x"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(loaded.len(), 2);
+
+        let open = r#"
+            [[rules]]
+            name = "ci"
+            path = "/ci"
+            content = "x"
+            "#;
+        assert!(rules_error(open).contains("has no token"));
+        let both = r#"
+            [[rules]]
+            name = "ci"
+            path = "/ci"
+            token = "secret"
+            public = true
+            content = "x"
+            "#;
+        assert!(rules_error(both).contains("has a token but is public"));
+        assert!(rules_error("").contains("defines no [[rules]]"));
+    }
+
+    #[test]
+    fn flattens_nested_payloads() {
+        let fields = fields(json!({
+            "build": {"status": "failed", "number": 42, "ok": false, "tag": null},
+            "commits": [{"message": "first"}, {"message": "second", "files": ["a.rs", "b.rs"]}]
+        }));
+        assert_eq!(fields["build.status"], "failed");
+        assert_eq!(fields["build.number"], "42");
+        assert_eq!(fields["build.ok"], "false");
+        assert_eq!(fields["build.tag"], "");
+        assert_eq!(fields["commits.0.message"], "first");
+        assert_eq!(fields["commits.1.files.1"], "b.rs");
+        assert!(!fields.contains_key("commits"));
+    }
+
+    #[test]
+    fn puts_payload_text_on_one_line() {
+        let fields = fields(json!({"message": "fix\n- injected block\r\nstatus:: done\n\n"}));
+        assert_eq!(fields["message"], "fix - injected block status:: done");
+        let (content, _) = substitute("CI: <% message %>", &fields, &HashMap::new());
+        assert_eq!(content.lines().count(), 1);
+    }
+
+    #[test]
+    fn matches_conditions_on_any_value() {
+        let rule = |when: &[(&str, &str)]| Rule {
+            name: "ci".to_string(),
+            path: "/ci".to_string(),
+            token: None,
+            public: true,
+            when: when.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect(),
+            content: String::new(),
+            page: None,
+            properties: BTreeMap::new(),
+        };
+        let fields = fields(json!({"build": {"status": "failed", "number": 42, "ok": false}}));
+        assert!(rule(&[]).matches(&fields));
+        assert!(rule(&[("build.status", "failed"), ("build.number", "42")]).matches(&fields));
+        assert!(rule(&[("build.ok", "false")]).matches(&fields));
+        assert!(!rule(&[("build.ok", "0")]).matches(&fields));
+        assert!(!rule(&[("build.status", "Failed")]).matches(&fields));
+        assert!(!rule(&[("build.missing", "")]).matches(&fields));
+    }
+}
@@ -0,0 +1,362 @@
+//! `rlu log-git` and `rlu log-cmd`: a work log on the journal. Commits go
+//! below a `Commits` block on the day they were authored, one child block
+//! per repository; long-running shell commands go below a `Commands` block
+//! on the day they finished. Both carry an id property (`commit::`,
+//! `cmd-id::`) so that logging the same thing twice adds it once.
+
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
+use clap::ValueEnum;
+use log::debug;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::import::fingerprint;
+use crate::model::Block;
+use crate::{journal, Client};
+
+const COMMITS_TITLE: &str = "Commits";
+const COMMANDS_TITLE: &str = "Commands";
+const COMMIT_PROPERTY: &str = "commit";
+const REPO_PROPERTY: &str = "git-repo";
+const COMMAND_ID_PROPERTY: &str = "cmd-id";
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum HookShell {
+    Bash,
+    Zsh,
+}
+
+/// Shell code that runs `rlu log-cmd` after each command, to be `eval`ed
+/// from the shell's rc file.
+pub fn hook_script(shell: HookShell) -> &'static str {
+    match shell {
+        HookShell::Bash => {
+            r#"# rlu: log commands that take a while to the journal
+__rlu_preexec() {
+    case $BASH_COMMAND in
+        __rlu_status=*) __rlu_armed=; return 0 ;;
+    esac
+    [ -n "$__rlu_armed" ] || return 0
+    __rlu_armed=
+    local line
+    line=$(HISTTIMEFORMAT= builtin history 1)
+    if [[ $line =~ ^\ *[0-9]+\*?\ +(.*)$ ]]; then
+        __rlu_cmd=${BASH_REMATCH[1]}
+    else
+        __rlu_cmd=$BASH_COMMAND
+    fi
+    printf -v __rlu_started '%(%s)T' -1
+}
+__rlu_precmd() {
+    if [ -n "$__rlu_started" ]; then
+        (rlu log-cmd --command "$__rlu_cmd" --exit "$__rlu_status" --started "$__rlu_started" >/dev/null 2>&1 &)
+    fi
+    __rlu_started=
+    __rlu_armed=1
+}
+# Keep a DEBUG trap that is already set, e.g. by bash-preexec, running after
+# ours; evaluating this script twice does not chain it to itself.
+if [[ $(trap -p DEBUG) != *__rlu_preexec* ]]; then
+    eval "__rlu_trap=($(trap -p DEBUG))"
+    __rlu_prev_debug=${__rlu_trap[2]}
+    unset __rlu_trap
+    trap '__rlu_preexec; eval "$__rlu_prev_debug"' DEBUG
+fi
+if [[ $PROMPT_COMMAND != *__rlu_precmd* ]]; then
+    PROMPT_COMMAND="__rlu_status=\$?; ${PROMPT_COMMAND:+$PROMPT_COMMAND; }__rlu_precmd"
+fi
+"#
+        }
+        HookShell::Zsh => {
+            r#"# rlu: log commands that take a while to the journal
+zmodload zsh/datetime
+autoload -Uz add-zsh-hook
+__rlu_preexec() {
+    __rlu_cmd=$1
+    __rlu_started=$EPOCHSECONDS
+}
+__rlu_precmd() {
+    local exit_status=$?
+    if [[ -n $__rlu_started ]]; then
+        (rlu log-cmd --command "$__rlu_cmd" --exit $exit_status --started $__rlu_started >/dev/null 2>&1 &)
+    fi
+    __rlu_started=
+}
+add-zsh-hook preexec __rlu_preexec
+add-zsh-hook precmd __rlu_precmd
+"#
+        }
+    }
+}
+
+/// `2m 13s`, `1h 04m`.
+fn format_duration(seconds: i64) -> String {
+    let seconds = seconds.max(0);
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m {:02}s", seconds / 60, seconds % 60),
+        _ => format!("{}h {:02}m", seconds / 3600, seconds % 3600 / 60),
+    }
+}
+
+/// Seconds `started` (a Unix timestamp) lies in the past.
+pub fn elapsed_since(started: i64) -> i64 {
+    Local::now().timestamp() - started
+}
+
+/// `text` as inline code on one line, fenced with more backticks than it
+/// contains in a row.
+fn inline_code(text: &str) -> String {
+    let text = text.lines().map(str::trim).filter(|line| !line.is_empty()).collect::<Vec<_>>().join(" ");
+    let longest_run = text.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    if longest_run == 0 {
+        return format!("`{}`", text);
+    }
+    let fence = "`".repeat(longest_run + 1);
+    format!("{} {} {}", fence, text, fence)
+}
+
+struct GitCommit {
+    sha: String,
+    short: String,
+    time: DateTime<Local>,
+    subject: String,
+}
+
+fn git(repo: &Path, args: &[&str]) -> Result<String, String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(args)
+        .output()
+        .map_err(|err| format!("Failed to run git: {}", err))?;
+    if !output.status.success() {
+        return Err(format!(
+            "git {} failed in {}: {}",
+            args.first().unwrap_or(&""),
+            repo.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Commits of `repo` authored since the start of `since`, oldest first.
+fn git_commits(repo: &Path, since: NaiveDate, author: Option<&str>) -> Result<Vec<GitCommit>, String> {
+    let since = format!("--since={} 00:00", since);
+    let mut args = vec!["log", "--no-merges", since.as_str(), "--format=%H%x1f%h%x1f%at%x1f%s"];
+    let author = author.map(|author| format!("--author={}", author));
+    if let Some(author) = &author {
+        args.push(author);
+    }
+
+    let mut commits: Vec<GitCommit> = git(repo, &args)?
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\u{1f}');
+            let sha = fields.next()?.to_string();
+            let short = fields.next()?.to_string();
+            let time = Local.timestamp_opt(fields.next()?.parse().ok()?, 0).single()?;
+            let subject = fields.next().unwrap_or_default().to_string();
+            Some(GitCommit { sha, short, time, subject })
+        })
+        .collect();
+    commits.reverse();
+    Ok(commits)
+}
+
+/// Whether `block` has the property line `key:: value`.
+fn has_property_line(block: &Block, key: &str, value: &str) -> bool {
+    let line = format!("{}:: {}", key, value);
+    block.content.lines().any(|existing| existing.trim() == line)
+}
+
+/// A finished shell command, as reported by the shell hook.
+pub struct CommandRun {
+    pub command: String,
+    pub exit: i32,
+    pub started: i64,
+}
+
+impl Client {
+    /// The top-level block titled `title` on the journal page of `date`,
+    /// with its children, created if it is not there yet.
+    fn journal_section(&self, date: NaiveDate, title: &str) -> Result<Block, String> {
+        let page = self.journal_page_uuid(date)?;
+        let existing = self
+            .page_blocks(&page)?
+            .into_iter()
+            .find(|block| block.content.lines().next().is_some_and(|line| line.trim() == title));
+        match existing {
+            Some(block) => Ok(block),
+            None => {
+                debug!("Creating {} block on {}", title, date);
+                self.call_as("logseq.Editor.insertBlock", json!([page, title, {"isPageBlock": true}]))
+            }
+        }
+    }
+
+    fn insert_last_child(&self, parent: &str, content: &str) -> Result<Block, String> {
+        self.call_as("logseq.Editor.insertBlock", json!([parent, content, {"sibling": false}]))
+    }
+
+    pub fn log_git(&self, repos: &[PathBuf], since: Option<&str>, author: Option<&str>) {
+        if let Err(err) = self.log_git_inner(repos, since, author) {
+            eprintln!("Failed to log commits: {}", err);
+            std::process::exit(1);
+        }
+    }
+
+    fn log_git_inner(&self, repos: &[PathBuf], since: Option<&str>, author: Option<&str>) -> Result<(), String> {
+        let since = match since {
+            Some(since) => journal::parse_date(since)?,
+            None => Local::now().date_naive(),
+        };
+        let mut seen = self.property_values(COMMIT_PROPERTY)?;
+        let (mut added, mut skipped) = (0, 0);
+
+        for repo in repos {
+            let root = PathBuf::from(git(repo, &["rev-parse", "--show-toplevel"])?.trim());
+            let name = root.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+            let root = root.display().to_string();
+
+            let mut days: BTreeMap<NaiveDate, Vec<GitCommit>> = BTreeMap::new();
+            for commit in git_commits(repo, since, author)? {
+                if seen.contains(&commit.sha) {
+                    skipped += 1;
+                    continue;
+                }
+                days.entry(commit.time.date_naive()).or_default().push(commit);
+            }
+
+            for (date, commits) in days {
+                let section = self.journal_section(date, COMMITS_TITLE)?;
+                let repo_block = match section.loaded_children().find(|block| has_property_line(block, REPO_PROPERTY, &root)) {
+                    Some(block) => block.uuid.clone(),
+                    None => self.insert_last_child(&section.uuid, &format!("{}\n{}:: {}", name, REPO_PROPERTY, root))?.uuid,
+                };
+                for commit in commits {
+                    let content = format!(
+                        "{} {}\n{}:: {}",
+                        inline_code(&commit.short),
+                        commit.subject,
+                        COMMIT_PROPERTY,
+                        commit.sha
+                    );
+                    self.insert_last_child(&repo_block, &content)?;
+                    debug!("Logged {} on {}", commit.sha, date);
+                    seen.insert(commit.sha);
+                    added += 1;
+                }
+            }
+        }
+
+        eprintln!("Logged {} commits, skipped {} already logged.", added, skipped);
+        Ok(())
+    }
+
+    pub fn log_cmd(&self, run: &CommandRun) {
+        if let Err(err) = self.log_cmd_inner(run) {
+            eprintln!("Failed to log command: {}", err);
+            std::process::exit(1);
+        }
+    }
+
+    fn log_cmd_inner(&self, run: &CommandRun) -> Result<(), String> {
+        let id = fingerprint(&[&run.command, &run.started.to_string()]);
+        if self.property_values(COMMAND_ID_PROPERTY)?.contains(&id) {
+            debug!("Already logged: {}", id);
+            return Ok(());
+        }
+
+        let mut content = format!(
+            "{} exited {} after {}\n{}:: {}",
+            inline_code(&run.command),
+            run.exit,
+            format_duration(elapsed_since(run.started)),
+            COMMAND_ID_PROPERTY,
+            id
+        );
+        if let Ok(cwd) = std::env::current_dir() {
+            content.push_str(&format!("\ncwd:: {}", cwd.display()));
+        }
+
+        let section = self.journal_section(Local::now().date_naive(), COMMANDS_TITLE)?;
+        self.insert_last_child(&section.uuid, &content)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_durations() {
+        assert_eq!(format_duration(-5), "0s");
+        assert_eq!(format_duration(59), "59s");
+        assert_eq!(format_duration(60), "1m 00s");
+        assert_eq!(format_duration(133), "2m 13s");
+        assert_eq!(format_duration(3600), "1h 00m");
+        assert_eq!(format_duration(3840), "1h 04m");
+        assert_eq!(format_duration(90000), "25h 00m");
+    }
+
+    #[test]
+    fn quotes_commands_as_inline_code() {
+        assert_eq!(inline_code("cargo test"), "`cargo test`");
+        assert_eq!(inline_code("echo `date`"), "`` echo `date` ``");
+        assert_eq!(inline_code("printf '``'"), "``` printf '``' ```");
+    }
+
+    #[test]
+    fn puts_multi_line_commands_on_one_line() {
+        assert_eq!(inline_code("for f in *; do\n  echo $f\ndone\n"), "`for f in *; do echo $f done`");
+        assert_eq!(inline_code("a &&\r\n\r\n  b"), "`a && b`");
+    }
+
+    #[test]
+    fn hooks_report_to_log_cmd() {
+        for shell in [HookShell::Bash, HookShell::Zsh] {
+            let script = hook_script(shell);
+            assert!(script.contains("rlu log-cmd --command \"$__rlu_cmd\" --exit"));
+            assert!(script.contains(">/dev/null 2>&1 &)"));
+        }
+        let zsh = hook_script(HookShell::Zsh);
+        assert!(zsh.contains("add-zsh-hook preexec __rlu_preexec") && zsh.contains("add-zsh-hook precmd __rlu_precmd"));
+        assert!(!zsh.contains("trap"));
+    }
+
+    /// Run `script` with bash, if there is one, and return its output.
+    fn bash(script: &str) -> Option<String> {
+        let output = Command::new("bash").arg("--norc").arg("-c").arg(script).output().ok()?;
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        Some(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    #[test]
+    fn bash_hook_chains_an_existing_debug_trap() {
+        let hook = hook_script(HookShell::Bash);
+        let script = format!(
+            "trap 'echo \"previous $BASH_COMMAND\" >/dev/null' DEBUG\nPROMPT_COMMAND='history -a'\n{hook}\n{hook}\n\
+             printf '%s\\n' \"$__rlu_prev_debug\" \"$PROMPT_COMMAND\"\ntrap -p DEBUG\n"
+        );
+        let Some(output) = bash(&script) else {
+            return;
+        };
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[0], "echo \"previous $BASH_COMMAND\" >/dev/null");
+        assert_eq!(lines[1], "__rlu_status=$?; history -a; __rlu_precmd");
+        assert_eq!(lines[2], r#"trap -- '__rlu_preexec; eval "$__rlu_prev_debug"' DEBUG"#);
+    }
+
+    #[test]
+    fn bash_hook_sets_its_own_trap_when_there_is_none() {
+        let Some(output) = bash(&format!("{}\nprintf '[%s]\\n' \"$__rlu_prev_debug\"\n", hook_script(HookShell::Bash))) else {
+            return;
+        };
+        assert_eq!(output.lines().next(), Some("[]"));
+    }
+}